@@ -0,0 +1,229 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr};
+use std::str::FromStr;
+
+use crate::Error;
+
+/// IpNetwork `struct` represents an IP address or a CIDR subnet, such as
+/// `192.168.1.0/24` or `fd00::/8`.
+///
+/// IPv4-mapped IPv6 addresses are treated as their IPv4 counterparts, so
+/// `::ffff:10.0.0.0/104` is the same network as `10.0.0.0/8`. Otherwise,
+/// IPv6 networks only hold IPv6 clients: `::/0` matches every IPv6 address
+/// but no IPv4 one, and matching all clients takes both `0.0.0.0/0` and `::/0`.
+///
+/// # Example
+///
+/// ```rust
+/// use tftpd::IpNetwork;
+///
+/// let network: IpNetwork = "10.0.0.0/8".parse().unwrap();
+///
+/// assert!(network.contains(&"10.1.2.3".parse().unwrap()));
+/// assert!(network.contains(&"::ffff:10.1.2.3".parse().unwrap()));
+/// assert!(!network.contains(&"11.0.0.1".parse().unwrap()));
+///
+/// let all_v6: IpNetwork = "::/0".parse().unwrap();
+/// assert!(!all_v6.contains(&"10.1.2.3".parse().unwrap()));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// Creates a new [`IpNetwork`] from an address and a prefix length,
+    /// clearing the host bits of the address.
+    pub fn new(address: IpAddr, prefix: u8) -> Result<IpNetwork, Error> {
+        let (address, prefix) = match address {
+            IpAddr::V6(v6) if prefix >= 96 && v6.to_ipv4_mapped().is_some() => {
+                (v6.to_canonical(), prefix - 96)
+            }
+            address => (address, prefix),
+        };
+
+        let max_prefix = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > max_prefix {
+            return Err(Error::Config(format!("Invalid prefix length: {prefix}")));
+        }
+
+        Ok(IpNetwork {
+            address: mask(address, prefix),
+            prefix,
+        })
+    }
+
+    /// Returns the prefix length of the network.
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Returns `true` if the `address` belongs to the network.
+    pub fn contains(&self, address: &IpAddr) -> bool {
+        let address = address.to_canonical();
+        address.is_ipv4() == self.address.is_ipv4() && mask(address, self.prefix) == self.address
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (
+                address.parse::<IpAddr>().map_err(Error::config)?,
+                Some(prefix.parse::<u8>().map_err(Error::config)?),
+            ),
+            None => (s.parse::<IpAddr>().map_err(Error::config)?, None),
+        };
+        let prefix = prefix.unwrap_or(if address.is_ipv4() { 32 } else { 128 });
+
+        IpNetwork::new(address, prefix)
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+fn mask(address: IpAddr, prefix: u8) -> IpAddr {
+    match address {
+        IpAddr::V4(v4) => {
+            let bits = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            IpAddr::V4((u32::from(v4) & bits).into())
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(v6) & bits))
+        }
+    }
+}
+
+/// AccessList `struct` holds the networks allowed and denied to make a
+/// type of request. Denied networks take precedence over allowed ones, and
+/// when no network is allowed explicitly, every client that is not denied
+/// is allowed. Denying every client takes both `0.0.0.0/0` and `::/0`, see
+/// [`IpNetwork`].
+///
+/// # Example
+///
+/// ```rust
+/// use tftpd::AccessList;
+///
+/// let mut acl = AccessList::default();
+/// acl.allow("192.168.0.0/16".parse().unwrap());
+/// acl.deny("192.168.1.13".parse().unwrap());
+///
+/// assert!(acl.is_allowed(&"192.168.1.12".parse().unwrap()));
+/// assert!(!acl.is_allowed(&"192.168.1.13".parse().unwrap()));
+/// assert!(!acl.is_allowed(&"10.0.0.1".parse().unwrap()));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccessList {
+    allowed: Vec<IpNetwork>,
+    denied: Vec<IpNetwork>,
+}
+
+impl AccessList {
+    /// Adds a network to the allowed list.
+    pub fn allow(&mut self, network: IpNetwork) {
+        self.allowed.push(network);
+    }
+
+    /// Adds a network to the denied list.
+    pub fn deny(&mut self, network: IpNetwork) {
+        self.denied.push(network);
+    }
+
+    /// Returns `true` if the client `address` is allowed.
+    pub fn is_allowed(&self, address: &IpAddr) -> bool {
+        if self.denied.iter().any(|network| network.contains(address)) {
+            return false;
+        }
+
+        self.allowed.is_empty() || self.allowed.iter().any(|network| network.contains(address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_networks() {
+        assert_eq!(
+            "10.1.2.3/8".parse::<IpNetwork>().unwrap().to_string(),
+            "10.0.0.0/8"
+        );
+        assert_eq!(
+            "10.1.2.3".parse::<IpNetwork>().unwrap().to_string(),
+            "10.1.2.3/32"
+        );
+        assert_eq!(
+            "10.1.2.3/0".parse::<IpNetwork>().unwrap().to_string(),
+            "0.0.0.0/0"
+        );
+        assert_eq!(
+            "2001:db8::1/32".parse::<IpNetwork>().unwrap().to_string(),
+            "2001:db8::/32"
+        );
+        assert_eq!(
+            "::ffff:10.1.2.3/104"
+                .parse::<IpNetwork>()
+                .unwrap()
+                .to_string(),
+            "10.0.0.0/8"
+        );
+
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+        assert!("::/129".parse::<IpNetwork>().is_err());
+        assert!("10.0.0/8".parse::<IpNetwork>().is_err());
+        assert!("10.0.0.0/a".parse::<IpNetwork>().is_err());
+    }
+
+    #[test]
+    fn matches_addresses() {
+        let v4: IpNetwork = "192.168.1.0/24".parse().unwrap();
+        assert!(v4.contains(&ip("192.168.1.200")));
+        assert!(v4.contains(&ip("::ffff:192.168.1.200")));
+        assert!(!v4.contains(&ip("192.168.2.1")));
+        assert!(!v4.contains(&ip("::1")));
+
+        let v6: IpNetwork = "fd00::/8".parse().unwrap();
+        assert!(v6.contains(&ip("fd12:3456::1")));
+        assert!(!v6.contains(&ip("fe80::1")));
+        assert!(!v6.contains(&ip("10.0.0.1")));
+
+        let all: IpNetwork = "::/0".parse().unwrap();
+        assert!(all.contains(&ip("::1")));
+        assert!(!all.contains(&ip("127.0.0.1")));
+    }
+
+    #[test]
+    fn checks_access_lists() {
+        let acl = AccessList::default();
+        assert!(acl.is_allowed(&ip("10.0.0.1")));
+
+        let mut acl = AccessList::default();
+        acl.deny("10.0.0.0/8".parse().unwrap());
+        assert!(!acl.is_allowed(&ip("10.0.0.1")));
+        assert!(!acl.is_allowed(&ip("::ffff:10.0.0.1")));
+        assert!(acl.is_allowed(&ip("127.0.0.1")));
+
+        acl.allow("10.0.0.0/24".parse().unwrap());
+        acl.allow("::1".parse().unwrap());
+        assert!(!acl.is_allowed(&ip("10.0.0.1")));
+        assert!(acl.is_allowed(&ip("::1")));
+        assert!(!acl.is_allowed(&ip("127.0.0.1")));
+    }
+}
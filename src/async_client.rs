@@ -0,0 +1,136 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::net::UdpSocket;
+
+use crate::client::{remove_options, response_error, Response};
+use crate::observer::TransferTracker;
+use crate::{
+    async_worker, log::*, AsyncSocket, Client, ClientConfig, Direction, Error, Mode, Packet,
+    TransferInfo, TransferReport,
+};
+
+/// AsyncClient `struct` is the asynchronous counterpart of [`Client`],
+/// running on a [tokio](https://tokio.rs) runtime.
+///
+/// Instead of the abort flag of [`Client`], dropping the future returned by
+/// [`AsyncClient::run()`] cancels the transfer. A partially downloaded file
+/// is then removed, unless cleaning on error is disabled.
+///
+/// # Example
+///
+/// ```rust
+/// use tftpd::{AsyncClient, ClientConfig};
+///
+/// let args = ["test.file", "-u"].iter().map(|s| s.to_string());
+/// let config = ClientConfig::new(args).unwrap();
+/// let client = AsyncClient::new(&config).unwrap();
+/// ```
+pub struct AsyncClient {
+    client: Client,
+}
+
+impl AsyncClient {
+    /// Creates the asynchronous TFTP Client with the supplied [`ClientConfig`].
+    pub fn new(config: &ClientConfig) -> Result<AsyncClient, Error> {
+        Ok(AsyncClient {
+            client: Client::new(config)?,
+        })
+    }
+
+    /// Run the Client depending on the [`Mode`] the client is in, and returns
+    /// the [`TransferReport`] of the transfer once the request is accepted.
+    ///
+    /// When the host name of the server resolves to several addresses, they
+    /// are tried in turn until one of them answers.
+    pub async fn run(&mut self) -> Result<TransferReport, Error> {
+        let mut request = self.client.request()?;
+        let mut result = Err(Error::Config("No address to connect to".to_string()));
+        for address in self.client.remote_addresses.clone() {
+            result = self.connect(&address, &mut request).await;
+            match &result {
+                Err(err @ (Error::Timeout(_) | Error::Io(_))) => {
+                    log_warn!("Could not reach {address}: {err}")
+                }
+                _ => break,
+            }
+        }
+        let (socket, response, from) = result?;
+
+        let client = &mut self.client;
+        socket.connect(from).await?;
+        let first_packet = match response {
+            Response::Accepted(Some(ack)) => {
+                AsyncSocket::send(&socket, &ack).await?;
+                None
+            }
+            Response::Data(packet) => Some(packet),
+            _ => None,
+        };
+
+        let info = |direction| TransferInfo {
+            file_path: client.file_local.clone(),
+            peer: from,
+            direction,
+        };
+        let report = match client.mode {
+            Mode::Upload => {
+                let file = client.source()?;
+                let tracker =
+                    TransferTracker::new(info(Direction::Send), client.opt_common.clone(), None);
+                async_worker::send(
+                    &socket,
+                    file,
+                    tracker,
+                    client.transfer_mode,
+                    client.opt_common.clone(),
+                    client.opt_local.clone(),
+                    false,
+                )
+                .await
+            }
+            Mode::Download => {
+                let file = client.sink()?;
+                let tracker =
+                    TransferTracker::new(info(Direction::Receive), client.opt_common.clone(), None);
+                async_worker::receive(
+                    &socket,
+                    file,
+                    first_packet,
+                    tracker,
+                    client.transfer_mode,
+                    client.opt_common.clone(),
+                    client.opt_local.clone(),
+                )
+                .await
+            }
+        };
+
+        Ok(report)
+    }
+
+    /// Sends `request` to the server at `address`, and returns the socket
+    /// with the first response of the server and its address.
+    async fn connect(
+        &mut self,
+        address: &SocketAddr,
+        request: &mut Packet,
+    ) -> Result<(UdpSocket, Response, SocketAddr), Error> {
+        let socket = if address.is_ipv4() {
+            UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?
+        } else {
+            UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0)).await?
+        };
+
+        loop {
+            AsyncSocket::send_to(&socket, request, address).await?;
+
+            let (packet, from) = AsyncSocket::recv_from(&socket, self.client.timeout_req)
+                .await
+                .map_err(|err| response_error(err, address))?;
+            match self.client.handle_response(packet)? {
+                Response::Retry(err) => remove_options(request, err)?,
+                response => return Ok((socket, response, from)),
+            }
+        }
+    }
+}
@@ -0,0 +1,575 @@
+use std::cmp::max;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{Error as IoError, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex as StdMutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::{self, JoinSet};
+
+use crate::async_socket::with_timeout;
+use crate::log::*;
+use crate::options::{OptionsPrivate, DEFAULT_BLOCK_SIZE};
+use crate::server::{Accepted, Decision};
+use crate::{
+    async_worker, AsyncSocket, Config, Direction, Error, ErrorCode, FileProvider, GeneratedFile,
+    Observer, Packet, Server,
+};
+
+// To notice the finished transfers while no packet is received
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// AsyncServer `struct` is the asynchronous counterpart of [`Server`],
+/// running on a [tokio](https://tokio.rs) runtime. Requests are checked the
+/// same way as by [`Server`], and each accepted transfer runs in its own task.
+/// The requests are checked, and the files read and written, on the blocking
+/// threads of the runtime.
+///
+/// Instead of the abort flag of [`Server`], dropping the future returned by
+/// [`AsyncServer::listen()`] stops the server and cancels all its transfers.
+/// Requests over the transfer limits are refused rather than queued.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use tftpd::{AsyncServer, Config};
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() {
+///     let args = ["/", "-p", "0"].iter().map(|s| s.to_string());
+///     let config = Config::new(args).unwrap();
+///     let mut server = AsyncServer::new(&config).unwrap();
+///
+///     // Serve for a while, then stop by dropping the future
+///     let _ = tokio::time::timeout(Duration::from_millis(100), server.listen()).await;
+/// }
+/// ```
+pub struct AsyncServer {
+    // Shared with the blocking threads checking the requests
+    server: Arc<StdMutex<Server>>,
+    socket: Arc<UdpSocket>,
+    single_port: bool,
+    opt_local: OptionsPrivate,
+    max_transfers: Option<usize>,
+    max_client_transfers: Option<usize>,
+}
+
+/// Transfers started by [`AsyncServer::listen()`], which are cancelled when
+/// its future is dropped.
+struct Transfers {
+    tasks: JoinSet<bool>,
+    addresses: HashMap<task::Id, SocketAddr>,
+    clients: HashMap<SocketAddr, mpsc::UnboundedSender<Packet>>,
+    largest_block_size: u16,
+}
+
+impl AsyncServer {
+    /// Creates the asynchronous TFTP Server with the supplied [`Config`].
+    /// It must be called from within a tokio runtime.
+    pub fn new(config: &Config) -> Result<AsyncServer, Error> {
+        let server = Server::new(config)?;
+        let socket = server.socket().try_clone()?;
+        socket.set_nonblocking(true)?;
+
+        Ok(AsyncServer {
+            server: Arc::new(StdMutex::new(server)),
+            socket: Arc::new(UdpSocket::from_std(socket)?),
+            single_port: config.single_port,
+            opt_local: config.opt_local.clone(),
+            max_transfers: config.max_transfers,
+            max_client_transfers: config.max_client_transfers,
+        })
+    }
+
+    /// Sets the [`FileProvider`] used for sending and receiving files, see
+    /// [`Server::set_provider()`].
+    pub fn set_provider<P: FileProvider + 'static>(&mut self, provider: P) {
+        self.server().set_provider(provider);
+    }
+
+    /// Registers a hook generating the content of read requests, see
+    /// [`Server::add_read_hook()`].
+    pub fn add_read_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&str, &SocketAddr) -> Option<GeneratedFile> + Send + Sync + 'static,
+    {
+        self.server().add_read_hook(hook);
+    }
+
+    /// Sets the [`Observer`] notified of the requests and transfers of the
+    /// server, see [`Server::set_observer()`].
+    pub fn set_observer(&mut self, observer: Arc<dyn Observer>) {
+        self.server().set_observer(observer);
+    }
+
+    fn server(&self) -> MutexGuard<'_, Server> {
+        lock(&self.server)
+    }
+
+    /// Returns the local [`SocketAddr`] the server listens on.
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Starts listening for connections. Note that the returned future does
+    /// not finish until it is dropped, which cancels the running transfers.
+    pub async fn listen(&mut self) {
+        let socket = self.socket.clone();
+        let mut transfers = Transfers {
+            tasks: JoinSet::new(),
+            addresses: HashMap::new(),
+            clients: HashMap::new(),
+            largest_block_size: DEFAULT_BLOCK_SIZE,
+        };
+
+        loop {
+            let size = if self.single_port {
+                transfers.largest_block_size
+            } else {
+                DEFAULT_BLOCK_SIZE
+            };
+
+            tokio::select! {
+                Some(finished) = transfers.tasks.join_next_with_id() => {
+                    let id = match finished {
+                        Ok((id, _)) => id,
+                        Err(err) => err.id(),
+                    };
+                    transfers.remove(&id);
+                }
+                received = async { socket.recv_from_with_size(size as usize, IDLE_TIMEOUT).await.ok() } => {
+                    if let Some((packet, from)) = received {
+                        self.handle_packet(&mut transfers, packet, from).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_packet(&mut self, transfers: &mut Transfers, packet: Packet, from: SocketAddr) {
+        match packet {
+            Packet::Rrq { .. } | Packet::Wrq { .. } => {
+                if self.has_capacity(transfers, &from.ip()) {
+                    self.handle_request(transfers, packet, from).await;
+                } else {
+                    log_warn!(
+                        "Refused request from {from}: {} transfers running",
+                        transfers.addresses.len()
+                    );
+                    send_error(
+                        &self.socket,
+                        ErrorCode::NotDefined,
+                        "too many transfers, try again later",
+                        &from,
+                    )
+                    .await;
+                }
+            }
+            _ => {
+                let routed = transfers
+                    .clients
+                    .get(&from)
+                    .is_some_and(|sender| sender.send(packet).is_ok());
+                if !routed {
+                    send_error(
+                        &self.socket,
+                        ErrorCode::IllegalOperation,
+                        "invalid request",
+                        &from,
+                    )
+                    .await;
+                    log_warn!("Received invalid request");
+                }
+            }
+        }
+    }
+
+    async fn handle_request(
+        &mut self,
+        transfers: &mut Transfers,
+        packet: Packet,
+        from: SocketAddr,
+    ) {
+        // The file of the request is opened along with its checks
+        let server = self.server.clone();
+        let decision = async_worker::blocking(move || lock(&server).check_request(packet, &from));
+        match decision.await {
+            Decision::Refuse(packet) => {
+                if AsyncSocket::send_to(&*self.socket, &packet, &from)
+                    .await
+                    .is_err()
+                {
+                    log_err!("Could not send error packet");
+                };
+            }
+            Decision::Ignore => {}
+            Decision::Send(accepted, file) => {
+                let Some(socket) = self.transfer_socket(transfers, &accepted, from).await else {
+                    return;
+                };
+                let opt_local = self.opt_local.clone();
+                let tracker = self.server().tracker(&accepted, from, Direction::Send);
+                transfers.spawn(from, async move {
+                    let check_response = accepted.reply.is_some();
+                    if !reply(&socket, &accepted).await {
+                        return false;
+                    }
+                    async_worker::send(
+                        &socket,
+                        file,
+                        tracker,
+                        accepted.mode,
+                        accepted.options,
+                        opt_local,
+                        check_response,
+                    )
+                    .await
+                    .is_success()
+                });
+            }
+            Decision::Receive(accepted, file) => {
+                let Some(socket) = self.transfer_socket(transfers, &accepted, from).await else {
+                    return;
+                };
+                let opt_local = accepted.opt_local(&self.opt_local);
+                let tracker = self.server().tracker(&accepted, from, Direction::Receive);
+                transfers.spawn(from, async move {
+                    if !reply(&socket, &accepted).await {
+                        return false;
+                    }
+                    async_worker::receive(
+                        &socket,
+                        file,
+                        None,
+                        tracker,
+                        accepted.mode,
+                        accepted.options,
+                        opt_local,
+                    )
+                    .await
+                    .is_success()
+                });
+            }
+        }
+    }
+
+    /// Creates the socket of a transfer, binding a new port unless the
+    /// server runs in single port mode.
+    async fn transfer_socket(
+        &mut self,
+        transfers: &mut Transfers,
+        accepted: &Accepted,
+        to: SocketAddr,
+    ) -> Option<TransferSocket> {
+        if self.single_port {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            transfers.clients.insert(to, sender);
+            transfers.largest_block_size =
+                max(transfers.largest_block_size, accepted.options.block_size);
+
+            return Some(TransferSocket {
+                socket: self.socket.clone(),
+                remote: to,
+                receiver: Some(Mutex::new(receiver)),
+            });
+        }
+
+        let address = SocketAddr::new(self.socket.local_addr().ok()?.ip(), 0);
+        match UdpSocket::bind(address).await {
+            Ok(socket) => Some(TransferSocket {
+                socket: Arc::new(socket),
+                remote: to,
+                receiver: None,
+            }),
+            Err(err) => {
+                log_err!("Could not create the socket of {to}: {err}");
+                None
+            }
+        }
+    }
+
+    fn has_capacity(&self, transfers: &Transfers, ip: &IpAddr) -> bool {
+        let total = transfers.addresses.len();
+        let client = transfers
+            .addresses
+            .values()
+            .filter(|address| address.ip() == *ip)
+            .count();
+
+        self.max_transfers.map_or(true, |max| total < max)
+            && self.max_client_transfers.map_or(true, |max| client < max)
+    }
+}
+
+impl Transfers {
+    fn spawn<F>(&mut self, address: SocketAddr, transfer: F)
+    where
+        F: Future<Output = bool> + Send + 'static,
+    {
+        let handle = self.tasks.spawn(transfer);
+        self.addresses.insert(handle.id(), address);
+    }
+
+    fn remove(&mut self, id: &task::Id) {
+        let Some(address) = self.addresses.remove(id) else {
+            return;
+        };
+        // A newer transfer of the client may use the same channel
+        if !self.addresses.values().any(|running| *running == address) {
+            self.clients.remove(&address);
+        }
+    }
+}
+
+/// Locks the server, which stays usable after a panic of a request check.
+fn lock(server: &StdMutex<Server>) -> MutexGuard<'_, Server> {
+    server.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+async fn send_error(socket: &UdpSocket, code: ErrorCode, msg: &str, to: &SocketAddr) {
+    let packet = Packet::Error {
+        code,
+        msg: msg.to_string(),
+    };
+    if AsyncSocket::send_to(socket, &packet, to).await.is_err() {
+        log_err!("Could not send error packet");
+    };
+}
+
+/// Answers an accepted request with its OACK or ACK, if any.
+async fn reply(socket: &TransferSocket, accepted: &Accepted) -> bool {
+    let Some(packet) = &accepted.reply else {
+        return true;
+    };
+
+    match socket.send(packet).await {
+        Ok(()) => true,
+        Err(err) => {
+            log_err!("Could not answer the request of {}: {err}", socket.remote);
+            false
+        }
+    }
+}
+
+/// Socket of a transfer: either a socket of its own, or the server socket
+/// with the packets of the client routed through a channel in single port
+/// mode.
+struct TransferSocket {
+    socket: Arc<UdpSocket>,
+    remote: SocketAddr,
+    receiver: Option<Mutex<mpsc::UnboundedReceiver<Packet>>>,
+}
+
+impl AsyncSocket for TransferSocket {
+    async fn send(&self, packet: &Packet) -> Result<(), Error> {
+        self.send_to(packet, &self.remote).await
+    }
+
+    async fn send_to(&self, packet: &Packet, to: &SocketAddr) -> Result<(), Error> {
+        AsyncSocket::send_to(&*self.socket, packet, to).await
+    }
+
+    async fn recv_with_size(&self, size: usize, timeout: Duration) -> Result<Packet, Error> {
+        if let Some(receiver) = &self.receiver {
+            let packet = with_timeout(timeout, async {
+                receiver
+                    .lock()
+                    .await
+                    .recv()
+                    .await
+                    .ok_or(IoError::from(ErrorKind::BrokenPipe))
+            })
+            .await
+            .map_err(Error::from_recv)?;
+            return Ok(packet);
+        }
+
+        // Packets from other addresses are ignored
+        let deadline = Instant::now() + timeout;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let (packet, from) = self.socket.recv_from_with_size(size, timeout).await?;
+            if from == self.remote {
+                return Ok(packet);
+            }
+        }
+    }
+
+    async fn recv_from_with_size(
+        &self,
+        size: usize,
+        timeout: Duration,
+    ) -> Result<(Packet, SocketAddr), Error> {
+        Ok((self.recv_with_size(size, timeout).await?, self.remote))
+    }
+
+    fn remote_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.remote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    fn start_server(directory: &str, flags: &[&str]) -> (SocketAddr, task::JoinHandle<()>) {
+        let _ = fs::remove_dir_all(directory);
+        fs::create_dir_all(directory).unwrap();
+        fs::write(Path::new(directory).join("hello.txt"), "Hello, world!").unwrap();
+
+        let args = ["tftpd", "-p", "0", "-d", directory];
+        let args = args.iter().chain(flags).map(|s| s.to_string());
+        let mut server = AsyncServer::new(&Config::new(args).unwrap()).unwrap();
+        let address = server.local_addr().unwrap();
+
+        (address, tokio::spawn(async move { server.listen().await }))
+    }
+
+    async fn exchange(socket: &UdpSocket, packet: Packet, to: &SocketAddr) -> (Packet, SocketAddr) {
+        AsyncSocket::send_to(socket, &packet, to).await.unwrap();
+        AsyncSocket::recv_from_with_size(socket, 512, Duration::from_secs(5))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn transfers_files() {
+        for (dir_name, flags) in [
+            ("target/test/async_transfers_files", &[][..]),
+            ("target/test/async_transfers_files_single_port", &["-s"][..]),
+        ] {
+            let (address, server) = start_server(dir_name, flags);
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+            let request = Packet::Rrq {
+                filename: "hello.txt".to_string(),
+                mode: "octet".to_string(),
+                options: vec![],
+            };
+            let (packet, from) = exchange(&socket, request, &address).await;
+            assert_eq!(
+                packet,
+                Packet::Data {
+                    block_num: 1,
+                    data: b"Hello, world!".to_vec()
+                }
+            );
+            assert_eq!(from.port() == address.port(), flags.contains(&"-s"));
+            AsyncSocket::send_to(&socket, &Packet::Ack(1), &from)
+                .await
+                .unwrap();
+
+            let request = Packet::Wrq {
+                filename: "upload.txt".to_string(),
+                mode: "octet".to_string(),
+                options: vec![],
+            };
+            let (packet, from) = exchange(&socket, request, &address).await;
+            assert_eq!(packet, Packet::Ack(0));
+            let data = Packet::Data {
+                block_num: 1,
+                data: b"Uploaded".to_vec(),
+            };
+            assert_eq!(exchange(&socket, data, &from).await.0, Packet::Ack(1));
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let uploaded = Path::new(dir_name).join("upload.txt");
+            assert_eq!(fs::read(uploaded).unwrap(), b"Uploaded");
+            server.abort();
+            let _ = server.await;
+            fs::remove_dir_all(dir_name).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn cancels_transfers_when_dropped() {
+        const DIR_NAME: &str = "target/test/async_cancels_transfers_when_dropped";
+        let (address, server) = start_server(DIR_NAME, &[]);
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let request = Packet::Wrq {
+            filename: "partial.txt".to_string(),
+            mode: "octet".to_string(),
+            options: vec![],
+        };
+        let (packet, from) = exchange(&socket, request, &address).await;
+        assert_eq!(packet, Packet::Ack(0));
+        let data = Packet::Data {
+            block_num: 1,
+            data: vec![0; 512],
+        };
+        assert_eq!(exchange(&socket, data, &from).await.0, Packet::Ack(1));
+
+        // The data is written to a temporary file until the transfer ends
+        let directory = Path::new(DIR_NAME);
+        let partial_files = || {
+            fs::read_dir(directory)
+                .unwrap()
+                .filter(|entry| {
+                    let name = entry.as_ref().unwrap().file_name();
+                    name.to_string_lossy().contains("partial.txt")
+                })
+                .count()
+        };
+        assert!(!directory.join("partial.txt").exists());
+        assert_eq!(partial_files(), 1);
+        server.abort();
+        let _ = server.await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(partial_files(), 0);
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+
+    #[cfg(feature = "client")]
+    #[tokio::test]
+    async fn serves_async_client() {
+        use crate::{AsyncClient, ClientConfig};
+
+        const DIR_NAME: &str = "target/test/async_serves_async_client";
+        let (address, server) = start_server(&format!("{DIR_NAME}/server"), &["--overwrite"]);
+        let directory = Path::new(DIR_NAME).join("server");
+        let download = Path::new(DIR_NAME).join("client");
+        fs::create_dir_all(&download).unwrap();
+        let port = address.port().to_string();
+
+        let args = ["hello.txt", "-d", "-p", &port, "-b", "4", "-w", "2"];
+        let mut args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        args.extend(["-rd".to_string(), download.display().to_string()]);
+        let config = ClientConfig::new(args.into_iter()).unwrap();
+        assert!(AsyncClient::new(&config)
+            .unwrap()
+            .run()
+            .await
+            .unwrap()
+            .is_success());
+        assert_eq!(
+            fs::read(download.join("hello.txt")).unwrap(),
+            b"Hello, world!"
+        );
+
+        fs::write(download.join("hello.txt"), "Uploaded").unwrap();
+        let local = download.join("hello.txt").display().to_string();
+        let args = [local.as_str(), "-u", "-p", &port];
+        let config = ClientConfig::new(args.iter().map(|s| s.to_string())).unwrap();
+        assert!(AsyncClient::new(&config)
+            .unwrap()
+            .run()
+            .await
+            .unwrap()
+            .is_success());
+        // The file is committed after the last block is acknowledged
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(fs::read(directory.join("hello.txt")).unwrap(), b"Uploaded");
+
+        server.abort();
+        let _ = server.await;
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+}
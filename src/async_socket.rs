@@ -0,0 +1,128 @@
+use std::future::Future;
+use std::io::{Error as IoError, ErrorKind};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::time;
+
+use crate::{Error, Packet};
+
+const MAX_REQUEST_PACKET_SIZE: usize = 512;
+
+/// AsyncSocket `trait` is the asynchronous counterpart of [`crate::Socket`],
+/// used by [`crate::AsyncServer`] and [`crate::AsyncClient`]. Instead of a
+/// read timeout set on the socket, each receive takes its own timeout, and
+/// fails with [`Error::Timeout`] once it expires.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use tftpd::{AsyncSocket, Packet};
+/// use tokio::net::UdpSocket;
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() {
+///     let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+///     let address = socket.local_addr().unwrap();
+///
+///     AsyncSocket::send_to(&socket, &Packet::Ack(1), &address).await.unwrap();
+///     let (packet, _) = AsyncSocket::recv_from(&socket, Duration::from_secs(1))
+///         .await
+///         .unwrap();
+///     assert_eq!(packet, Packet::Ack(1));
+/// }
+/// ```
+pub trait AsyncSocket: Send + Sync + 'static {
+    /// Sends a [`Packet`] to the socket's connected remote.
+    fn send(&self, packet: &Packet) -> impl Future<Output = Result<(), Error>> + Send;
+    /// Sends a [`Packet`] to the specified remote.
+    fn send_to(
+        &self,
+        packet: &Packet,
+        to: &SocketAddr,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+    /// Receives a [`Packet`] from the socket's connected remote within
+    /// `timeout`. Like [`crate::Socket::recv()`], it is intended for only
+    /// accepting incoming requests.
+    fn recv(&self, timeout: Duration) -> impl Future<Output = Result<Packet, Error>> + Send {
+        self.recv_with_size(MAX_REQUEST_PACKET_SIZE, timeout)
+    }
+    /// Receives a data packet of up to `size` bytes of data from the socket's
+    /// connected remote within `timeout`.
+    fn recv_with_size(
+        &self,
+        size: usize,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<Packet, Error>> + Send;
+    /// Receives a [`Packet`] from any remote within `timeout`, and returns
+    /// the [`SocketAddr`] of the remote. Like [`crate::Socket::recv_from()`],
+    /// it is intended for only accepting incoming requests.
+    fn recv_from(
+        &self,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<(Packet, SocketAddr), Error>> + Send {
+        self.recv_from_with_size(MAX_REQUEST_PACKET_SIZE, timeout)
+    }
+    /// Receives a data packet of up to `size` bytes of data from any remote
+    /// within `timeout`, and returns the [`SocketAddr`] of the remote.
+    fn recv_from_with_size(
+        &self,
+        size: usize,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<(Packet, SocketAddr), Error>> + Send;
+    /// Returns the remote [`SocketAddr`] if it exists.
+    fn remote_addr(&self) -> Result<SocketAddr, Error>;
+}
+
+impl AsyncSocket for UdpSocket {
+    async fn send(&self, packet: &Packet) -> Result<(), Error> {
+        UdpSocket::send(self, &packet.serialize()?).await?;
+
+        Ok(())
+    }
+
+    async fn send_to(&self, packet: &Packet, to: &SocketAddr) -> Result<(), Error> {
+        UdpSocket::send_to(self, &packet.serialize()?, to).await?;
+
+        Ok(())
+    }
+
+    async fn recv_with_size(&self, size: usize, timeout: Duration) -> Result<Packet, Error> {
+        let mut buf = vec![0; size + 4];
+        let amt = with_timeout(timeout, UdpSocket::recv(self, &mut buf))
+            .await
+            .map_err(Error::from_recv)?;
+        let packet = Packet::deserialize(&buf[..amt])?;
+
+        Ok(packet)
+    }
+
+    async fn recv_from_with_size(
+        &self,
+        size: usize,
+        timeout: Duration,
+    ) -> Result<(Packet, SocketAddr), Error> {
+        let mut buf = vec![0; size + 4];
+        let (amt, addr) = with_timeout(timeout, UdpSocket::recv_from(self, &mut buf))
+            .await
+            .map_err(Error::from_recv)?;
+        let packet = Packet::deserialize(&buf[..amt])?;
+
+        Ok((packet, addr))
+    }
+
+    fn remote_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.peer_addr()?)
+    }
+}
+
+pub(crate) async fn with_timeout<T>(
+    timeout: Duration,
+    future: impl Future<Output = std::io::Result<T>>,
+) -> std::io::Result<T> {
+    time::timeout(timeout, future)
+        .await
+        .unwrap_or_else(|_| Err(IoError::from(ErrorKind::TimedOut)))
+}
@@ -0,0 +1,273 @@
+use std::io::{ErrorKind, Read};
+use std::iter;
+use std::panic;
+use std::time::{Duration, Instant};
+
+use tokio::runtime::Handle;
+use tokio::task;
+use tokio::time;
+
+use crate::log::*;
+use crate::observer::TransferTracker;
+use crate::options::{OptionsPrivate, OptionsProtocol};
+use crate::worker::{finish_receive, finish_send};
+use crate::{
+    AsyncSocket, Error, FileSink, Packet, ReceiveTransfer, SendTransfer, Transfer, TransferMode,
+    TransferReport,
+};
+
+#[cfg(feature = "debug_drop")]
+use crate::drop::drop_check;
+
+const DEFAULT_DUPLICATE_DELAY: Duration = Duration::from_millis(1);
+
+/// Sends the content of `file` to the connected remote of `socket`, and
+/// returns the [`TransferReport`] of the transfer. This is the asynchronous
+/// counterpart of [`crate::Worker::send()`].
+pub(crate) async fn send<S: AsyncSocket>(
+    socket: &S,
+    file: Box<dyn Read + Send>,
+    mut tracker: TransferTracker,
+    mode: TransferMode,
+    opt_common: OptionsProtocol,
+    opt_local: OptionsPrivate,
+    check_response: bool,
+) -> TransferReport {
+    let timeout = opt_common.timeout;
+    let options = opt_local.clone();
+    // The first blocks of the file are read right away
+    let created = blocking(move || {
+        SendTransfer::new(
+            file,
+            mode,
+            opt_common,
+            options,
+            check_response,
+            Instant::now(),
+        )
+    })
+    .await;
+    let transfer = match created {
+        Ok(transfer) => transfer,
+        Err(err) => return finish_send(Err(err), &mut tracker),
+    };
+    let mut running = Running::new(transfer, tracker, false);
+    let result = run(&mut running, socket, &opt_local, timeout).await;
+
+    let Some((_, mut tracker)) = running.inner.take() else {
+        return TransferReport::default();
+    };
+    finish_send(result, &mut tracker)
+}
+
+/// Receives a file from the connected remote of `socket` into `file`, and
+/// returns the [`TransferReport`] of the transfer. This is the asynchronous
+/// counterpart of [`crate::Worker::receive()`].
+///
+/// A `first_packet` already received from the remote is handled before
+/// waiting for the next ones. If the future is dropped before the transfer
+/// finishes, the file is discarded like after a failed transfer.
+pub(crate) async fn receive<S: AsyncSocket>(
+    socket: &S,
+    file: Box<dyn FileSink>,
+    first_packet: Option<Packet>,
+    tracker: TransferTracker,
+    mode: TransferMode,
+    opt_common: OptionsProtocol,
+    opt_local: OptionsPrivate,
+) -> TransferReport {
+    let timeout = opt_common.timeout;
+    let clean_on_error = opt_local.clean_on_error;
+    let transfer = ReceiveTransfer::new(file, mode, opt_common, opt_local.clone(), Instant::now());
+    let mut running = Running::new(transfer, tracker, clean_on_error);
+    if let Some(packet) = first_packet {
+        running
+            .step(move |transfer| transfer.handle_packet(packet, Instant::now()))
+            .await;
+    }
+    let result = run(&mut running, socket, &opt_local, timeout).await;
+
+    let Some((transfer, mut tracker)) = running.inner.take() else {
+        return TransferReport::default();
+    };
+    blocking(move || finish_receive(result, transfer.into_inner(), &mut tracker, clean_on_error))
+        .await
+}
+
+/// Runs `f` on a blocking thread of the runtime, as file operations may
+/// block the other tasks.
+pub(crate) async fn blocking<R, F>(f: F) -> R
+where
+    R: Send + 'static,
+    F: FnOnce() -> R + Send + 'static,
+{
+    task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|err| panic::resume_unwind(err.into_panic()))
+}
+
+/// Transfer of a task with its tracker, which is moved to a blocking thread
+/// of the runtime whenever its file is read or written. If the future of the
+/// task is dropped, the transfer is cancelled.
+struct Running<X: Cancel> {
+    inner: Option<(X, TransferTracker)>,
+    clean_on_error: bool,
+}
+
+impl<X: Cancel> Running<X> {
+    fn new(transfer: X, tracker: TransferTracker, clean_on_error: bool) -> Running<X> {
+        Running {
+            inner: Some((transfer, tracker)),
+            clean_on_error,
+        }
+    }
+
+    /// Runs `step` on the transfer from a blocking thread of the runtime.
+    async fn step<R, F>(&mut self, step: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut X) -> R + Send + 'static,
+    {
+        let mut running = Running {
+            inner: self.inner.take(),
+            clean_on_error: self.clean_on_error,
+        };
+        // Dropped on the blocking thread if the task is cancelled meanwhile
+        let (mut running, result) = blocking(move || {
+            let (transfer, _) = running.transfer();
+            let result = step(transfer);
+            (running, result)
+        })
+        .await;
+        self.inner = running.inner.take();
+
+        result
+    }
+
+    fn transfer(&mut self) -> (&mut X, &mut TransferTracker) {
+        let (transfer, tracker) = self.inner.as_mut().expect("transfer is running");
+        (transfer, tracker)
+    }
+}
+
+impl<X: Cancel> Drop for Running<X> {
+    fn drop(&mut self) {
+        let Some((transfer, mut tracker)) = self.inner.take() else {
+            return;
+        };
+        let clean_on_error = self.clean_on_error;
+        let cancel = move || transfer.cancel(&mut tracker, clean_on_error);
+        match Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(cancel)),
+            Err(_) => cancel(),
+        }
+    }
+}
+
+/// Transfers which are cleaned up when their task is cancelled.
+trait Cancel: Transfer + Send + 'static {
+    fn cancel(self, tracker: &mut TransferTracker, clean_on_error: bool);
+}
+
+impl Cancel for SendTransfer<Box<dyn Read + Send>> {
+    fn cancel(self, _tracker: &mut TransferTracker, _clean_on_error: bool) {}
+}
+
+impl Cancel for ReceiveTransfer<Box<dyn FileSink>> {
+    /// Discards the file like after a failed transfer.
+    fn cancel(self, tracker: &mut TransferTracker, clean_on_error: bool) {
+        let file_path = tracker.info().file_path.clone();
+        log_warn!("Cancelled receiving {}", file_path.display());
+        if clean_on_error && self.into_inner().discard().is_err() {
+            log_err!("Error while cleaning {}", file_path.display());
+        }
+        tracker.failed(&Error::Aborted("Transfer cancelled".to_string()));
+    }
+}
+
+async fn run<X: Cancel, S: AsyncSocket>(
+    running: &mut Running<X>,
+    socket: &S,
+    opt_local: &OptionsPrivate,
+    timeout: Duration,
+) -> Result<u64, Error> {
+    let mut packets = running.step(transmit).await;
+    loop {
+        for packet in &packets {
+            send_packet(socket, packet, opt_local.repeat_count).await?;
+        }
+        let (transfer, tracker) = running.transfer();
+        tracker.update(transfer);
+        if let Some(result) = transfer.poll_result() {
+            return result;
+        }
+
+        let now = Instant::now();
+        let deadline = transfer.poll_timeout().unwrap_or(now + timeout);
+        if deadline <= now {
+            packets = running
+                .step(move |transfer| {
+                    transfer.handle_timeout(now);
+                    transmit(transfer)
+                })
+                .await;
+            continue;
+        }
+
+        let received = socket
+            .recv_with_size(transfer.max_packet_size(), deadline - now)
+            .await;
+        packets = match received {
+            Ok(packet) => {
+                running
+                    .step(move |transfer| {
+                        transfer.handle_packet(packet, Instant::now());
+                        transmit(transfer)
+                    })
+                    .await
+            }
+            Err(e) => {
+                match e {
+                    Error::Timeout(_) => (),
+                    Error::Io(io_e) if io_e.kind() == ErrorKind::ConnectionReset => {
+                        log_info!("  Cnx reset during reception {io_e:?}")
+                    }
+                    Error::Io(io_e) => log_warn!("  IO error during reception {io_e:?}"),
+                    _ => (),
+                }
+                running
+                    .step(|transfer| {
+                        transfer.handle_timeout(Instant::now());
+                        transmit(transfer)
+                    })
+                    .await
+            }
+        };
+    }
+}
+
+/// Returns the packets the transfer has to send, reading the next blocks of
+/// the file if needed.
+fn transmit<X: Transfer>(transfer: &mut X) -> Vec<Packet> {
+    iter::from_fn(|| transfer.poll_transmit(Instant::now())).collect()
+}
+
+async fn send_packet<S: AsyncSocket>(
+    socket: &S,
+    packet: &Packet,
+    repeat_count: u8,
+) -> Result<(), Error> {
+    #[cfg(feature = "debug_drop")]
+    if drop_check(packet) {
+        return Ok(());
+    };
+
+    for i in 0..repeat_count {
+        if i > 0 {
+            time::sleep(DEFAULT_DUPLICATE_DELAY).await;
+        }
+        socket.send(packet).await?;
+    }
+
+    Ok(())
+}
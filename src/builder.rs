@@ -0,0 +1,427 @@
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::options::Rollover;
+use crate::{
+    AccessList, ClientRoots, Config, Error, FileProvider, GeneratedFile, Observer, RemapRules,
+    Server, SymlinkPolicy, UploadLimits,
+};
+
+/// ServerBuilder `struct` is used for creating a [`Server`] from code,
+/// with a typed setter for each setting of [`Config`], instead of
+/// command line arguments.
+///
+/// The server can listen on a new socket bound to [`ServerBuilder::ip_address()`]
+/// and [`ServerBuilder::port()`], or on an already bound socket given to
+/// [`ServerBuilder::socket()`]. [`ServerBuilder::start()`] runs the server in
+/// its own thread, and returns a [`ServerHandle`] to stop it.
+///
+/// # Example
+///
+/// ```rust
+/// use std::env;
+/// use tftpd::ServerBuilder;
+///
+/// // Run a read-only server on a port chosen by the OS.
+/// let handle = ServerBuilder::new()
+///     .port(0)
+///     .directory(env::temp_dir())
+///     .read_only(true)
+///     .start()
+///     .unwrap();
+///
+/// println!("Listening on {}", handle.local_addr());
+/// handle.shutdown();
+/// handle.join().unwrap();
+/// ```
+pub struct ServerBuilder {
+    config: Config,
+    socket: Option<UdpSocket>,
+    provider: Option<Box<ServerSetup>>,
+    read_hooks: Vec<Box<ServerSetup>>,
+    observer: Option<Arc<dyn Observer>>,
+}
+
+type ServerSetup = dyn FnOnce(&mut Server) + Send;
+
+/// ServerHandle `struct` is used for controlling a [`Server`] started by
+/// [`ServerBuilder::start()`] in its own thread.
+pub struct ServerHandle {
+    local_addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerBuilder {
+    /// Creates a [`ServerBuilder`] with the default settings of [`Config`].
+    pub fn new() -> ServerBuilder {
+        ServerBuilder {
+            config: Config::default(),
+            socket: None,
+            provider: None,
+            read_hooks: Vec::new(),
+            observer: None,
+        }
+    }
+
+    /// Sets the local IP address of the server.
+    pub fn ip_address(mut self, ip_address: IpAddr) -> Self {
+        self.config.ip_address = ip_address;
+        self
+    }
+
+    /// Sets the local port of the server, where `0` lets the OS choose one.
+    pub fn port(mut self, port: u16) -> Self {
+        self.config.port = port;
+        self
+    }
+
+    /// Sets the directory used for both sending and receiving files.
+    pub fn directory<P: Into<PathBuf>>(mut self, directory: P) -> Self {
+        self.config.directory = directory.into();
+        self
+    }
+
+    /// Sets the directory receiving the uploaded files.
+    pub fn receive_directory<P: Into<PathBuf>>(mut self, directory: P) -> Self {
+        self.config.receive_directory = directory.into();
+        self
+    }
+
+    /// Sets the directory of the downloaded files.
+    pub fn send_directory<P: Into<PathBuf>>(mut self, directory: P) -> Self {
+        self.config.send_directory = directory.into();
+        self
+    }
+
+    /// Sets the send and receive directories of the clients of some networks.
+    pub fn client_roots(mut self, client_roots: ClientRoots) -> Self {
+        self.config.client_roots = client_roots;
+        self
+    }
+
+    /// Uses the server port for all transfers.
+    pub fn single_port(mut self, single_port: bool) -> Self {
+        self.config.single_port = single_port;
+        self
+    }
+
+    /// Drives all transfers from the listening thread.
+    pub fn event_loop(mut self, event_loop: bool) -> Self {
+        self.config.event_loop = event_loop;
+        self
+    }
+
+    /// Refuses all write requests.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.config.read_only = read_only;
+        self
+    }
+
+    /// Overwrites existing files on write requests.
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.config.overwrite = overwrite;
+        self
+    }
+
+    /// Creates the missing directories of the received files.
+    pub fn create_dirs(mut self, create_dirs: bool) -> Self {
+        self.config.create_dirs = create_dirs;
+        self
+    }
+
+    /// Sets the permissions of the directories created for the received
+    /// files, on Unix.
+    pub fn dir_mode(mut self, dir_mode: u32) -> Self {
+        self.config.dir_mode = dir_mode;
+        self
+    }
+
+    /// Sets how the symbolic links found in the requested paths are handled.
+    pub fn symlink_policy(mut self, symlink_policy: SymlinkPolicy) -> Self {
+        self.config.symlink_policy = symlink_policy;
+        self
+    }
+
+    /// Sets the rules remapping the requested filenames.
+    pub fn remap_rules(mut self, remap_rules: RemapRules) -> Self {
+        self.config.remap_rules = remap_rules;
+        self
+    }
+
+    /// Sets the clients allowed to make read requests.
+    pub fn read_acl(mut self, read_acl: AccessList) -> Self {
+        self.config.read_acl = read_acl;
+        self
+    }
+
+    /// Sets the clients allowed to make write requests.
+    pub fn write_acl(mut self, write_acl: AccessList) -> Self {
+        self.config.write_acl = write_acl;
+        self
+    }
+
+    /// Sets the maximum sizes of the received files.
+    pub fn upload_limits(mut self, upload_limits: UploadLimits) -> Self {
+        self.config.upload_limits = upload_limits;
+        self
+    }
+
+    /// Sets the maximum amount of concurrent transfers, [`None`] for no limit.
+    pub fn max_transfers(mut self, max_transfers: Option<usize>) -> Self {
+        self.config.max_transfers = max_transfers;
+        self
+    }
+
+    /// Sets the maximum amount of concurrent transfers of a client IP
+    /// address, [`None`] for no limit.
+    pub fn max_client_transfers(mut self, max_client_transfers: Option<usize>) -> Self {
+        self.config.max_client_transfers = max_client_transfers;
+        self
+    }
+
+    /// Sets the time requests over the limits wait for a free transfer.
+    pub fn queue_timeout(mut self, queue_timeout: Duration) -> Self {
+        self.config.queue_timeout = queue_timeout;
+        self
+    }
+
+    /// Sets the time given to the running transfers to finish when shutting
+    /// down.
+    pub fn drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.config.drain_timeout = drain_timeout;
+        self
+    }
+
+    /// Sets how many times each packet is sent.
+    pub fn repeat_count(mut self, repeat_count: u8) -> Self {
+        self.config.opt_local.repeat_count = repeat_count;
+        self
+    }
+
+    /// Discards the partially received data of a custom [`crate::FileSink`]
+    /// after a failed transfer. Files on disk are always cleaned.
+    pub fn clean_on_error(mut self, clean_on_error: bool) -> Self {
+        self.config.opt_local.clean_on_error = clean_on_error;
+        self
+    }
+
+    /// Sets the maximum amount of retries of a timed out packet.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.config.opt_local.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the block counter roll-over policy.
+    pub fn rollover(mut self, rollover: Rollover) -> Self {
+        self.config.opt_local.rollover = rollover;
+        self
+    }
+
+    /// Listens on an already bound `socket`, instead of binding the IP
+    /// address and port of the builder.
+    pub fn socket(mut self, socket: UdpSocket) -> Self {
+        self.socket = Some(socket);
+        self
+    }
+
+    /// Sets the [`FileProvider`] used for sending and receiving files, see
+    /// [`Server::set_provider()`].
+    pub fn provider<P: FileProvider + 'static>(mut self, provider: P) -> Self {
+        self.provider = Some(Box::new(move |server| server.set_provider(provider)));
+        self
+    }
+
+    /// Registers a hook generating the content of read requests, see
+    /// [`Server::add_read_hook()`].
+    pub fn read_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str, &SocketAddr) -> Option<GeneratedFile> + Send + Sync + 'static,
+    {
+        self.read_hooks
+            .push(Box::new(move |server| server.add_read_hook(hook)));
+        self
+    }
+
+    /// Sets the [`Observer`] notified of the requests and transfers, see
+    /// [`Server::set_observer()`].
+    pub fn observer(mut self, observer: Arc<dyn Observer>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Returns the [`Config`] holding the settings of the builder.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Creates the [`Server`], checking that its directories exist.
+    pub fn build(mut self) -> Result<Server, Error> {
+        if self.config.receive_directory.as_os_str().is_empty() {
+            self.config
+                .receive_directory
+                .clone_from(&self.config.directory);
+        }
+        if self.config.send_directory.as_os_str().is_empty() {
+            self.config
+                .send_directory
+                .clone_from(&self.config.directory);
+        }
+
+        // A custom provider does not use the directories
+        if self.provider.is_none() {
+            for directory in [&self.config.receive_directory, &self.config.send_directory] {
+                if !directory.exists() {
+                    return Err(Error::Config(format!(
+                        "{} does not exist",
+                        directory.display()
+                    )));
+                }
+            }
+        }
+
+        let socket = match self.socket.take() {
+            Some(socket) => socket,
+            None => UdpSocket::bind(SocketAddr::from((self.config.ip_address, self.config.port)))?,
+        };
+        let local_addr = socket.local_addr()?;
+        self.config.ip_address = local_addr.ip();
+        self.config.port = local_addr.port();
+
+        let mut server = Server::with_socket(socket, &self.config);
+        if let Some(set_provider) = self.provider {
+            set_provider(&mut server);
+        }
+        for add_read_hook in self.read_hooks {
+            add_read_hook(&mut server);
+        }
+        if let Some(observer) = self.observer {
+            server.set_observer(observer);
+        }
+
+        Ok(server)
+    }
+
+    /// Creates the [`Server`] and runs it in a new thread.
+    pub fn start(self) -> Result<ServerHandle, Error> {
+        let mut server = self.build()?;
+        let local_addr = server.local_addr()?;
+        let shutdown = server.get_shutdown_flag();
+        let thread = thread::spawn(move || server.listen());
+
+        Ok(ServerHandle {
+            local_addr,
+            shutdown,
+            thread,
+        })
+    }
+}
+
+impl ServerHandle {
+    /// Returns the local [`SocketAddr`] the server listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops the server once the running transfers finish or the drain
+    /// timeout expires. New requests are refused.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+
+        // Wake up the server instead of waiting for its socket timeout
+        let mut address = self.local_addr;
+        if address.ip().is_unspecified() {
+            address.set_ip(match address {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+        let unspecified = match address {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        if let Ok(socket) = UdpSocket::bind((unspecified, 0)) {
+            let _ = socket.send_to(&[], address);
+        }
+    }
+
+    /// Waits for the server thread to finish, see [`ServerHandle::shutdown()`].
+    pub fn join(self) -> Result<(), Error> {
+        self.thread
+            .join()
+            .map_err(|_| Error::io(ErrorKind::Other, "Server thread panicked"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::{Packet, Socket};
+
+    #[test]
+    fn starts_and_stops_server() {
+        const DIR_NAME: &str = "target/test/starts_and_stops_server";
+        let _ = fs::create_dir_all(DIR_NAME);
+        fs::write(format!("{DIR_NAME}/hello.txt"), "Hello, world!").unwrap();
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let handle = ServerBuilder::new()
+            .socket(socket)
+            .directory(DIR_NAME)
+            .read_only(true)
+            .start()
+            .unwrap();
+        assert_eq!(handle.local_addr(), address);
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let request = Packet::Rrq {
+            filename: "hello.txt".to_string(),
+            mode: "octet".to_string(),
+            options: vec![],
+        };
+        Socket::send_to(&client, &request, &address).unwrap();
+        let (packet, from) = client.recv_from_with_size(512).unwrap();
+        assert_eq!(
+            packet,
+            Packet::Data {
+                block_num: 1,
+                data: b"Hello, world!".to_vec()
+            }
+        );
+        Socket::send_to(&client, &Packet::Ack(1), &from).unwrap();
+
+        handle.shutdown();
+        handle.join().unwrap();
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+
+    #[test]
+    fn checks_directories() {
+        let builder = ServerBuilder::new()
+            .port(0)
+            .directory("/nonexistent/tftpd")
+            .rollover(Rollover::None)
+            .max_transfers(Some(4));
+        assert_eq!(builder.config().opt_local.rollover, Rollover::None);
+        assert_eq!(builder.config().max_transfers, Some(4));
+        assert!(builder.build().is_err());
+    }
+}
@@ -1,15 +1,27 @@
 use std::cmp::PartialEq;
-use std::error::Error;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, Cursor, ErrorKind, Read, Write};
 use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
 use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, SyncSender};
 use std::sync::{atomic::AtomicBool, Arc};
+use std::thread;
 use std::time::Duration;
 
+use crate::client_config::is_stdio;
+use crate::netascii::netascii_len;
 #[cfg(debug_assertions)]
 use crate::options::OptionFmt;
 use crate::options::{OptionsPrivate, OptionsProtocol};
-use crate::{log::*, ClientConfig, Packet, Socket, Worker};
+use crate::{
+    log::*, ClientConfig, Error, ErrorCode, FileSink, FileWriter, OptionType, Packet, Socket,
+    TransferMode, TransferReport, Worker, WriteSink,
+};
+
+/// Amount of chunks buffered between a stream of the caller and its transfer
+const STREAM_CHANNEL_SIZE: usize = 16;
+/// Size of the chunks read from a stream of the caller
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
 
 /// Client `struct` is used for client sided TFTP requests.
 ///
@@ -27,17 +39,28 @@ use crate::{log::*, ClientConfig, Packet, Socket, Worker};
 /// let server = Client::new(&config).unwrap();
 /// ```
 pub struct Client {
-    remote_address: SocketAddr,
-    timeout_req: Duration,
-    mode: Mode,
-    file_local: PathBuf,
+    pub(crate) remote_addresses: Vec<SocketAddr>,
+    pub(crate) timeout_req: Duration,
+    pub(crate) mode: Mode,
+    pub(crate) transfer_mode: TransferMode,
+    pub(crate) file_local: PathBuf,
     file_remote: String,
     receive_directory: PathBuf,
-    opt_local: OptionsPrivate,
-    opt_common: OptionsProtocol,
+    pub(crate) opt_local: OptionsPrivate,
+    pub(crate) opt_common: OptionsProtocol,
     abort: Arc<AtomicBool>,
 }
 
+/// First response of the server to a request, see [`Client::handle_response()`].
+pub(crate) enum Response {
+    /// The transfer starts once the acknowledgement, if any, is sent
+    Accepted(Option<Packet>),
+    /// The server ignored the options and sent the first data block
+    Data(Packet),
+    /// The server refused the options, the request is sent again without them
+    Retry(Error),
+}
+
 /// Enum used to set the client either in Download Mode or Upload Mode
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Mode {
@@ -48,12 +71,14 @@ pub enum Mode {
 }
 
 impl Client {
-    /// Creates the TFTP Client with the supplied [`ClientConfig`].
-    pub fn new(config: &ClientConfig) -> Result<Client, Box<dyn Error>> {
+    /// Creates the TFTP Client with the supplied [`ClientConfig`], resolving
+    /// the host name of the server if any.
+    pub fn new(config: &ClientConfig) -> Result<Client, Error> {
         Ok(Client {
-            remote_address: SocketAddr::from((config.remote_ip_address, config.port)),
+            remote_addresses: config.remote_addresses()?,
             timeout_req: config.timeout_req,
             mode: config.mode,
+            transfer_mode: config.transfer_mode,
             file_local: config.file_path.clone(),
             file_remote: config.file_remote.clone(),
             receive_directory: config.receive_directory.clone(),
@@ -63,9 +88,198 @@ impl Client {
         })
     }
 
-    /// Run the Client depending on the [`Mode`] the client is in
-    pub fn run(&mut self) -> Result<bool, Box<dyn Error>> {
-        let socket = if self.remote_address.is_ipv4() {
+    /// Run the Client depending on the [`Mode`] the client is in, and returns
+    /// the [`TransferReport`] of the transfer once the request is accepted.
+    ///
+    /// When the host name of the server resolves to several addresses, they
+    /// are tried in turn until one of them answers.
+    pub fn run(&mut self) -> Result<TransferReport, Error> {
+        let request = self.request()?;
+        let worker = self.start(request)?;
+        let join_handle = match self.mode {
+            Mode::Upload => worker.send(self.source()?, false)?,
+            Mode::Download => worker.receive(self.sink()?)?,
+        };
+
+        join_transfer(join_handle)
+    }
+
+    /// Downloads the `remote` file of the server into `writer`, without any
+    /// local file, and returns the [`TransferReport`] of the transfer once the
+    /// request is accepted.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::net::Ipv4Addr;
+    /// use tftpd::{Client, ClientConfig};
+    ///
+    /// let config = ClientConfig {
+    ///     remote_ip_address: Ipv4Addr::new(192, 168, 1, 10).into(),
+    ///     ..Default::default()
+    /// };
+    /// let mut client = Client::new(&config).unwrap();
+    ///
+    /// let mut data = Vec::new();
+    /// let report = client.get("firmware.bin", &mut data).unwrap();
+    /// assert_eq!(report.bytes, data.len() as u64);
+    /// ```
+    pub fn get<W: Write>(&mut self, remote: &str, mut writer: W) -> Result<TransferReport, Error> {
+        let worker = self.start_stream(Mode::Download, remote, None)?;
+
+        let (sender, receiver) = mpsc::sync_channel(STREAM_CHANNEL_SIZE);
+        let join_handle = worker.receive(Box::new(ChannelSink { sender }))?;
+
+        // The data is written from this thread, as `writer` may be borrowed
+        let mut written = Ok(());
+        for chunk in receiver {
+            if let Err(err) = writer.write_all(&chunk) {
+                // Closing the channel fails the transfer
+                written = Err(err);
+                break;
+            }
+        }
+        let report = join_transfer(join_handle)?;
+        written.and_then(|_| writer.flush())?;
+
+        Ok(report)
+    }
+
+    /// Uploads the content of `reader` to the `remote` file of the server,
+    /// without any local file, and returns the [`TransferReport`] of the
+    /// transfer once the request is accepted. The transfer size option is
+    /// only sent when `size` is known.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::net::Ipv4Addr;
+    /// use tftpd::{Client, ClientConfig};
+    ///
+    /// let config = ClientConfig {
+    ///     remote_ip_address: Ipv4Addr::new(192, 168, 1, 10).into(),
+    ///     ..Default::default()
+    /// };
+    /// let mut client = Client::new(&config).unwrap();
+    ///
+    /// let data = b"Hello, world!";
+    /// let report = client.put("hello.txt", &data[..], Some(data.len() as u64)).unwrap();
+    /// assert!(report.is_success());
+    /// ```
+    pub fn put<R: Read>(
+        &mut self,
+        remote: &str,
+        mut reader: R,
+        size: Option<u64>,
+    ) -> Result<TransferReport, Error> {
+        // The netascii size is unknown without reading the whole content
+        let size = size.filter(|_| self.transfer_mode != TransferMode::Netascii);
+        let worker = self.start_stream(Mode::Upload, remote, size)?;
+
+        let (sender, receiver) = mpsc::sync_channel(STREAM_CHANNEL_SIZE);
+        let channel_reader = ChannelReader {
+            receiver,
+            chunk: Cursor::new(Vec::new()),
+        };
+        let join_handle = worker.send(Box::new(channel_reader), false)?;
+
+        // The data is read from this thread, as `reader` may be borrowed
+        let mut buffer = vec![0; STREAM_CHUNK_SIZE];
+        loop {
+            let chunk = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(size) => Ok(buffer[..size].to_vec()),
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => Err(err),
+            };
+            let failed = chunk.is_err();
+            // The channel is closed once the transfer has ended
+            if sender.send(chunk).is_err() || failed {
+                break;
+            }
+        }
+        drop(sender);
+
+        join_transfer(join_handle)
+    }
+
+    /// Starts the transfer of the `remote` file from or to a stream of the
+    /// caller. The configuration of the client is restored afterwards, so
+    /// that later transfers are not affected.
+    fn start_stream(
+        &mut self,
+        mode: Mode,
+        remote: &str,
+        transfer_size: Option<u64>,
+    ) -> Result<Worker<dyn Socket>, Error> {
+        let config = (
+            self.mode,
+            self.file_remote.clone(),
+            self.file_local.clone(),
+            self.opt_common.clone(),
+        );
+
+        self.mode = mode;
+        self.file_remote = remote.to_string();
+        // Only used to identify the transfer in the logs
+        self.file_local = PathBuf::from(remote);
+        self.opt_common.transfer_size = transfer_size;
+        let request = match mode {
+            Mode::Upload => self.write_request(),
+            Mode::Download => self.read_request(),
+        };
+        let result = self.start(request);
+
+        (
+            self.mode,
+            self.file_remote,
+            self.file_local,
+            self.opt_common,
+        ) = config;
+        result
+    }
+
+    /// Sends `request` to the server, trying each of its addresses, and
+    /// returns the [`Worker`] of the accepted transfer.
+    fn start(&mut self, mut request: Packet) -> Result<Worker<dyn Socket>, Error> {
+        let mut result = Err(Error::Config("No address to connect to".to_string()));
+        for address in self.remote_addresses.clone() {
+            result = self.connect(&address, &mut request);
+            match &result {
+                Err(err @ (Error::Timeout(_) | Error::Io(_))) => {
+                    log_warn!("Could not reach {address}: {err}")
+                }
+                _ => break,
+            }
+        }
+        let (socket, response, from) = result?;
+
+        socket.connect(from)?;
+        let first_packet = match response {
+            Response::Accepted(Some(ack)) => {
+                Socket::send_to(&socket, &ack, &from)?;
+                None
+            }
+            Response::Data(packet) => Some(packet),
+            _ => None,
+        };
+
+        let mut worker = self.configure_worker(socket)?;
+        if let Some(packet) = first_packet {
+            worker.set_first_packet(packet);
+        }
+
+        Ok(worker)
+    }
+
+    /// Sends `request` to the server at `address`, and returns the socket
+    /// with the first response of the server and its address.
+    fn connect(
+        &mut self,
+        address: &SocketAddr,
+        request: &mut Packet,
+    ) -> Result<(UdpSocket, Response, SocketAddr), Error> {
+        let socket = if address.is_ipv4() {
             UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?
         } else {
             UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))?
@@ -73,141 +287,162 @@ impl Client {
 
         socket.set_read_timeout(Some(self.timeout_req))?;
 
-        match self.mode {
-            Mode::Upload => self.upload(socket),
-            Mode::Download => self.download(socket),
+        loop {
+            Socket::send_to(&socket, request, address)?;
+
+            let (packet, from) =
+                Socket::recv_from(&socket).map_err(|err| response_error(err, address))?;
+            match self.handle_response(packet)? {
+                Response::Retry(err) => remove_options(request, err)?,
+                response => return Ok((socket, response, from)),
+            }
         }
     }
 
-    fn upload(&mut self, socket: UdpSocket) -> Result<bool, Box<dyn Error>> {
-        if self.mode != Mode::Upload {
-            return Err(Box::from("Client mode is set to Download"));
+    /// Resolves the local and remote files depending on the [`Mode`], and
+    /// returns the request to send to the server.
+    pub(crate) fn request(&mut self) -> Result<Packet, Error> {
+        match self.mode {
+            Mode::Upload => self.upload_request(),
+            Mode::Download => self.download_request(),
         }
+    }
 
+    fn upload_request(&mut self) -> Result<Packet, Error> {
         if self.file_remote.is_empty() {
             // 1 path provided: use filename as remote
             self.file_remote = self
                 .file_local
                 .file_name()
-                .ok_or("Invalid filename")?
+                .ok_or(Error::Config("Invalid filename".to_string()))?
                 .to_str()
-                .ok_or("Filename is not valid UTF-8")?
+                .ok_or(Error::Config("Filename is not valid UTF-8".to_string()))?
                 .to_owned();
         }
 
-        self.opt_common.transfer_size = Some(fs::metadata(self.file_local.clone())?.len());
+        // The size of a stream, such as the standard input or a pipe, is
+        // unknown without reading it whole
+        let sized = !is_stdio(&self.file_local) && fs::metadata(&self.file_local)?.is_file();
+        self.opt_common.transfer_size = match self.transfer_mode {
+            _ if !sized => None,
+            TransferMode::Netascii => Some(netascii_len(File::open(&self.file_local)?)?),
+            _ => Some(fs::metadata(self.file_local.clone())?.len()),
+        };
 
-        log_dbg!("  Sending Write request for {}", self.file_remote);
-        Socket::send_to(
-            &socket,
-            &Packet::Wrq {
-                filename: self.file_remote.clone(),
-                mode: "octet".into(),
-                options: self.opt_common.prepare(),
-            },
-            &self.remote_address,
-        )?;
-
-        match Socket::recv_from(&socket) {
-            Ok((packet, from)) => {
-                socket.connect(from)?;
-                match packet {
-                    Packet::Oack(options) => {
-                        // Reset options before applying those from server
-                        self.opt_common = Default::default();
-                        self.opt_common.apply(&options)?;
-                        log_dbg!("  Accepted options: {}", OptionFmt(&options));
-                    }
-
-                    Packet::Ack(_) => {
-                        self.opt_common = Default::default();
-                        log_dbg!("  Options not accepted, using default");
-                    }
-
-                    Packet::Error { code, msg } => {
-                        return Err(Box::from(format!(
-                            "Client received error from server: {code}: {msg}"
-                        )))
-                    }
-
-                    _ => {
-                        return Err(Box::from(format!(
-                            "Client received unexpected packet from server: {packet:#?}"
-                        )))
-                    }
-                }
+        Ok(self.write_request())
+    }
 
-                let worker = self.configure_worker(socket)?;
-                let join_handle = worker.send(false)?;
-                Ok(join_handle.join().unwrap())
-            }
-            Err(err) => Err(Box::from(format!("Unexpected Error: {err}"))),
+    fn write_request(&self) -> Packet {
+        let mut options = self.opt_common.prepare();
+        if self.opt_common.transfer_size.is_none() {
+            options.retain(|option| option.option != OptionType::TransferSize);
         }
-    }
 
-    fn download(&mut self, socket: UdpSocket) -> Result<bool, Box<dyn Error>> {
-        if self.mode != Mode::Download {
-            return Err(Box::from("Client mode is set to Upload"));
+        log_dbg!("  Sending Write request for {}", self.file_remote);
+        Packet::Wrq {
+            filename: self.file_remote.clone(),
+            mode: self.transfer_mode.to_string(),
+            options,
         }
+    }
 
+    fn download_request(&mut self) -> Result<Packet, Error> {
         if self.file_remote.is_empty() {
             // 1 path provided: use it as remote and use rxdir + filename as local
             self.file_remote = self.file_local.display().to_string();
-            self.file_local = self
-                .receive_directory
-                .join(self.file_local.file_name().ok_or("Invalid filename")?)
-        } else {
+            self.file_local = self.receive_directory.join(
+                self.file_local
+                    .file_name()
+                    .ok_or(Error::Config("Invalid filename".to_string()))?,
+            )
+        } else if !is_stdio(&self.file_local) {
             // 2 paths provided: prefix the local one with rxdir and use remote as is
             self.file_local = self.receive_directory.join(self.file_local.clone());
         }
 
+        Ok(self.read_request())
+    }
+
+    fn read_request(&self) -> Packet {
         log_dbg!("  Sending Read request for {}", self.file_remote);
-        Socket::send_to(
-            &socket,
-            &Packet::Rrq {
-                filename: self.file_remote.clone(),
-                mode: "octet".into(),
-                options: self.opt_common.prepare(),
-            },
-            &self.remote_address,
-        )?;
-
-        match Socket::recv_from(&socket) {
-            Ok((packet, from)) => {
-                socket.connect(from)?;
-                match packet {
-                    Packet::Oack(options) => {
-                        // Reset options before applying those from server
-                        self.opt_common = Default::default();
-                        self.opt_common.apply(&options)?;
-                        log_dbg!("  Accepted options: {}", OptionFmt(&options));
-                        Socket::send_to(&socket, &Packet::Ack(0), &from)?;
-                        let worker = self.configure_worker(socket)?;
-                        let join_handle = worker.receive()?;
-                        Ok(join_handle.join().unwrap())
-                    }
-
-                    // We could implement this by forwarding Option<packet::Data> to worker.receive()
-                    Packet::Data { .. } => Err(
-                        "Client received data instead of o-ack. This implementation \
-                        does not support servers without options (RFC 2347)"
-                            .into(),
-                    ),
-
-                    Packet::Error { code, msg } => Err(Box::from(format!(
-                        "Client received error from server: {code}: {msg}"
-                    ))),
-
-                    _ => Err(Box::from(format!(
-                        "Client received unexpected packet from server: {packet:#?}"
-                    ))),
+        Packet::Rrq {
+            filename: self.file_remote.clone(),
+            mode: self.transfer_mode.to_string(),
+            options: self.opt_common.prepare(),
+        }
+    }
+
+    /// Opens the local file to upload, or the standard input.
+    pub(crate) fn source(&self) -> Result<Box<dyn Read + Send>, Error> {
+        if is_stdio(&self.file_local) {
+            return Ok(Box::new(io::stdin()));
+        }
+
+        Ok(Box::new(File::open(&self.file_local)?))
+    }
+
+    /// Creates the local file to download to, or the standard output.
+    pub(crate) fn sink(&self) -> Result<Box<dyn FileSink>, Error> {
+        if is_stdio(&self.file_local) {
+            return Ok(Box::new(WriteSink::new(io::stdout())));
+        }
+
+        Ok(Box::new(FileWriter::create(&self.file_local)?))
+    }
+
+    /// Applies the options of the first response of the server, and tells
+    /// how the transfer starts. Servers which do not support options
+    /// (RFC 1350) answer with the first data block or acknowledgement, and
+    /// the default options are used.
+    pub(crate) fn handle_response(&mut self, packet: Packet) -> Result<Response, Error> {
+        match packet {
+            Packet::Oack(options) => {
+                // Reset options before applying those from server
+                self.opt_common = Default::default();
+                self.opt_common.apply(&options)?;
+                log_dbg!("  Accepted options: {}", OptionFmt(&options));
+                match self.mode {
+                    Mode::Upload => Ok(Response::Accepted(None)),
+                    Mode::Download => Ok(Response::Accepted(Some(Packet::Ack(0)))),
                 }
             }
-            Err(err) => Err(Box::from(format!("Unexpected Error: {err}"))),
+
+            Packet::Ack(_) if self.mode == Mode::Upload => {
+                self.opt_common = Default::default();
+                log_dbg!("  Options not accepted, using default");
+                Ok(Response::Accepted(None))
+            }
+
+            Packet::Data { .. } if self.mode == Mode::Download => {
+                self.opt_common = Default::default();
+                log_dbg!("  Options not accepted, using default");
+                Ok(Response::Data(packet))
+            }
+
+            Packet::Error {
+                code: ErrorCode::RefusedOption,
+                msg,
+            } => {
+                self.opt_common = Default::default();
+                Ok(Response::Retry(Error::OptionRejected(format!(
+                    "Client received error from server: {}: {msg}",
+                    ErrorCode::RefusedOption
+                ))))
+            }
+
+            Packet::Error { code, msg } => Err(Error::Protocol(
+                code,
+                format!("Client received error from server: {code}: {msg}"),
+            )),
+
+            _ => Err(Error::Protocol(
+                ErrorCode::IllegalOperation,
+                format!("Client received unexpected packet from server: {packet:#?}"),
+            )),
         }
     }
 
-    fn configure_worker(&self, socket: UdpSocket) -> Result<Worker<dyn Socket>, Box<dyn Error>> {
+    fn configure_worker(&self, socket: UdpSocket) -> Result<Worker<dyn Socket>, Error> {
         let mut socket: Box<dyn Socket> = Box::new(socket);
 
         socket.set_read_timeout(self.opt_common.timeout)?;
@@ -216,6 +451,7 @@ impl Client {
         Ok(Worker::new(
             socket,
             self.file_local.clone(),
+            self.transfer_mode,
             self.opt_local.clone(),
             self.opt_common.clone(),
             self.abort.clone(),
@@ -227,3 +463,205 @@ impl Client {
         self.abort.clone()
     }
 }
+
+/// Waits for the end of a transfer, and returns its [`TransferReport`].
+fn join_transfer(join_handle: thread::JoinHandle<TransferReport>) -> Result<TransferReport, Error> {
+    join_handle
+        .join()
+        .map_err(|_| Error::io(ErrorKind::Other, "Transfer thread panicked"))
+}
+
+/// Sink of a transfer passing the received data to the thread of the
+/// caller, see [`Client::get()`].
+struct ChannelSink {
+    sender: SyncSender<Vec<u8>>,
+}
+
+impl Write for ChannelSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sender
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "Failed to write the data"))?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl FileSink for ChannelSink {}
+
+/// Source of a transfer reading the data passed by the thread of the
+/// caller, see [`Client::put()`]. The data ends once the channel is closed.
+struct ChannelReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    chunk: Cursor<Vec<u8>>,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let size = self.chunk.read(buf)?;
+            if size > 0 || buf.is_empty() {
+                return Ok(size);
+            }
+
+            match self.receiver.recv() {
+                Ok(chunk) => self.chunk = Cursor::new(chunk?),
+                Err(_) => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Describes a failure to receive the first response of the server at
+/// `address`.
+pub(crate) fn response_error(err: Error, address: &SocketAddr) -> Error {
+    match err {
+        Error::Timeout(_) => Error::Timeout(format!("No response from the server {address}")),
+        err => err,
+    }
+}
+
+/// Removes the options of `request` once the server refused them, or
+/// returns `err` if the request had none.
+pub(crate) fn remove_options(request: &mut Packet, err: Error) -> Result<(), Error> {
+    match request {
+        Packet::Rrq { options, .. } | Packet::Wrq { options, .. } if !options.is_empty() => {
+            log_warn!("Server refused the options, sending the request without them");
+            options.clear();
+            Ok(())
+        }
+        _ => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    use crate::ServerBuilder;
+
+    #[test]
+    fn transfers_in_memory() {
+        const DIR_NAME: &str = "target/test/transfers_in_memory";
+        let directory = Path::new(DIR_NAME);
+        let _ = fs::remove_dir_all(directory);
+        fs::create_dir_all(directory).unwrap();
+        let content: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        fs::write(directory.join("device.bin"), &content).unwrap();
+
+        let handle = ServerBuilder::new()
+            .ip_address([127, 0, 0, 1].into())
+            .port(0)
+            .directory(directory)
+            .start()
+            .unwrap();
+        let config = ClientConfig {
+            port: handle.local_addr().port(),
+            ..Default::default()
+        };
+        let mut client = Client::new(&config).unwrap();
+
+        let mut data = Vec::new();
+        let report = client.get("device.bin", &mut data).unwrap();
+        assert!(report.is_success());
+        assert_eq!(data, content);
+
+        // Without a size, the data is read until the end of the stream
+        let stream = Read::chain(&data[..100], &data[100..]);
+        let report = client.put("upload.bin", stream, None).unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.bytes, 3000);
+
+        // The configuration of the client is left unchanged
+        assert_eq!(client.mode, config.mode);
+        assert_eq!(client.file_remote, config.file_remote);
+        assert_eq!(client.file_local, config.file_path);
+        assert_eq!(client.opt_common, config.opt_common);
+
+        handle.shutdown();
+        handle.join().unwrap();
+        assert_eq!(fs::read(directory.join("upload.bin")).unwrap(), content);
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+
+    #[test]
+    fn omits_transfer_size_of_streams() {
+        let args = ["-", "remote.bin", "-u"].iter().map(|s| s.to_string());
+        let mut client = Client::new(&ClientConfig::new(args).unwrap()).unwrap();
+
+        let Packet::Wrq { options, .. } = client.request().unwrap() else {
+            panic!("expected a write request");
+        };
+        assert!(options
+            .iter()
+            .all(|option| option.option != OptionType::TransferSize));
+
+        let args = ["Cargo.toml", "-u"].iter().map(|s| s.to_string());
+        let mut client = Client::new(&ClientConfig::new(args).unwrap()).unwrap();
+
+        let Packet::Wrq { options, .. } = client.request().unwrap() else {
+            panic!("expected a write request");
+        };
+        let size = fs::metadata("Cargo.toml").unwrap().len();
+        assert!(options
+            .iter()
+            .any(|option| option.option == OptionType::TransferSize && option.value == size));
+    }
+
+    #[test]
+    fn downloads_from_servers_without_options() {
+        const DIR_NAME: &str = "target/test/downloads_from_servers_without_options";
+        let directory = Path::new(DIR_NAME);
+        let _ = fs::remove_dir_all(directory);
+        fs::create_dir_all(directory).unwrap();
+
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let port = server.local_addr().unwrap().port().to_string();
+        let args = ["hello.txt", "-d", "-p", &port, "-rd"];
+        let args = args.iter().map(|s| s.to_string());
+        let args = args.chain([directory.display().to_string()]);
+        let mut client = Client::new(&ClientConfig::new(args).unwrap()).unwrap();
+        let handle = thread::spawn(move || client.run());
+
+        // The options are refused, then the request is sent again without them
+        let (request, from) = server.recv_from_with_size(512).unwrap();
+        assert!(matches!(request, Packet::Rrq { options, .. } if !options.is_empty()));
+        let error = Packet::Error {
+            code: ErrorCode::RefusedOption,
+            msg: "options not supported".to_string(),
+        };
+        Socket::send_to(&server, &error, &from).unwrap();
+        let (request, from) = server.recv_from_with_size(512).unwrap();
+        assert!(matches!(request, Packet::Rrq { options, .. } if options.is_empty()));
+
+        // The server answers with the first data block instead of an OACK
+        let transfer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        transfer
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let data = Packet::Data {
+            block_num: 1,
+            data: b"Hello".to_vec(),
+        };
+        Socket::send_to(&transfer, &data, &from).unwrap();
+        let (ack, _) = transfer.recv_from_with_size(512).unwrap();
+        assert_eq!(ack, Packet::Ack(1));
+
+        let report = handle.join().unwrap().unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.bytes, 5);
+        assert_eq!(report.options.block_size, 512);
+        assert_eq!(fs::read(directory.join("hello.txt")).unwrap(), b"Hello");
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+}
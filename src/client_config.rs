@@ -1,17 +1,22 @@
-use std::error::Error;
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
 use std::path::{Path, PathBuf, MAIN_SEPARATOR};
-use std::process;
 use std::time::Duration;
+use std::{mem, process};
 
 use crate::client::Mode;
 use crate::config;
+use crate::config_file::{apply_config_file, take_config_file};
 use crate::log::*;
 use crate::options::{OptionsPrivate, OptionsProtocol, DEFAULT_TIMEOUT};
+use crate::{Error, TransferMode};
 
 #[cfg(feature = "debug_drop")]
 use crate::drop::drop_set;
 
+const URL_SCHEME: &str = "tftp://";
+/// Local file name standing for the standard input or output
+const STDIO: &str = "-";
+
 /// Configuration `struct` used for parsing TFTP Client options from user
 /// input.
 ///
@@ -27,16 +32,26 @@ use crate::drop::drop_set;
 ///
 /// let client_config = ClientConfig::new(env::args());
 /// ```
+///
+/// Instead of a file name, the server and the remote file can be given as a
+/// `tftp://host[:port]/file[;mode=octet]` URL. The `-` local file uploads
+/// from the standard input, or downloads to the standard output.
 #[derive(Debug)]
 pub struct ClientConfig {
     /// Local IP address of the TFTP Client. (default: 127.0.0.1)
     pub remote_ip_address: IpAddr,
+    /// Host name of the server, resolved instead of `remote_ip_address` if set. (default: none)
+    pub remote_host: Option<String>,
+    /// IP version of the resolved addresses tried first. (default: any)
+    pub ip_preference: IpPreference,
     /// Local Port number of the TFTP Client. (default: 69)
     pub port: u16,
     /// Timeout to use after request. (default: 5s)
     pub timeout_req: Duration,
     /// Upload or Download a file. (default: Download)
     pub mode: Mode,
+    /// Transfer mode used for the file. (default: octet)
+    pub transfer_mode: TransferMode,
     /// Download directory of the TFTP Client. (default: current working directory)
     pub receive_directory: PathBuf,
     /// File to Upload or Download.
@@ -49,13 +64,29 @@ pub struct ClientConfig {
     pub opt_common: OptionsProtocol,
 }
 
+/// IpPreference `enum` tells which addresses of the host name of a server
+/// the client tries first.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IpPreference {
+    /// Addresses are tried in the order returned by the resolver
+    #[default]
+    Any,
+    /// IPv4 addresses are tried first
+    Ipv4,
+    /// IPv6 addresses are tried first
+    Ipv6,
+}
+
 impl Default for ClientConfig {
     fn default() -> Self {
         Self {
             remote_ip_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            remote_host: None,
+            ip_preference: IpPreference::Any,
             port: 69,
             timeout_req: DEFAULT_TIMEOUT,
             mode: Mode::Download,
+            transfer_mode: TransferMode::Octet,
             receive_directory: Default::default(),
             file_path: Default::default(),
             file_remote: Default::default(),
@@ -65,18 +96,22 @@ impl Default for ClientConfig {
     }
 }
 
-fn parse_duration<T: Iterator<Item = String>>(args: &mut T) -> Result<Duration, Box<dyn Error>> {
+fn parse_duration<T: Iterator<Item = String>>(args: &mut T) -> Result<Duration, Error> {
     if let Some(dur_str) = args.next() {
-        let dur = Duration::from_secs_f32(dur_str.parse::<f32>()?);
+        let dur = Duration::from_secs_f32(dur_str.parse::<f32>().map_err(Error::config)?);
         if dur < Duration::from_secs_f32(0.000001) {
-            Err("duration cannot be shorter than 1 us".into())
+            Err(Error::Config(
+                "duration cannot be shorter than 1 us".to_string(),
+            ))
         } else if dur > Duration::from_secs(255) {
-            Err("duration cannot be greater than 255 s".into())
+            Err(Error::Config(
+                "duration cannot be greater than 255 s".to_string(),
+            ))
         } else {
             Ok(dur)
         }
     } else {
-        Err("Missing duration after flag".into())
+        Err(Error::Config("Missing duration after flag".to_string()))
     }
 }
 
@@ -92,142 +127,364 @@ fn print_version_exit() {
 impl ClientConfig {
     /// Creates a new configuration by parsing the supplied arguments. It is
     /// intended for use with [`env::args()`].
-    pub fn new<T: Iterator<Item = String>>(mut args: T) -> Result<ClientConfig, Box<dyn Error>> {
+    ///
+    /// The `--config <FILE>` flag loads a TOML or INI configuration file,
+    /// whose keys are the long flags without the leading dashes. Flags and
+    /// file names given as arguments override the values of the file.
+    pub fn new<T: Iterator<Item = String>>(args: T) -> Result<ClientConfig, Error> {
         let mut config = ClientConfig::default();
         let mut verbosity: isize = 1;
 
+        let mut args: Vec<String> = args.collect();
+
+        if let Some(file) = take_config_file(&mut args)? {
+            apply_config_file(Path::new(&file), |arg, args| {
+                config.parse_arg(arg, args, &mut verbosity)
+            })?;
+        }
+
+        // File names given as arguments replace the ones of the config file
+        let file_path = mem::take(&mut config.file_path);
+        let file_remote = mem::take(&mut config.file_remote);
+
+        let mut args = args.into_iter();
         while let Some(arg) = args.next() {
-            match arg.as_str() {
-                "-i" | "--ip-address" => {
-                    if let Some(ip_str) = args.next() {
-                        let ip_addr: IpAddr = ip_str.parse()?;
-                        config.remote_ip_address = ip_addr;
-                    } else {
-                        return Err("Missing ip address after flag".into());
-                    }
-                }
-                "-p" | "--port" => {
-                    if let Some(port_str) = args.next() {
-                        config.port = port_str.parse::<u16>()?;
-                    } else {
-                        return Err("Missing port number after flag".into());
-                    }
-                }
-                "-b" | "--blocksize" => {
-                    if let Some(blocksize_str) = args.next() {
-                        config.opt_common.block_size = blocksize_str.parse::<u16>()?;
-                    } else {
-                        return Err("Missing blocksize after flag".into());
-                    }
-                }
-                "-w" | "--windowsize" => {
-                    if let Some(windowsize_str) = args.next() {
-                        config.opt_common.window_size = windowsize_str.parse::<u16>()?;
-                    } else {
-                        return Err("Missing windowsize after flag".into());
-                    }
+            if !config.parse_arg(&arg, &mut args, &mut verbosity)? {
+                return Err(Error::Config(format!(
+                    "unkwon flag {arg} (or use '--' to force into filename)"
+                )));
+            }
+        }
+
+        if config.file_path.as_os_str().is_empty() {
+            config.file_path = file_path;
+        }
+        if config.file_remote.is_empty() {
+            config.file_remote = file_remote;
+        }
+        if config.file_path.as_os_str().is_empty() {
+            // Only a remote file or URL provided: use its filename as local
+            if let Some(file_name) = Path::new(&config.file_remote).file_name() {
+                config.file_path = file_name.into();
+            }
+        }
+
+        if config.file_path.as_os_str().is_empty() {
+            return Err(Error::Config("missing filename".to_string()));
+        }
+        if is_stdio(&config.file_path) && config.file_remote.is_empty() {
+            return Err(Error::Config(format!("missing remote file for {STDIO}")));
+        }
+
+        if config.opt_common.timeout <= config.opt_common.window_wait {
+            return Err(Error::Config(
+                "Inter-packet wait time cannot be exceed timeout".to_string(),
+            ));
+        }
+
+        if !config.opt_common.window_wait.is_zero() && config.opt_common.window_size == 1 {
+            return Err(Error::Config(
+                "Inter-packet wait time needs window size > 1".to_string(),
+            ));
+        }
+
+        verbosity_set(verbosity);
+        if is_stdio(&config.file_path) && config.mode == Mode::Download {
+            // The logs would be mixed with the downloaded data
+            log_to_stderr_set();
+        }
+
+        Ok(config)
+    }
+
+    fn parse_arg<T: Iterator<Item = String>>(
+        &mut self,
+        arg: &str,
+        args: &mut T,
+        verbosity: &mut isize,
+    ) -> Result<bool, Error> {
+        match arg {
+            "-i" | "--ip-address" => {
+                if let Some(host_str) = args.next() {
+                    self.set_host(&host_str)?;
+                } else {
+                    return Err(Error::Config("Missing ip address after flag".to_string()));
                 }
-                "-W" | "--windowwait" => {
-                    config.opt_common.window_wait = parse_duration(&mut args)?;
+            }
+            "-p" | "--port" => {
+                if let Some(port_str) = args.next() {
+                    self.port = port_str.parse::<u16>().map_err(Error::config)?;
+                } else {
+                    return Err(Error::Config("Missing port number after flag".to_string()));
                 }
-                "-t" | "--timeout" => {
-                    config.opt_common.timeout = parse_duration(&mut args)?;
+            }
+            "-4" | "--ipv4" => {
+                self.ip_preference = IpPreference::Ipv4;
+            }
+            "-6" | "--ipv6" => {
+                self.ip_preference = IpPreference::Ipv6;
+            }
+            "-b" | "--blocksize" => {
+                if let Some(blocksize_str) = args.next() {
+                    self.opt_common.block_size =
+                        blocksize_str.parse::<u16>().map_err(Error::config)?;
+                } else {
+                    return Err(Error::Config("Missing blocksize after flag".to_string()));
                 }
-                "-T" | "--timeout-req" => {
-                    config.timeout_req = parse_duration(&mut args)?;
+            }
+            "-w" | "--windowsize" => {
+                if let Some(windowsize_str) = args.next() {
+                    self.opt_common.window_size =
+                        windowsize_str.parse::<u16>().map_err(Error::config)?;
+                } else {
+                    return Err(Error::Config("Missing windowsize after flag".to_string()));
                 }
-                "-rd" | "--receive-directory" => {
-                    if let Some(dir_str) = args.next() {
-                        if !Path::new(&dir_str).exists() {
-                            return Err(format!("{dir_str} does not exist").into());
-                        }
-                        config.receive_directory = dir_str.into();
-                    } else {
-                        return Err("Missing receive directory after flag".into());
+            }
+            "-W" | "--windowwait" => {
+                self.opt_common.window_wait = parse_duration(args)?;
+            }
+            "-t" | "--timeout" => {
+                self.opt_common.timeout = parse_duration(args)?;
+            }
+            "-T" | "--timeout-req" => {
+                self.timeout_req = parse_duration(args)?;
+            }
+            "-rd" | "--receive-directory" => {
+                if let Some(dir_str) = args.next() {
+                    if !Path::new(&dir_str).exists() {
+                        return Err(Error::Config(format!("{dir_str} does not exist")));
                     }
+                    self.receive_directory = dir_str.into();
+                } else {
+                    return Err(Error::Config(
+                        "Missing receive directory after flag".to_string(),
+                    ));
                 }
-                "-u" | "--upload" => {
-                    config.mode = Mode::Upload;
-                }
-                "-d" | "--download" => {
-                    config.mode = Mode::Download;
+            }
+            "--file" => {
+                if let Some(file_str) = args.next() {
+                    self.file_path = convert_file_path_abs(&file_str);
+                } else {
+                    return Err(Error::Config("Missing file after flag".to_string()));
                 }
-                "-h" | "--help" => {
-                    println!("TFTP Client\n");
-                    println!("Usage: tftpd client [options] <file> [remote file] \n");
-                    println!("Options:");
-                    println!("  -i, --ip-address <IP ADDRESS>\t\tIP address of the server (default: 127.0.0.1)");
-                    println!("  -p, --port <PORT>\t\t\tUDP port of the server (default: 69)");
-                    println!("  -b, --blocksize <number>\t\tset the blocksize (default: 512)");
-                    println!("  -w, --windowsize <number>\t\tset the windowsize (default: 1)");
-                    println!("  -W, --windowwait <seconds>\t\t inter-packet wait time in seconds for windows (default: 0)");
-                    println!("  -t, --timeout <seconds>\t\tset the timeout for data in seconds (default: 5, can be float)");
-                    println!("  -T, --timeout-req <seconds>\t\tset the timeout after request in seconds (default: 5, can be float)");
-                    println!("  -u, --upload\t\t\t\tselect upload mode, ignores previous flags");
-                    println!("  -d, --download\t\t\tselect download mode, ignores previous flags");
-                    println!("  -rd, --receive-directory <DIR>\tdirectory to receive files when in Download mode (default: current)");
-                    config::print_opt_local_help();
-                    println!(
-                        "  -v, --verbose\t\t\t\tIncrease log verbosity (can be repeated, e.g. -vv)"
-                    );
-                    println!("  -q, --quiet\t\t\t\tDecrease log verbosity (can be repeated)");
-                    println!("  -h, --help\t\t\t\tprint help information");
-                    println!("  -V, --version\t\t\t\tprint version");
-                    process::exit(0);
+            }
+            "--remote-file" => {
+                if let Some(file_str) = args.next() {
+                    self.file_remote = file_str;
+                } else {
+                    return Err(Error::Config("Missing remote file after flag".to_string()));
                 }
-                "-q" | "--quiet" => verbosity -= 1,
-                "-v" | "--verbose" => verbosity += 1,
-                "-V" | "--version" => print_version_exit(),
-                #[cfg(feature = "debug_drop")]
-                "-D" => drop_set(args.next())?,
-                "--" => {
-                    for arg in args.by_ref() {
-                        config.set_paths(&arg)?;
+            }
+            "-u" | "--upload" => {
+                self.mode = Mode::Upload;
+            }
+            "-d" | "--download" => {
+                self.mode = Mode::Download;
+            }
+            "--mode" => {
+                if let Some(mode_str) = args.next() {
+                    let transfer_mode = mode_str.parse::<TransferMode>().map_err(Error::config)?;
+                    if !transfer_mode.is_supported() {
+                        return Err(Error::Config(format!(
+                            "Unsupported transfer mode: {mode_str}"
+                        )));
                     }
+                    self.transfer_mode = transfer_mode;
+                } else {
+                    return Err(Error::Config(
+                        "Missing transfer mode after flag".to_string(),
+                    ));
+                }
+            }
+            "-h" | "--help" => {
+                println!("TFTP Client\n");
+                println!("Usage: tftpd client [options] <file> [remote file] \n");
+                println!("       tftpd client [options] - <remote file>  (standard input or output as file)\n");
+                println!("       tftpd client [options] [file] tftp://<host>[:port]/<remote file>[;mode=<MODE>]\n");
+                println!("Options:");
+                println!("  -i, --ip-address <HOST>\t\tIP address or host name of the server (default: 127.0.0.1)");
+                println!("  -4, --ipv4\t\t\t\ttry the IPv4 addresses of the server first");
+                println!("  -6, --ipv6\t\t\t\ttry the IPv6 addresses of the server first");
+                println!("  -p, --port <PORT>\t\t\tUDP port of the server (default: 69)");
+                println!("  -b, --blocksize <number>\t\tset the blocksize (default: 512)");
+                println!("  -w, --windowsize <number>\t\tset the windowsize (default: 1)");
+                println!("  -W, --windowwait <seconds>\t\t inter-packet wait time in seconds for windows (default: 0)");
+                println!("  -t, --timeout <seconds>\t\tset the timeout for data in seconds (default: 5, can be float)");
+                println!("  -T, --timeout-req <seconds>\t\tset the timeout after request in seconds (default: 5, can be float)");
+                println!("  -u, --upload\t\t\t\tselect upload mode, ignores previous flags");
+                println!("  -d, --download\t\t\tselect download mode, ignores previous flags");
+                println!("  --mode <MODE>\t\t\t\ttransfer mode: octet, netascii (default: octet)");
+                println!("  -rd, --receive-directory <DIR>\tdirectory to receive files when in Download mode (default: current)");
+                config::print_opt_local_help();
+                println!(
+                    "  -v, --verbose\t\t\t\tIncrease log verbosity (can be repeated, e.g. -vv)"
+                );
+                println!("  -q, --quiet\t\t\t\tDecrease log verbosity (can be repeated)");
+                println!("  --file <FILE>\t\t\t\tfile to upload or download, same as the <file> argument");
+                println!("  --remote-file <FILE>\t\t\tfile path to send to the server, same as the [remote file] argument");
+                println!("  --config <FILE>\t\t\tload options from a TOML or INI file, overridden by arguments");
+                println!("  -h, --help\t\t\t\tprint help information");
+                println!("  -V, --version\t\t\t\tprint version");
+                process::exit(0);
+            }
+            "-q" | "--quiet" => *verbosity -= 1,
+            "-v" | "--verbose" => *verbosity += 1,
+            "-V" | "--version" => print_version_exit(),
+            #[cfg(feature = "debug_drop")]
+            "-D" => drop_set(args.next())?,
+            STDIO => self.set_paths(arg)?,
+            "--" => {
+                for arg in args {
+                    self.set_paths(&arg)?;
                 }
-                arg => {
-                    if !config::parse_local_args(arg, &mut args, &mut config.opt_local)? {
-                        if arg.starts_with('-') {
-                            return Err(format!(
-                                "unkwon flag {arg} (or use '--' to force into filename)"
-                            )
-                            .into());
-                        }
-                        config.set_paths(arg)?;
+            }
+            arg => {
+                if !config::parse_local_args(arg, args, &mut self.opt_local)? {
+                    if arg.starts_with('-') {
+                        return Ok(false);
                     }
+                    self.set_paths(arg)?;
                 }
             }
         }
+        Ok(true)
+    }
 
-        if config.file_path.as_os_str().is_empty() {
-            return Err("missing filename".into());
+    /// Returns the addresses of the server, resolving its host name if set,
+    /// with the addresses of the preferred IP version first.
+    pub fn remote_addresses(&self) -> Result<Vec<SocketAddr>, Error> {
+        let Some(host) = &self.remote_host else {
+            return Ok(vec![SocketAddr::new(self.remote_ip_address, self.port)]);
+        };
+
+        let mut addresses: Vec<SocketAddr> = (host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|err| Error::Config(format!("Could not resolve {host}: {err}")))?
+            .collect();
+        if addresses.is_empty() {
+            return Err(Error::Config(format!("No address found for {host}")));
+        }
+        // The sort is stable, keeping the order of the resolver otherwise
+        match self.ip_preference {
+            IpPreference::Any => {}
+            IpPreference::Ipv4 => addresses.sort_by_key(|address| !address.is_ipv4()),
+            IpPreference::Ipv6 => addresses.sort_by_key(|address| !address.is_ipv6()),
         }
 
-        if config.opt_common.timeout <= config.opt_common.window_wait {
-            return Err("Inter-packet wait time cannot be exceed timeout".into());
+        Ok(addresses)
+    }
+
+    fn set_host(&mut self, host: &str) -> Result<(), Error> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(Error::Config("Missing host name".to_string()));
         }
 
-        if !config.opt_common.window_wait.is_zero() && config.opt_common.window_size == 1 {
-            return Err("Inter-packet wait time needs window size > 1".into());
+        match host.parse::<IpAddr>() {
+            Ok(ip_addr) => {
+                self.remote_ip_address = ip_addr;
+                self.remote_host = None;
+            }
+            Err(_) => self.remote_host = Some(host.to_string()),
         }
+        Ok(())
+    }
 
-        verbosity_set(verbosity);
+    /// Sets the server, the remote file and the transfer mode from a
+    /// `tftp://host[:port]/file[;mode=octet]` URL (RFC 3617).
+    fn set_url(&mut self, url: &str) -> Result<(), Error> {
+        let invalid = |reason: &str| Error::Config(format!("Invalid URL {url}: {reason}"));
 
-        Ok(config)
+        let rest = &url[URL_SCHEME.len()..];
+        let (authority, path) = rest.split_once('/').ok_or(invalid("missing file"))?;
+        let (path, mode) = match path.split_once(';') {
+            Some((path, param)) => {
+                let mode = param
+                    .strip_prefix("mode=")
+                    .ok_or(invalid("unknown parameter"))?;
+                (path, Some(mode))
+            }
+            None => (path, None),
+        };
+
+        let (host, port) = match authority.rsplit_once(':') {
+            // A colon inside brackets belongs to an IPv6 address
+            Some((host, port)) if !port.ends_with(']') => (host, Some(port)),
+            _ => (authority, None),
+        };
+        self.set_host(host)?;
+        if let Some(port) = port {
+            self.port = port.parse().map_err(|_| invalid("invalid port"))?;
+        }
+
+        if let Some(mode) = mode {
+            let transfer_mode = mode
+                .parse::<TransferMode>()
+                .map_err(|_| invalid("invalid mode"))?;
+            if !transfer_mode.is_supported() {
+                return Err(invalid("unsupported mode"));
+            }
+            self.transfer_mode = transfer_mode;
+        }
+
+        let path = percent_decode(path).ok_or(invalid("invalid escape in file"))?;
+        if path.is_empty() {
+            return Err(invalid("missing file"));
+        }
+        if !self.file_remote.is_empty() {
+            return Err(Error::Config("too many arguments".to_string()));
+        }
+        self.file_remote = path;
+
+        Ok(())
     }
 
-    fn set_paths(&mut self, arg: &str) -> Result<(), Box<dyn Error>> {
-        if self.file_path.as_os_str().is_empty() {
+    fn set_paths(&mut self, arg: &str) -> Result<(), Error> {
+        if arg.starts_with(URL_SCHEME) {
+            self.set_url(arg)?;
+        } else if arg == STDIO && !self.file_path.as_os_str().is_empty() {
+            // The standard input or output is the local file wherever it is
+            // given, the other file being the remote one
+            if !self.file_remote.is_empty() || is_stdio(&self.file_path) {
+                return Err(Error::Config("too many arguments".to_string()));
+            }
+            self.file_remote = self.file_path.to_string_lossy().to_string();
+            self.file_path = PathBuf::from(STDIO);
+        } else if arg == STDIO {
+            self.file_path = PathBuf::from(STDIO);
+        } else if self.file_path.as_os_str().is_empty() {
             self.file_path = convert_file_path_abs(arg);
         } else if self.file_remote.is_empty() {
             self.file_remote = arg.to_string();
         } else {
-            return Err("too many arguments".into());
+            return Err(Error::Config("too many arguments".to_string()));
         }
         Ok(())
     }
 }
 
+/// Tells whether the local file `path` stands for the standard input or output.
+pub(crate) fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO
+}
+
+/// Decodes the `%XX` escapes of a URL component.
+fn percent_decode(component: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(component.len());
+    let mut iter = component.bytes();
+
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
 pub fn convert_file_path_abs(filename: &str) -> PathBuf {
     let normalized_filename = if MAIN_SEPARATOR == '\\' {
         filename.replace('/', "\\")
@@ -241,6 +498,7 @@ pub fn convert_file_path_abs(filename: &str) -> PathBuf {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::Ipv6Addr;
 
     #[test]
     fn parses_full_config() {
@@ -297,6 +555,69 @@ mod tests {
         assert_eq!(config.mode, Mode::Download);
     }
 
+    #[test]
+    fn parses_transfer_mode() {
+        let config = ClientConfig::new(
+            ["test.file", "--mode", "NetASCII"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .unwrap();
+        assert_eq!(config.transfer_mode, TransferMode::Netascii);
+
+        let config = ClientConfig::new(["test.file"].iter().map(|s| s.to_string())).unwrap();
+        assert_eq!(config.transfer_mode, TransferMode::Octet);
+
+        assert!(ClientConfig::new(
+            ["test.file", "--mode", "mail"]
+                .iter()
+                .map(|s| s.to_string())
+        )
+        .is_err());
+        assert!(
+            ClientConfig::new(["test.file", "--mode", "foo"].iter().map(|s| s.to_string()))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parses_config_file() {
+        const DIR_NAME: &str = "target/test/parses_client_config_file";
+        let _ = std::fs::create_dir_all(DIR_NAME);
+        let file = format!("{DIR_NAME}/tftpc.ini");
+        std::fs::write(
+            &file,
+            "[client]\nport = 6969\nblocksize = 1024\nupload = true\nfile = local.file\nremote-file = remote.file\n",
+        )
+        .unwrap();
+
+        let config = ClientConfig::new(
+            ["--config", &file, "-b", "2048", "test.file"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(config.port, 6969);
+        assert_eq!(config.opt_common.block_size, 2048);
+        assert_eq!(config.mode, Mode::Upload);
+        assert_eq!(config.file_path, PathBuf::from("test.file"));
+        assert_eq!(config.file_remote, "remote.file");
+
+        std::fs::write(&file, "blocksize = big\n").unwrap();
+        let err = ClientConfig::new(
+            ["--config", &file, "test.file"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with(&format!("{file}:1: blocksize: ")));
+
+        std::fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+
     #[test]
     fn parses_file_paths() {
         let config = ClientConfig::new(["test/test.file"].iter().map(|s| s.to_string())).unwrap();
@@ -318,6 +639,99 @@ mod tests {
         assert_eq!(config.file_path, path);
     }
 
+    #[test]
+    fn parses_stdio_paths() {
+        let config =
+            ClientConfig::new(["image.bin", "-", "-d"].iter().map(|s| s.to_string())).unwrap();
+
+        assert_eq!(config.file_path, PathBuf::from("-"));
+        assert_eq!(config.file_remote, "image.bin");
+
+        let config =
+            ClientConfig::new(["-u", "-", "remote.bin"].iter().map(|s| s.to_string())).unwrap();
+
+        assert_eq!(config.file_path, PathBuf::from("-"));
+        assert_eq!(config.file_remote, "remote.bin");
+
+        let config = ClientConfig::new(["-u", "-"].iter().map(|s| s.to_string()));
+        assert!(matches!(config, Err(Error::Config(_))));
+
+        let config = ClientConfig::new(["a", "b", "-"].iter().map(|s| s.to_string()));
+        assert!(matches!(config, Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn parses_urls() {
+        let config = ClientConfig::new(
+            [
+                "tftp://bootserver.lab:1069/images/boot%20file.bin;mode=netascii",
+                "-d",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(config.remote_host, Some("bootserver.lab".to_string()));
+        assert_eq!(config.port, 1069);
+        assert_eq!(config.file_remote, "images/boot file.bin");
+        assert_eq!(config.file_path, PathBuf::from("boot file.bin"));
+        assert_eq!(config.transfer_mode, TransferMode::Netascii);
+
+        let config = ClientConfig::new(
+            ["local.file", "tftp://[::1]/remote.file", "-u"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(config.remote_host, None);
+        assert_eq!(config.remote_ip_address, Ipv6Addr::LOCALHOST);
+        assert_eq!(config.port, 69);
+        assert_eq!(config.file_remote, "remote.file");
+        assert_eq!(config.file_path, PathBuf::from("local.file"));
+    }
+
+    #[test]
+    fn rejects_invalid_urls() {
+        for url in [
+            "tftp://host",
+            "tftp://host/",
+            "tftp://host:port/file",
+            "tftp://host/file;mode=mail",
+            "tftp://host/file;blksize=1024",
+            "tftp://host/file%2",
+        ] {
+            let config = ClientConfig::new([url].iter().map(|s| s.to_string()));
+            assert!(matches!(config, Err(Error::Config(_))), "{url}");
+        }
+    }
+
+    #[test]
+    fn resolves_host_names() {
+        let config = ClientConfig::new(
+            ["test.file", "-i", "localhost", "-4", "-p", "1069"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(config.remote_host, Some("localhost".to_string()));
+        assert_eq!(config.ip_preference, IpPreference::Ipv4);
+        let addresses = config.remote_addresses().unwrap();
+        assert!(addresses[0].is_ipv4());
+        assert!(addresses.iter().all(|address| address.port() == 1069));
+
+        let config = ClientConfig::new(
+            ["test.file", "-i", "tftpd.invalid"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .unwrap();
+
+        assert!(matches!(config.remote_addresses(), Err(Error::Config(_))));
+    }
+
     #[test]
     fn converts_file_path_abs() {
         let path = convert_file_path_abs("test.file");
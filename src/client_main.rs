@@ -1,10 +1,16 @@
-use std::{env, error::Error, net::SocketAddr, process, process::ExitCode};
-use tftpd::{log_err, log_info, Client, ClientConfig, Mode};
+use std::{env, net::SocketAddr, process, process::ExitCode};
+use tftpd::{log_err, log_info, log_warn, Client, ClientConfig, Error, Mode, TransferReport};
 
 fn main() -> ExitCode {
     match client(env::args()) {
-        Ok(true) => ExitCode::SUCCESS,
-        Ok(false) => ExitCode::FAILURE,
+        Ok(report) => {
+            log_warn!("{report}");
+            if report.is_success() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
         Err(err) => {
             log_err!("{err}");
             ExitCode::FAILURE
@@ -12,7 +18,7 @@ fn main() -> ExitCode {
     }
 }
 
-fn client<T: Iterator<Item = String>>(args: T) -> Result<bool, Box<dyn Error>> {
+fn client<T: Iterator<Item = String>>(args: T) -> Result<TransferReport, Error> {
     // Parse arguments, skipping first one (exec name)
     let config = ClientConfig::new(args.skip(1)).unwrap_or_else(|err| {
         log_err!("Problem parsing arguments: {err}");
@@ -28,13 +34,13 @@ fn client<T: Iterator<Item = String>>(args: T) -> Result<bool, Box<dyn Error>> {
         log_info!(
             "Starting TFTP Client, uploading {} to {}",
             config.file_path.display(),
-            SocketAddr::new(config.remote_ip_address, config.port),
+            server_name(&config),
         );
     } else {
         log_info!(
             "Starting TFTP Client, downloading {} from {}",
             config.file_path.display(),
-            SocketAddr::new(config.remote_ip_address, config.port),
+            server_name(&config),
         );
     }
 
@@ -43,3 +49,10 @@ fn client<T: Iterator<Item = String>>(args: T) -> Result<bool, Box<dyn Error>> {
 
     client.run()
 }
+
+fn server_name(config: &ClientConfig) -> String {
+    match &config.remote_host {
+        Some(host) => format!("{host}:{}", config.port),
+        None => SocketAddr::new(config.remote_ip_address, config.port).to_string(),
+    }
+}
@@ -1,14 +1,21 @@
-use std::error::Error;
 use std::net::{IpAddr, Ipv4Addr};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{env, process};
 
+use crate::config_file::{apply_config_file, take_config_file};
+use crate::limits::parse_size;
 use crate::log::*;
 use crate::options::{OptionsPrivate, Rollover};
+use crate::server::convert_file_path;
+use crate::{AccessList, ClientRoots, Error, IpNetwork, RemapRules, SymlinkPolicy, UploadLimits};
 
 #[cfg(feature = "debug_drop")]
 use crate::drop::drop_set;
 
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_DIR_MODE: u32 = 0o755;
+
 /// Configuration `struct` used for parsing TFTP options from user
 /// input.
 ///
@@ -24,6 +31,7 @@ use crate::drop::drop_set;
 ///
 /// let config = Config::new(env::args()).unwrap();
 /// ```
+#[derive(Debug)]
 pub struct Config {
     /// Local IP address of the TFTP Server. (default: 127.0.0.1)
     pub ip_address: IpAddr,
@@ -35,12 +43,39 @@ pub struct Config {
     pub receive_directory: PathBuf,
     /// Download directory of the TFTP Server. (default: directory)
     pub send_directory: PathBuf,
+    /// Send and receive directories of the clients of some networks. (default: none)
+    pub client_roots: ClientRoots,
     /// Use a single port for both sending and receiving. (default: false)
     pub single_port: bool,
+    /// Drive all transfers from the listening thread through the server port,
+    /// instead of a thread and a port for each transfer. (default: false)
+    pub event_loop: bool,
     /// Refuse all write requests, making the server read-only. (default: false)
     pub read_only: bool,
     /// Overwrite existing files. (default: false)
     pub overwrite: bool,
+    /// Create the missing directories of received files. (default: false)
+    pub create_dirs: bool,
+    /// Permissions of the directories created for received files, on Unix. (default: 0o755)
+    pub dir_mode: u32,
+    /// Handling of the symbolic links in the requested paths. (default: follow within root)
+    pub symlink_policy: SymlinkPolicy,
+    /// Rules remapping the requested filenames. (default: no rules)
+    pub remap_rules: RemapRules,
+    /// Clients allowed to make read requests. (default: all clients)
+    pub read_acl: AccessList,
+    /// Clients allowed to make write requests. (default: all clients)
+    pub write_acl: AccessList,
+    /// Maximum sizes of the received files. (default: unlimited)
+    pub upload_limits: UploadLimits,
+    /// Maximum amount of concurrent transfers. (default: unlimited)
+    pub max_transfers: Option<usize>,
+    /// Maximum amount of concurrent transfers of a client IP address. (default: unlimited)
+    pub max_client_transfers: Option<usize>,
+    /// Time requests over the limits wait for a transfer to finish before being refused. (default: 0s)
+    pub queue_timeout: Duration,
+    /// Time given to the running transfers to finish when shutting down. (default: 30s)
+    pub drain_timeout: Duration,
     /// Local options for server
     pub opt_local: OptionsPrivate,
 }
@@ -53,9 +88,22 @@ impl Default for Config {
             directory: env::current_dir().unwrap_or_else(|_| env::temp_dir()),
             receive_directory: Default::default(),
             send_directory: Default::default(),
+            client_roots: Default::default(),
             single_port: Default::default(),
+            event_loop: Default::default(),
             read_only: Default::default(),
             overwrite: Default::default(),
+            create_dirs: Default::default(),
+            dir_mode: DEFAULT_DIR_MODE,
+            symlink_policy: Default::default(),
+            remap_rules: Default::default(),
+            read_acl: Default::default(),
+            write_acl: Default::default(),
+            upload_limits: Default::default(),
+            max_transfers: Default::default(),
+            max_client_transfers: Default::default(),
+            queue_timeout: Default::default(),
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
             opt_local: Default::default(),
         }
     }
@@ -65,17 +113,23 @@ pub fn parse_local_args<T: Iterator<Item = String>>(
     arg: &str,
     args: &mut T,
     opt_local: &mut OptionsPrivate,
-) -> Result<bool, Box<dyn Error>> {
+) -> Result<bool, Error> {
     match arg {
         "--duplicate-packets" => {
             if let Some(duplicate_packets_str) = args.next() {
-                let duplicate_packets = duplicate_packets_str.parse::<u8>()?;
+                let duplicate_packets =
+                    duplicate_packets_str.parse::<u8>().map_err(Error::config)?;
                 if duplicate_packets == u8::MAX {
-                    return Err(format!("Duplicate packets should be less than {}", u8::MAX).into());
+                    return Err(Error::Config(format!(
+                        "Duplicate packets should be less than {}",
+                        u8::MAX
+                    )));
                 }
                 opt_local.repeat_count = duplicate_packets + 1;
             } else {
-                return Err("Missing duplicate packets after flag".into());
+                return Err(Error::Config(
+                    "Missing duplicate packets after flag".to_string(),
+                ));
             }
         }
         "--keep-on-error" => {
@@ -83,9 +137,9 @@ pub fn parse_local_args<T: Iterator<Item = String>>(
         }
         "-m" | "--maxretries" => {
             if let Some(retries_str) = args.next() {
-                opt_local.max_retries = retries_str.parse::<usize>()?;
+                opt_local.max_retries = retries_str.parse::<usize>().map_err(Error::config)?;
             } else {
-                return Err("Missing max retries after flag".into());
+                return Err(Error::Config("Missing max retries after flag".to_string()));
             }
         }
         "-R" | "--rollover" => {
@@ -95,10 +149,16 @@ pub fn parse_local_args<T: Iterator<Item = String>>(
                     "0" => Rollover::Enforce0,
                     "1" => Rollover::Enforce1,
                     "x" => Rollover::DontCare,
-                    _ => return Err("Invalid rollover policy value: use n, 0, 1, x".into()),
+                    _ => {
+                        return Err(Error::Config(
+                            "Invalid rollover policy value: use n, 0, 1, x".to_string(),
+                        ))
+                    }
                 }
             } else {
-                return Err("Rollover policy value missing: use n, 0, 1, x".into());
+                return Err(Error::Config(
+                    "Rollover policy value missing: use n, 0, 1, x".to_string(),
+                ));
             }
         }
         _ => return Ok(false),
@@ -112,7 +172,7 @@ pub fn print_opt_local_help() {
     println!(
         "  --duplicate-packets <NUM>\t\tDuplicate all packets sent from the server (default: 0)"
     );
-    println!("  --keep-on-error\t\t\tPrevent daemon from deleting files after receiving errors");
+    println!("  --keep-on-error\t\t\tPrevent daemon from discarding data of custom file sinks after receiving errors");
 }
 
 fn print_version_exit() {
@@ -127,118 +187,309 @@ fn print_version_exit() {
 impl Config {
     /// Creates a new configuration by parsing the supplied arguments. It is
     /// intended for use with [`env::args()`].
-    pub fn new<T: Iterator<Item = String>>(mut args: T) -> Result<Config, Box<dyn Error>> {
+    ///
+    /// The `--config <FILE>` flag loads a TOML or INI configuration file,
+    /// whose keys are the long flags without the leading dashes. Flags given
+    /// as arguments override the values of the file.
+    pub fn new<T: Iterator<Item = String>>(args: T) -> Result<Config, Error> {
         let mut config = Config::default();
         let mut verbosity: isize = 1;
 
         // Skip arg 0 (executable name)
-        args.next();
+        let mut args: Vec<String> = args.skip(1).collect();
+
+        if let Some(file) = take_config_file(&mut args)? {
+            apply_config_file(Path::new(&file), |arg, args| {
+                config.parse_arg(arg, args, &mut verbosity)
+            })?;
+        }
 
+        let mut args = args.into_iter();
         while let Some(arg) = args.next() {
-            match arg.as_str() {
-                "-i" | "--ip-address" => {
-                    if let Some(ip_str) = args.next() {
-                        let ip_addr: IpAddr = ip_str.parse()?;
-                        config.ip_address = ip_addr;
-                    } else {
-                        return Err("Missing ip address after flag".into());
-                    }
+            if !config.parse_arg(&arg, &mut args, &mut verbosity)? {
+                return Err(Error::Config(format!("Invalid flag: {arg}")));
+            }
+        }
+
+        if config.receive_directory.as_os_str().is_empty() {
+            config.receive_directory.clone_from(&config.directory);
+        }
+        if config.send_directory.as_os_str().is_empty() {
+            config.send_directory.clone_from(&config.directory);
+        }
+
+        verbosity_set(verbosity);
+
+        Ok(config)
+    }
+
+    fn parse_arg<T: Iterator<Item = String>>(
+        &mut self,
+        arg: &str,
+        args: &mut T,
+        verbosity: &mut isize,
+    ) -> Result<bool, Error> {
+        match arg {
+            "-i" | "--ip-address" => {
+                if let Some(ip_str) = args.next() {
+                    let ip_addr: IpAddr = ip_str.parse().map_err(Error::config)?;
+                    self.ip_address = ip_addr;
+                } else {
+                    return Err(Error::Config("Missing ip address after flag".to_string()));
                 }
-                "-p" | "--port" => {
-                    if let Some(port_str) = args.next() {
-                        config.port = port_str.parse::<u16>()?;
-                    } else {
-                        return Err("Missing port number after flag".into());
-                    }
+            }
+            "-p" | "--port" => {
+                if let Some(port_str) = args.next() {
+                    self.port = port_str.parse::<u16>().map_err(Error::config)?;
+                } else {
+                    return Err(Error::Config("Missing port number after flag".to_string()));
                 }
-                "-d" | "--directory" => {
-                    if let Some(dir_str) = args.next() {
-                        if !Path::new(&dir_str).exists() {
-                            return Err(format!("{dir_str} does not exist").into());
-                        }
-                        config.directory = dir_str.into();
-                    } else {
-                        return Err("Missing directory after flag".into());
+            }
+            "-d" | "--directory" => {
+                if let Some(dir_str) = args.next() {
+                    if !Path::new(&dir_str).exists() {
+                        return Err(Error::Config(format!("{dir_str} does not exist")));
                     }
+                    self.directory = dir_str.into();
+                } else {
+                    return Err(Error::Config("Missing directory after flag".to_string()));
                 }
-                "-rd" | "--receive-directory" => {
-                    if let Some(dir_str) = args.next() {
-                        if !Path::new(&dir_str).exists() {
-                            return Err(format!("{dir_str} does not exist").into());
-                        }
-                        config.receive_directory = dir_str.into();
-                    } else {
-                        return Err("Missing receive directory after flag".into());
+            }
+            "-rd" | "--receive-directory" => {
+                if let Some(dir_str) = args.next() {
+                    if !Path::new(&dir_str).exists() {
+                        return Err(Error::Config(format!("{dir_str} does not exist")));
                     }
+                    self.receive_directory = dir_str.into();
+                } else {
+                    return Err(Error::Config(
+                        "Missing receive directory after flag".to_string(),
+                    ));
                 }
-                "-sd" | "--send-directory" => {
-                    if let Some(dir_str) = args.next() {
-                        if !Path::new(&dir_str).exists() {
-                            return Err(format!("{dir_str} does not exist").into());
-                        }
-                        config.send_directory = dir_str.into();
-                    } else {
-                        return Err("Missing send directory after flag".into());
+            }
+            "-sd" | "--send-directory" => {
+                if let Some(dir_str) = args.next() {
+                    if !Path::new(&dir_str).exists() {
+                        return Err(Error::Config(format!("{dir_str} does not exist")));
                     }
+                    self.send_directory = dir_str.into();
+                } else {
+                    return Err(Error::Config(
+                        "Missing send directory after flag".to_string(),
+                    ));
                 }
-                "-s" | "--single-port" => {
-                    config.single_port = true;
+            }
+            "--client-directory" | "--client-send-directory" | "--client-receive-directory" => {
+                let Some((network_str, dir_str)) = args.next().and_then(|root| {
+                    root.split_once('=')
+                        .map(|(network, dir)| (network.to_string(), dir.to_string()))
+                }) else {
+                    return Err(Error::Config(format!(
+                        "Missing <IP[/PREFIX]>=<DIRECTORY> after {arg}"
+                    )));
+                };
+                let network = network_str.parse::<IpNetwork>().map_err(|err| {
+                    Error::Config(format!("Invalid network {network_str}: {err}"))
+                })?;
+                if !Path::new(&dir_str).exists() {
+                    return Err(Error::Config(format!("{dir_str} does not exist")));
                 }
-                "-r" | "--read-only" => {
-                    config.read_only = true;
+                if arg != "--client-receive-directory" {
+                    self.client_roots
+                        .add_send_directory(network, dir_str.clone().into());
                 }
-                "-h" | "--help" => {
-                    println!("TFTP Server Daemon\n");
-                    println!("Usage: tftpd [OPTIONS]\n");
-                    println!("Options:");
-                    println!("  -i, --ip-address <IP ADDRESS>\t\tSet the ip address of the server (default: 127.0.0.1)");
-                    println!("  -p, --port <PORT>\t\t\tSet the listening port of the server (default: 69)");
-                    println!("  -d, --directory <DIRECTORY>\t\tSet the serving directory (default: current working directory)");
-                    println!("  -rd, --receive-directory <DIRECTORY>\tSet the directory to receive files to (default: the directory setting)");
-                    println!("  -sd, --send-directory <DIRECTORY>\tSet the directory to send files from (default: the directory setting)");
-                    println!("  -s, --single-port\t\t\tUse a single port for both sending and receiving (default: false)");
-                    println!("  -r, --read-only\t\t\tRefuse all write requests, making the server read-only (default: false)");
-                    println!("  --overwrite\t\t\t\tOverwrite existing files (default: false)");
-                    print_opt_local_help();
-                    println!(
-                        "  -v, --verbose\t\t\t\tIncrease log verbosity (can be repeated, e.g. -vv)"
-                    );
-                    println!("  -q, --quiet\t\t\t\tDecrease log verbosity (can be repeated)");
-                    println!("  -h, --help\t\t\t\tPrint help information");
-                    println!("  -V, --version\t\t\t\tprint version");
-                    process::exit(0);
+                if arg != "--client-send-directory" {
+                    self.client_roots
+                        .add_receive_directory(network, dir_str.into());
                 }
-                "--overwrite" => {
-                    config.overwrite = true;
+            }
+            "-s" | "--single-port" => {
+                self.single_port = true;
+            }
+            "--event-loop" => {
+                self.event_loop = true;
+            }
+            "-r" | "--read-only" => {
+                self.read_only = true;
+            }
+            "--map-file" => {
+                if let Some(file_str) = args.next() {
+                    self.remap_rules = RemapRules::from_file(Path::new(&file_str))?;
+                } else {
+                    return Err(Error::Config("Missing remap file after flag".to_string()));
                 }
-                "-q" | "--quiet" => verbosity -= 1,
-                "-v" | "--verbose" => verbosity += 1,
-                "-V" | "--version" => print_version_exit(),
-                #[cfg(feature = "debug_drop")]
-                "-D" => drop_set(args.next())?,
-                arg => {
-                    if !parse_local_args(arg, &mut args, &mut config.opt_local)? {
-                        return Err(format!("Invalid flag: {arg}").into());
-                    }
+            }
+            "--max-transfers" => {
+                if let Some(max_str) = args.next() {
+                    // Zero means no limit
+                    self.max_transfers = Some(max_str.parse::<usize>().map_err(Error::config)?)
+                        .filter(|max| *max > 0);
+                } else {
+                    return Err(Error::Config(
+                        "Missing max transfers after flag".to_string(),
+                    ));
                 }
             }
+            "--max-client-transfers" => {
+                if let Some(max_str) = args.next() {
+                    self.max_client_transfers =
+                        Some(max_str.parse::<usize>().map_err(Error::config)?)
+                            .filter(|max| *max > 0);
+                } else {
+                    return Err(Error::Config(
+                        "Missing max client transfers after flag".to_string(),
+                    ));
+                }
+            }
+            "--queue-timeout" => {
+                if let Some(timeout_str) = args.next() {
+                    self.queue_timeout = Duration::try_from_secs_f32(
+                        timeout_str.parse::<f32>().map_err(Error::config)?,
+                    )
+                    .map_err(Error::config)?;
+                } else {
+                    return Err(Error::Config(
+                        "Missing queue timeout after flag".to_string(),
+                    ));
+                }
+            }
+            "--drain-timeout" => {
+                if let Some(timeout_str) = args.next() {
+                    self.drain_timeout = Duration::try_from_secs_f32(
+                        timeout_str.parse::<f32>().map_err(Error::config)?,
+                    )
+                    .map_err(Error::config)?;
+                } else {
+                    return Err(Error::Config(
+                        "Missing drain timeout after flag".to_string(),
+                    ));
+                }
+            }
+            "--allow-read" | "--deny-read" | "--allow-write" | "--deny-write" => {
+                let Some(network_str) = args.next() else {
+                    return Err(Error::Config(format!("Missing network after {arg}")));
+                };
+                let network = network_str.parse::<IpNetwork>().map_err(|err| {
+                    Error::Config(format!("Invalid network {network_str}: {err}"))
+                })?;
+                match arg {
+                    "--allow-read" => self.read_acl.allow(network),
+                    "--deny-read" => self.read_acl.deny(network),
+                    "--allow-write" => self.write_acl.allow(network),
+                    _ => self.write_acl.deny(network),
+                }
+            }
+            "--max-upload-size" => {
+                let Some(size_str) = args.next() else {
+                    return Err(Error::Config(
+                        "Missing max upload size after flag".to_string(),
+                    ));
+                };
+                // Zero means no limit
+                let max_size = Some(parse_size(&size_str)?).filter(|max| *max > 0);
+                self.upload_limits.set_max_size(max_size);
+            }
+            "--max-upload-size-in" => {
+                let Some((directory, size_str)) = args.next().and_then(|limit| {
+                    limit
+                        .rsplit_once('=')
+                        .map(|(directory, size)| (directory.to_string(), size.to_string()))
+                }) else {
+                    return Err(Error::Config(
+                        "Missing <DIRECTORY>=<SIZE> after flag".to_string(),
+                    ));
+                };
+                let max_size = Some(parse_size(&size_str)?).filter(|max| *max > 0);
+                self.upload_limits
+                    .limit_directory(convert_file_path(&directory), max_size);
+            }
+            "-h" | "--help" => {
+                println!("TFTP Server Daemon\n");
+                println!("Usage: tftpd [OPTIONS]\n");
+                println!("Options:");
+                println!("  -i, --ip-address <IP ADDRESS>\t\tSet the ip address of the server (default: 127.0.0.1)");
+                println!(
+                    "  -p, --port <PORT>\t\t\tSet the listening port of the server (default: 69)"
+                );
+                println!("  -d, --directory <DIRECTORY>\t\tSet the serving directory (default: current working directory)");
+                println!("  -rd, --receive-directory <DIRECTORY>\tSet the directory to receive files to (default: the directory setting)");
+                println!("  -sd, --send-directory <DIRECTORY>\tSet the directory to send files from (default: the directory setting)");
+                println!("  --client-directory <IP[/PREFIX]>=<DIR>\tServe the clients of the network from the directory (can be repeated)");
+                println!("  --client-receive-directory <IP[/PREFIX]>=<DIR>\tReceive files from the clients of the network to the directory (can be repeated)");
+                println!("  --client-send-directory <IP[/PREFIX]>=<DIR>\tSend files to the clients of the network from the directory (can be repeated)");
+                println!("  -s, --single-port\t\t\tUse a single port for both sending and receiving (default: false)");
+                println!("  --event-loop\t\t\t\tDrive all transfers from a single thread through the server port (default: false)");
+                println!("  -r, --read-only\t\t\tRefuse all write requests, making the server read-only (default: false)");
+                println!("  --overwrite\t\t\t\tOverwrite existing files (default: false)");
+                println!("  --create-dirs\t\t\t\tCreate the missing directories of received files (default: false)");
+                println!("  --symlinks <POLICY>\t\t\tSymbolic links to follow: all, within-root, none (default: within-root)");
+                println!("  --dir-mode <MODE>\t\t\tOctal permissions of the created directories (default: 755)");
+                println!("  --map-file <FILE>\t\t\tRemap requested filenames using the rules in the file, like tftp-hpa");
+                println!("  --allow-read <IP[/PREFIX]>\t\tOnly allow read requests from the network (can be repeated)");
+                println!("  --deny-read <IP[/PREFIX]>\t\tRefuse read requests from the network (can be repeated)");
+                println!("  --allow-write <IP[/PREFIX]>\t\tOnly allow write requests from the network (can be repeated)");
+                println!("  --deny-write <IP[/PREFIX]>\t\tRefuse write requests from the network (can be repeated)");
+                println!("  --max-upload-size <SIZE>\t\tMaximum size of received files, with K, M or G suffix, 0 for no limit (default: 0)");
+                println!("  --max-upload-size-in <DIR>=<SIZE>\tMaximum size of received files in a directory of the receive directory (can be repeated)");
+                print_opt_local_help();
+                println!(
+                    "  -v, --verbose\t\t\t\tIncrease log verbosity (can be repeated, e.g. -vv)"
+                );
+                println!("  -q, --quiet\t\t\t\tDecrease log verbosity (can be repeated)");
+                println!("  --max-transfers <NUM>\t\t\tMaximum concurrent transfers, 0 for no limit (default: 0)");
+                println!("  --max-client-transfers <NUM>\t\tMaximum concurrent transfers per client IP, 0 for no limit (default: 0)");
+                println!("  --queue-timeout <SECONDS>\t\tTime requests over the limits wait before being refused (default: 0, can be float)");
+                println!("  --drain-timeout <SECONDS>\t\tTime given to running transfers on SIGTERM (default: 30, can be float)");
+                println!("  --config <FILE>\t\t\tLoad options from a TOML or INI file, overridden by flags");
+                println!("  -h, --help\t\t\t\tPrint help information");
+                println!("  -V, --version\t\t\t\tprint version");
+                process::exit(0);
+            }
+            "--overwrite" => {
+                self.overwrite = true;
+            }
+            "--create-dirs" => {
+                self.create_dirs = true;
+            }
+            "--symlinks" => {
+                self.symlink_policy = match args.next().as_deref() {
+                    Some("all") => SymlinkPolicy::Follow,
+                    Some("within-root") => SymlinkPolicy::FollowWithinRoot,
+                    Some("none") => SymlinkPolicy::Refuse,
+                    _ => {
+                        return Err(Error::Config(
+                            "Missing or invalid symlink policy: use all, within-root, none"
+                                .to_string(),
+                        ))
+                    }
+                };
+            }
+            "--dir-mode" => {
+                let Some(mode) = args
+                    .next()
+                    .and_then(|mode_str| u32::from_str_radix(&mode_str, 8).ok())
+                    .filter(|mode| *mode <= 0o7777)
+                else {
+                    return Err(Error::Config(
+                        "Missing or invalid octal directory mode after flag".to_string(),
+                    ));
+                };
+                self.dir_mode = mode;
+            }
+            "-q" | "--quiet" => *verbosity -= 1,
+            "-v" | "--verbose" => *verbosity += 1,
+            "-V" | "--version" => print_version_exit(),
+            #[cfg(feature = "debug_drop")]
+            "-D" => drop_set(args.next())?,
+            arg => return parse_local_args(arg, args, &mut self.opt_local),
         }
-
-        if config.receive_directory.as_os_str().is_empty() {
-            config.receive_directory.clone_from(&config.directory);
-        }
-        if config.send_directory.as_os_str().is_empty() {
-            config.send_directory.clone_from(&config.directory);
-        }
-
-        verbosity_set(verbosity);
-
-        Ok(config)
+        Ok(true)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
     use std::net::Ipv6Addr;
 
     use super::*;
@@ -259,6 +510,7 @@ mod tests {
                 "-sd",
                 "/",
                 "-s",
+                "--event-loop",
                 "-r",
                 "--keep-on-error",
             ]
@@ -274,6 +526,7 @@ mod tests {
         assert_eq!(config.send_directory, PathBuf::from("/"));
         assert!(!config.opt_local.clean_on_error);
         assert!(config.single_port);
+        assert!(config.event_loop);
         assert!(config.read_only);
     }
 
@@ -363,6 +616,259 @@ mod tests {
         .is_err());
     }
 
+    #[test]
+    fn parses_access_lists() {
+        let config = Config::new(
+            [
+                "/",
+                "--allow-read",
+                "10.0.0.0/8",
+                "--deny-read",
+                "10.0.0.1",
+                "--deny-write",
+                "::/0",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        )
+        .unwrap();
+
+        assert!(config.read_acl.is_allowed(&"10.0.0.2".parse().unwrap()));
+        assert!(!config.read_acl.is_allowed(&"10.0.0.1".parse().unwrap()));
+        assert!(!config.read_acl.is_allowed(&"127.0.0.1".parse().unwrap()));
+        assert!(config.write_acl.is_allowed(&"127.0.0.1".parse().unwrap()));
+        assert!(!config.write_acl.is_allowed(&"::1".parse().unwrap()));
+
+        assert!(Config::new(
+            ["/", "--allow-write", "10.0.0.0/40"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parses_config_file() {
+        const DIR_NAME: &str = "target/test/parses_config_file";
+        let _ = fs::create_dir_all(DIR_NAME);
+        let file = format!("{DIR_NAME}/tftpd.toml");
+        fs::write(
+            &file,
+            "port = 6969\nip-address = \"0.0.0.0\"\nsingle-port = true\n\n[options]\nmaxretries = 3\ndeny-write = [\"10.0.0.0/8\"]\n",
+        )
+        .unwrap();
+
+        let config = Config::new(
+            ["/", "--config", &file, "-p", "1234"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(config.port, 1234);
+        assert_eq!(config.ip_address, Ipv4Addr::new(0, 0, 0, 0));
+        assert!(config.single_port);
+        assert_eq!(config.opt_local.max_retries, 3);
+        assert!(!config.write_acl.is_allowed(&"10.1.2.3".parse().unwrap()));
+
+        fs::write(&file, "single-port = true\nport = 70000\n").unwrap();
+        let err = Config::new(["/", "--config", &file].iter().map(|s| s.to_string())).unwrap_err();
+        assert!(err.to_string().starts_with(&format!("{file}:2: port: ")));
+
+        fs::write(&file, "single-port = 1\n").unwrap();
+        let err = Config::new(["/", "--config", &file].iter().map(|s| s.to_string())).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{file}:1: single-port: key does not take a value")
+        );
+
+        fs::write(&file, "# comment\nfoo = bar\n").unwrap();
+        let err = Config::new(["/", "--config", &file].iter().map(|s| s.to_string())).unwrap_err();
+        assert_eq!(err.to_string(), format!("{file}:2: foo: unknown key"));
+
+        for key in ["help", "version"] {
+            fs::write(&file, format!("{key} = true\n")).unwrap();
+            let err =
+                Config::new(["/", "--config", &file].iter().map(|s| s.to_string())).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("{file}:1: {key}: not allowed in a config file")
+            );
+        }
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+
+    #[test]
+    fn parses_transfer_limits() {
+        let config = Config::new(["/"].iter().map(|s| s.to_string())).unwrap();
+        assert_eq!(config.max_transfers, None);
+        assert_eq!(config.max_client_transfers, None);
+        assert!(config.queue_timeout.is_zero());
+
+        let config = Config::new(
+            [
+                "/",
+                "--max-transfers",
+                "100",
+                "--max-client-transfers",
+                "0",
+                "--queue-timeout",
+                "2",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        )
+        .unwrap();
+        assert_eq!(config.max_transfers, Some(100));
+        assert_eq!(config.max_client_transfers, None);
+        assert_eq!(config.queue_timeout, Duration::from_secs(2));
+
+        assert!(
+            Config::new(["/", "--max-transfers", "-1"].iter().map(|s| s.to_string()),).is_err()
+        );
+    }
+
+    #[test]
+    fn parses_drain_timeout() {
+        let config = Config::new(["/"].iter().map(|s| s.to_string())).unwrap();
+        assert_eq!(config.drain_timeout, Duration::from_secs(30));
+
+        let config = Config::new(
+            ["/", "--drain-timeout", "2.5"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .unwrap();
+        assert_eq!(config.drain_timeout, Duration::from_millis(2500));
+
+        assert!(
+            Config::new(["/", "--drain-timeout", "-1"].iter().map(|s| s.to_string()),).is_err()
+        );
+    }
+
+    #[test]
+    fn parses_symlink_policy() {
+        let config = Config::new(["/"].iter().map(|s| s.to_string())).unwrap();
+        assert_eq!(config.symlink_policy, SymlinkPolicy::FollowWithinRoot);
+
+        let config =
+            Config::new(["/", "--symlinks", "none"].iter().map(|s| s.to_string())).unwrap();
+        assert_eq!(config.symlink_policy, SymlinkPolicy::Refuse);
+
+        let config = Config::new(["/", "--symlinks", "all"].iter().map(|s| s.to_string())).unwrap();
+        assert_eq!(config.symlink_policy, SymlinkPolicy::Follow);
+
+        assert!(Config::new(["/", "--symlinks", "some"].iter().map(|s| s.to_string())).is_err());
+    }
+
+    #[test]
+    fn parses_client_roots() {
+        let config = Config::new(
+            [
+                "/",
+                "--client-directory",
+                "10.0.0.0/8=/",
+                "--client-receive-directory",
+                "10.1.0.0/16=.",
+                "--client-send-directory",
+                "fd00::/8=.",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        )
+        .unwrap();
+
+        let roots = &config.client_roots;
+        let client = "10.1.2.3".parse().unwrap();
+        assert_eq!(roots.send_directory(&client), Some(Path::new("/")));
+        assert_eq!(roots.receive_directory(&client), Some(Path::new(".")));
+        let client = "fd00::1".parse().unwrap();
+        assert_eq!(roots.send_directory(&client), Some(Path::new(".")));
+        assert_eq!(roots.receive_directory(&client), None);
+
+        assert!(Config::new(
+            ["/", "--client-directory", "10.0.0.0/8"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .is_err());
+        assert!(Config::new(
+            ["/", "--client-directory", "10.0.0.0/8=/this/does/not/exist"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parses_upload_limits() {
+        let config = Config::new(
+            [
+                "/",
+                "--max-upload-size",
+                "1M",
+                "--max-upload-size-in",
+                "firmware=64M",
+                "--max-upload-size-in",
+                "firmware/logs=0",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        )
+        .unwrap();
+
+        let limits = &config.upload_limits;
+        assert_eq!(limits.max_size(Path::new("file.bin")), Some(1 << 20));
+        assert_eq!(
+            limits.max_size(Path::new("firmware/image.bin")),
+            Some(64 << 20)
+        );
+        assert_eq!(limits.max_size(Path::new("firmware/logs/boot.log")), None);
+
+        assert!(Config::new(
+            ["/", "--max-upload-size-in", "firmware"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .is_err());
+        assert!(Config::new(
+            ["/", "--max-upload-size", "1T"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parses_create_dirs() {
+        let config = Config::new(["/"].iter().map(|s| s.to_string())).unwrap();
+        assert!(!config.create_dirs);
+        assert_eq!(config.dir_mode, 0o755);
+
+        let config = Config::new(
+            ["/", "--create-dirs", "--dir-mode", "0750"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .unwrap();
+        assert!(config.create_dirs);
+        assert_eq!(config.dir_mode, 0o750);
+
+        assert!(Config::new(["/", "--dir-mode", "789"].iter().map(|s| s.to_string())).is_err());
+        assert!(Config::new(["/", "--dir-mode", "17777"].iter().map(|s| s.to_string())).is_err());
+    }
+
+    #[test]
+    fn returns_error_on_invalid_map_file() {
+        assert!(Config::new(
+            ["/", "--map-file", "/this/does/not/exist"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .is_err());
+    }
+
     #[test]
     fn returns_error_on_invalid_duplicate_packets() {
         assert!(Config::new(
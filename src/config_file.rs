@@ -0,0 +1,332 @@
+use std::fs;
+use std::path::Path;
+use std::vec;
+
+use crate::Error;
+
+// Characters starting a comment, in TOML and INI files
+const COMMENT_CHARS: [char; 2] = ['#', ';'];
+
+/// Entry of a configuration file, holding the values of a key.
+#[derive(Debug, PartialEq)]
+pub struct ConfigEntry {
+    /// Name of the key, with underscores replaced by dashes
+    pub key: String,
+    /// Values of the key, empty for a `true` flag
+    pub values: Vec<String>,
+    /// Line of the key in the file
+    pub line: usize,
+}
+
+/// Reads a configuration file, in either TOML or INI format. Only the
+/// subset of both formats needed for flags is supported: `key = value`
+/// pairs with strings, numbers, booleans and single-line arrays as values,
+/// `#` and `;` comments, and section headers which are only used for grouping.
+///
+/// Keys are the long command-line flags without the leading dashes. A
+/// `false` boolean value omits the flag, and an array repeats it.
+pub fn read_config_file(path: &Path) -> Result<Vec<ConfigEntry>, Error> {
+    let contents = fs::read_to_string(path).map_err(|err| {
+        Error::Config(format!(
+            "Could not read config file {}: {err}",
+            path.display()
+        ))
+    })?;
+
+    parse_config(&contents).map_err(|err| Error::Config(format!("{}:{err}", path.display())))
+}
+
+/// Applies the entries of the configuration file at `path` with
+/// `parse_arg`, which returns `false` for unknown flags. Errors cite the
+/// file, line and key of the offending entry. The `help` and `version` keys
+/// are refused, as these flags exit the process.
+pub fn apply_config_file<F>(path: &Path, mut parse_arg: F) -> Result<(), Error>
+where
+    F: FnMut(&str, &mut vec::IntoIter<String>) -> Result<bool, Error>,
+{
+    for entry in read_config_file(path)? {
+        let flag = format!("--{}", entry.key);
+        let error = |err: String| {
+            Error::Config(format!(
+                "{}:{}: {}: {err}",
+                path.display(),
+                entry.line,
+                entry.key
+            ))
+        };
+        if matches!(entry.key.as_str(), "help" | "version") {
+            return Err(error("not allowed in a config file".to_string()));
+        }
+
+        // A flag with multiple values is repeated once for each value
+        let runs = if entry.values.is_empty() {
+            vec![vec![]]
+        } else {
+            entry.values.into_iter().map(|value| vec![value]).collect()
+        };
+        for values in runs {
+            let mut args = values.into_iter();
+            if !parse_arg(&flag, &mut args).map_err(|err| error(err.to_string()))? {
+                return Err(error("unknown key".to_string()));
+            }
+            if args.next().is_some() {
+                return Err(error("key does not take a value".to_string()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the `--config <FILE>` flag from `args`, and returns the file.
+pub fn take_config_file(args: &mut Vec<String>) -> Result<Option<String>, Error> {
+    let Some(index) = args.iter().position(|arg| arg == "--config") else {
+        return Ok(None);
+    };
+    if index + 1 >= args.len() {
+        return Err(Error::Config("Missing config file after flag".to_string()));
+    }
+
+    let file = args.remove(index + 1);
+    args.remove(index);
+    Ok(Some(file))
+}
+
+fn parse_config(contents: &str) -> Result<Vec<ConfigEntry>, String> {
+    let mut entries = vec![];
+
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |err: &str| format!("{line_number}: {err}");
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            if !strip_comment(line).ends_with(']') {
+                return Err(error("unterminated section header"));
+            }
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(error("expected key = value"));
+        };
+        let key = key.trim().trim_matches('"');
+        if key.is_empty() {
+            return Err(error("missing key"));
+        }
+        let key = key.replace('_', "-");
+
+        let values = match parse_value(value.trim()).map_err(|err| format!("{key}: {err}")) {
+            Ok(Some(values)) => values,
+            Ok(None) => continue,
+            Err(err) => return Err(error(&err)),
+        };
+
+        entries.push(ConfigEntry {
+            key,
+            values,
+            line: line_number,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Parses a value, returning [`None`] for a `false` boolean.
+fn parse_value(value: &str) -> Result<Option<Vec<String>>, String> {
+    if let Some(array) = value.strip_prefix('[') {
+        let mut values = vec![];
+        let mut rest = array.trim_start();
+
+        loop {
+            if let Some(after) = rest.strip_prefix(']') {
+                check_end(after)?;
+                return Ok(Some(values));
+            }
+
+            let (value, after) = parse_scalar(rest, true)?;
+            values.push(value);
+
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after.trim_start();
+            } else if !rest.starts_with(']') {
+                return Err("expected ',' or ']' in array".to_string());
+            }
+        }
+    }
+
+    let (value, rest) = parse_scalar(value, false)?;
+    check_end(rest)?;
+
+    match value.as_str() {
+        "true" => Ok(Some(vec![])),
+        "false" => Ok(None),
+        _ => Ok(Some(vec![value])),
+    }
+}
+
+/// Parses a quoted or bare value, returning it and the remaining input.
+fn parse_scalar(input: &str, in_array: bool) -> Result<(String, &str), String> {
+    let mut chars = input.char_indices();
+
+    match chars.next() {
+        Some((_, '"')) => {
+            let mut value = String::new();
+            while let Some((index, c)) = chars.next() {
+                match c {
+                    '"' => return Ok((value, &input[index + 1..])),
+                    '\\' => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, 't')) => value.push('\t'),
+                        Some((_, '\\')) => value.push('\\'),
+                        Some((_, '"')) => value.push('"'),
+                        Some((_, c)) => return Err(format!("invalid escape sequence '\\{c}'")),
+                        None => break,
+                    },
+                    c => value.push(c),
+                }
+            }
+            Err("unterminated string".to_string())
+        }
+        Some((_, '\'')) => match input[1..].find('\'') {
+            Some(end) => Ok((input[1..end + 1].to_string(), &input[end + 2..])),
+            None => Err("unterminated string".to_string()),
+        },
+        Some(_) => {
+            let end = input
+                .find(|c| COMMENT_CHARS.contains(&c) || (in_array && (c == ',' || c == ']')))
+                .unwrap_or(input.len());
+            let value = input[..end].trim_end();
+            if value.is_empty() {
+                return Err("missing value".to_string());
+            }
+            Ok((value.to_string(), &input[end..]))
+        }
+        None => Err("missing value".to_string()),
+    }
+}
+
+fn check_end(rest: &str) -> Result<(), String> {
+    if strip_comment(rest).is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "unexpected characters after value: {}",
+            rest.trim()
+        ))
+    }
+}
+
+fn strip_comment(input: &str) -> &str {
+    input.split(COMMENT_CHARS).next().unwrap_or_default().trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, values: &[&str], line: usize) -> ConfigEntry {
+        ConfigEntry {
+            key: key.to_string(),
+            values: values.iter().map(|s| s.to_string()).collect(),
+            line,
+        }
+    }
+
+    #[test]
+    fn parses_toml() {
+        let entries = parse_config(
+            r#"
+# server settings
+ip-address = "0.0.0.0"
+port = 6969 # comment
+single_port = true
+read-only = false
+
+[acl]
+deny-read = ["10.0.0.0/8", '::1' ,]
+map-file = "C:\\tftp \"maps\""
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                entry("ip-address", &["0.0.0.0"], 3),
+                entry("port", &["6969"], 4),
+                entry("single-port", &[], 5),
+                entry("deny-read", &["10.0.0.0/8", "::1"], 9),
+                entry("map-file", &["C:\\tftp \"maps\""], 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_ini() {
+        let entries = parse_config(
+            "; server settings\n[server]\ndirectory = /srv/tftp\nrollover = x\ntimeout = 2.5\nport = 69 ; tftp\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                entry("directory", &["/srv/tftp"], 3),
+                entry("rollover", &["x"], 4),
+                entry("timeout", &["2.5"], 5),
+                entry("port", &["69"], 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_line_of_errors() {
+        assert_eq!(
+            parse_config("port = 69\nip-address").unwrap_err(),
+            "2: expected key = value"
+        );
+        assert_eq!(
+            parse_config("\n\ndirectory = \"/srv").unwrap_err(),
+            "3: directory: unterminated string"
+        );
+        assert_eq!(
+            parse_config("port = \"69\" 70").unwrap_err(),
+            "1: port: unexpected characters after value: 70"
+        );
+        assert_eq!(
+            parse_config("deny-read = [\"a\" \"b\"]").unwrap_err(),
+            "1: deny-read: expected ',' or ']' in array"
+        );
+        assert_eq!(
+            parse_config("port =").unwrap_err(),
+            "1: port: missing value"
+        );
+        assert_eq!(
+            parse_config("[server").unwrap_err(),
+            "1: unterminated section header"
+        );
+    }
+
+    #[test]
+    fn takes_config_file() {
+        let mut args = vec![
+            "-p".to_string(),
+            "--config".to_string(),
+            "a.toml".to_string(),
+        ];
+        assert_eq!(
+            take_config_file(&mut args).unwrap(),
+            Some("a.toml".to_string())
+        );
+        assert_eq!(args, vec!["-p".to_string()]);
+
+        assert_eq!(take_config_file(&mut args).unwrap(), None);
+        assert!(take_config_file(&mut vec!["--config".to_string()]).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::error::Error;
+use crate::Error;
 
 /// Allows conversions between byte arrays and other types.
 ///
@@ -17,9 +17,11 @@ pub struct Convert;
 
 impl Convert {
     /// Converts a [`u8`] slice to a [`u16`].
-    pub fn to_u16(buf: &[u8]) -> Result<u16, &'static str> {
+    pub fn to_u16(buf: &[u8]) -> Result<u16, Error> {
         if buf.len() < 2 {
-            Err("Error when converting to u16")
+            Err(Error::MalformedPacket(
+                "Error when converting to u16".to_string(),
+            ))
         } else {
             Ok(((buf[0] as u16) << 8) + buf[1] as u16)
         }
@@ -27,13 +29,14 @@ impl Convert {
 
     /// Converts a zero-terminated [`u8`] slice to a [`String`], and returns the
     /// size of the [`String`]. Useful for TFTP packet conversions.
-    pub fn to_string(buf: &[u8], start: usize) -> Result<(String, usize), Box<dyn Error>> {
+    pub fn to_string(buf: &[u8], start: usize) -> Result<(String, usize), Error> {
         match buf[start..].iter().position(|&b| b == 0x00) {
             Some(index) => Ok((
-                String::from_utf8(buf[start..start + index].to_vec())?,
+                String::from_utf8(buf[start..start + index].to_vec())
+                    .map_err(|_| Error::MalformedPacket("Invalid UTF-8 string".to_string()))?,
                 index + start,
             )),
-            None => Err("Invalid string".into()),
+            None => Err(Error::MalformedPacket("Invalid string".to_string())),
         }
     }
 }
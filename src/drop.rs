@@ -1,21 +1,20 @@
-use std::error::Error;
 use std::sync::Mutex;
 
 use crate::log::*;
-use crate::Packet;
+use crate::{Error, Packet};
 
 static TX_DROP: Mutex<Vec<i32>> = Mutex::new(Vec::new());
 
-pub fn drop_set(opt: Option<String>) -> Result<(), Box<dyn Error>> {
+pub fn drop_set(opt: Option<String>) -> Result<(), Error> {
     if let Some(arg) = opt {
         let mut tx_drop = TX_DROP.lock().unwrap();
         for val in arg.split(',') {
-            let val_num = val.parse::<i32>()?;
+            let val_num = val.parse::<i32>().map_err(Error::config)?;
             tx_drop.push(val_num);
         }
         Ok(())
     } else {
-        Err("Missing argument".into())
+        Err(Error::Config("Missing argument".to_string()))
     }
 }
 
@@ -0,0 +1,108 @@
+use std::fmt;
+use std::io::{Error as IoError, ErrorKind};
+use std::sync::Arc;
+
+use crate::ErrorCode;
+
+/// Error `enum` is the error type of the crate, telling the callers what
+/// failed without matching the error messages.
+///
+/// # Example
+///
+/// ```rust
+/// use tftpd::{Error, Packet};
+///
+/// match Packet::deserialize(&[0x00]) {
+///     Err(Error::MalformedPacket(detail)) => println!("Dropped packet: {detail}"),
+///     _ => unreachable!(),
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// An IO error, other than a timeout of a socket, shared by the clones
+    /// of the error
+    Io(Arc<IoError>),
+    /// The remote peer did not answer in time
+    Timeout(String),
+    /// An error packet with this code was received or sent
+    Protocol(ErrorCode, String),
+    /// A packet could not be parsed
+    MalformedPacket(String),
+    /// An option could not be parsed or applied
+    OptionRejected(String),
+    /// Access to a file was denied, which refuses the request with
+    /// [`ErrorCode::AccessViolation`]
+    AccessDenied(String),
+    /// The transfer was aborted locally
+    Aborted(String),
+    /// The configuration is invalid
+    Config(String),
+}
+
+impl Error {
+    /// Converts an IO error of a socket receive, where timeouts are expected.
+    pub(crate) fn from_recv(err: IoError) -> Error {
+        match err.kind() {
+            /* Windows returns WouldBlock and Unix TimedOut */
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => Error::Timeout(err.to_string()),
+            _ => Error::from(err),
+        }
+    }
+
+    pub(crate) fn io<S: Into<String>>(kind: ErrorKind, msg: S) -> Error {
+        Error::from(IoError::new(kind, msg.into()))
+    }
+
+    /// Converts an error of a configuration value, such as a parse error.
+    pub(crate) fn config<E: fmt::Display>(err: E) -> Error {
+        Error::Config(err.to_string())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{err}"),
+            Error::MalformedPacket(detail) => write!(f, "Malformed packet: {detail}"),
+            Error::Timeout(msg)
+            | Error::Protocol(_, msg)
+            | Error::OptionRejected(msg)
+            | Error::AccessDenied(msg)
+            | Error::Aborted(msg)
+            | Error::Config(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(err: IoError) -> Self {
+        Error::Io(Arc::new(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_io_errors() {
+        let err = Error::from(IoError::from_raw_os_error(2));
+        let clone = err.clone();
+
+        let Error::Io(io_err) = clone else {
+            panic!("clone is not an IO error");
+        };
+        assert_eq!(io_err.raw_os_error(), Some(2));
+        assert_eq!(io_err.kind(), ErrorKind::NotFound);
+        assert!(std::error::Error::source(&err).is_some());
+    }
+}
@@ -0,0 +1,276 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::Instant;
+
+use crate::log::*;
+use crate::observer::TransferTracker;
+use crate::options::OptionsPrivate;
+use crate::worker::{finish_receive, finish_send};
+use crate::{Error, FileSink, Packet, ReceiveTransfer, SendTransfer, Socket, Transfer};
+
+#[cfg(feature = "debug_drop")]
+use crate::drop::drop_check;
+
+/// EventLoop `struct` drives many transfers from a single thread through the
+/// server socket, instead of spawning a [`crate::Worker`] thread with its own
+/// socket for each of them. Packets are dispatched to the transfers by the
+/// address of the client, and the retransmission deadlines are kept in a
+/// timer heap.
+pub(crate) struct EventLoop {
+    sessions: HashMap<SocketAddr, Session>,
+    timers: BinaryHeap<Reverse<(Instant, SocketAddr)>>,
+    completed: usize,
+    failed: usize,
+}
+
+struct Session {
+    transfer: SessionTransfer,
+    tracker: TransferTracker,
+    opt_local: OptionsPrivate,
+    deadline: Option<Instant>,
+}
+
+enum SessionTransfer {
+    Send(SendTransfer<Box<dyn Read + Send>>),
+    Receive(ReceiveTransfer<Box<dyn FileSink>>),
+}
+
+impl Session {
+    fn transfer(&mut self) -> &mut dyn Transfer {
+        match &mut self.transfer {
+            SessionTransfer::Send(transfer) => transfer,
+            SessionTransfer::Receive(transfer) => transfer,
+        }
+    }
+
+    fn update(&mut self) {
+        match &self.transfer {
+            SessionTransfer::Send(transfer) => self.tracker.update(transfer),
+            SessionTransfer::Receive(transfer) => self.tracker.update(transfer),
+        }
+    }
+}
+
+impl EventLoop {
+    pub(crate) fn new() -> EventLoop {
+        EventLoop {
+            sessions: HashMap::new(),
+            timers: BinaryHeap::new(),
+            completed: 0,
+            failed: 0,
+        }
+    }
+
+    /// Starts sending a file to `to`, once the request has been accepted.
+    pub(crate) fn start_send(
+        &mut self,
+        socket: &UdpSocket,
+        to: SocketAddr,
+        transfer: SendTransfer<Box<dyn Read + Send>>,
+        tracker: TransferTracker,
+        opt_local: OptionsPrivate,
+    ) {
+        self.start(
+            socket,
+            to,
+            SessionTransfer::Send(transfer),
+            tracker,
+            opt_local,
+        );
+    }
+
+    /// Starts receiving a file from `to`, once the request has been accepted.
+    pub(crate) fn start_receive(
+        &mut self,
+        socket: &UdpSocket,
+        to: SocketAddr,
+        transfer: ReceiveTransfer<Box<dyn FileSink>>,
+        tracker: TransferTracker,
+        opt_local: OptionsPrivate,
+    ) {
+        self.start(
+            socket,
+            to,
+            SessionTransfer::Receive(transfer),
+            tracker,
+            opt_local,
+        );
+    }
+
+    fn start(
+        &mut self,
+        socket: &UdpSocket,
+        to: SocketAddr,
+        transfer: SessionTransfer,
+        tracker: TransferTracker,
+        opt_local: OptionsPrivate,
+    ) {
+        let session = Session {
+            transfer,
+            tracker,
+            opt_local,
+            deadline: None,
+        };
+
+        if let Some(previous) = self.sessions.insert(to, session) {
+            log_warn!("New request from {to} replaces its running transfer");
+            self.finish(
+                previous,
+                Err(Error::Aborted(
+                    "Transfer replaced by a new request".to_string(),
+                )),
+            );
+        }
+        self.drive(socket, to);
+    }
+
+    /// Passes a packet to the transfer of `from`, if it is running.
+    pub(crate) fn handle_packet(&mut self, socket: &UdpSocket, packet: Packet, from: SocketAddr) {
+        if let Some(session) = self.sessions.get_mut(&from) {
+            session.transfer().handle_packet(packet, Instant::now());
+            self.drive(socket, from);
+        }
+    }
+
+    /// Handles the deadlines that have expired at `now`.
+    pub(crate) fn handle_timeouts(&mut self, socket: &UdpSocket, now: Instant) {
+        let mut expired = vec![];
+        while let Some(Reverse((deadline, address))) = self.timers.peek().copied() {
+            if deadline > now {
+                break;
+            }
+            self.timers.pop();
+            expired.push((deadline, address));
+        }
+
+        for (deadline, address) in expired {
+            // Entries of rescheduled and finished transfers are stale
+            let Some(session) = self.sessions.get_mut(&address) else {
+                continue;
+            };
+            if session.deadline != Some(deadline) {
+                continue;
+            }
+
+            session.deadline = None;
+            session.transfer().handle_timeout(now);
+            self.drive(socket, address);
+        }
+    }
+
+    /// Aborts all the transfers, and returns their amount.
+    pub(crate) fn abort(&mut self, socket: &UdpSocket, msg: &str) -> usize {
+        let addresses: Vec<SocketAddr> = self.sessions.keys().copied().collect();
+        for address in &addresses {
+            if let Some(session) = self.sessions.get_mut(address) {
+                session.transfer().abort(msg);
+            }
+            self.drive(socket, *address);
+        }
+
+        addresses.len()
+    }
+
+    /// Returns the earliest deadline of the transfers.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        self.timers.peek().map(|Reverse((deadline, _))| *deadline)
+    }
+
+    /// Returns `true` if a transfer with `address` is running.
+    pub(crate) fn contains(&self, address: &SocketAddr) -> bool {
+        self.sessions.contains_key(address)
+    }
+
+    /// Returns the amount of running transfers.
+    pub(crate) fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Returns the amount of running transfers of the client `ip`.
+    pub(crate) fn client_len(&self, ip: &IpAddr) -> usize {
+        self.sessions
+            .keys()
+            .filter(|address| address.ip() == *ip)
+            .count()
+    }
+
+    /// Returns the amount of completed and failed transfers since the last
+    /// call.
+    pub(crate) fn take_finished(&mut self) -> (usize, usize) {
+        let finished = (self.completed, self.failed);
+        self.completed = 0;
+        self.failed = 0;
+        finished
+    }
+
+    fn drive(&mut self, socket: &UdpSocket, address: SocketAddr) {
+        let Some(session) = self.sessions.get_mut(&address) else {
+            return;
+        };
+
+        let now = Instant::now();
+        let repeat_count = session.opt_local.repeat_count;
+        let mut result = None;
+        while let Some(packet) = session.transfer().poll_transmit(now) {
+            if let Err(err) = send_packet(socket, &packet, &address, repeat_count) {
+                result = Some(Err(err));
+                break;
+            }
+        }
+        session.update();
+
+        let result = result.or_else(|| session.transfer().poll_result());
+        if let Some(result) = result {
+            if let Some(session) = self.sessions.remove(&address) {
+                self.finish(session, result);
+            }
+            return;
+        }
+
+        let deadline = session.transfer().poll_timeout();
+        if deadline != session.deadline {
+            session.deadline = deadline;
+            if let Some(deadline) = deadline {
+                self.timers.push(Reverse((deadline, address)));
+            }
+        }
+    }
+
+    fn finish(&mut self, mut session: Session, result: Result<u64, Error>) {
+        let report = match session.transfer {
+            SessionTransfer::Send(_) => finish_send(result, &mut session.tracker),
+            SessionTransfer::Receive(transfer) => finish_receive(
+                result,
+                transfer.into_inner(),
+                &mut session.tracker,
+                session.opt_local.clean_on_error,
+            ),
+        };
+
+        if report.is_success() {
+            self.completed += 1;
+        } else {
+            self.failed += 1;
+        }
+    }
+}
+
+fn send_packet(
+    socket: &UdpSocket,
+    packet: &Packet,
+    to: &SocketAddr,
+    repeat_count: u8,
+) -> Result<(), Error> {
+    #[cfg(feature = "debug_drop")]
+    if drop_check(packet) {
+        return Ok(());
+    };
+
+    for _ in 0..repeat_count {
+        Socket::send_to(socket, packet, to)?;
+    }
+
+    Ok(())
+}
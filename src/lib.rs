@@ -2,7 +2,8 @@
 
 //! Multithreaded TFTP daemon implemented in pure Rust.
 //!
-//! This server implements [RFC 1350](https://www.rfc-editor.org/rfc/rfc1350), The TFTP Protocol (Revision 2).
+//! This server implements [RFC 1350](https://www.rfc-editor.org/rfc/rfc1350), The TFTP Protocol (Revision 2),
+//! with both the `octet` and `netascii` transfer modes.
 //! It also supports the following [RFC 2347](https://www.rfc-editor.org/rfc/rfc2347) TFTP Option Extensions:
 //!
 //! - [RFC 2348](https://www.rfc-editor.org/rfc/rfc2348) Blocksize Option
@@ -15,41 +16,99 @@
 //! Since TFTP servers do not offer any type of login or access control mechanisms, this server only allows
 //! transfer and receiving inside a chosen folder, and disallows external file access.
 
+#[cfg(all(feature = "async", feature = "client"))]
+mod async_client;
+#[cfg(feature = "async")]
+mod async_server;
+#[cfg(feature = "async")]
+mod async_socket;
+#[cfg(feature = "async")]
+mod async_worker;
 #[cfg(feature = "client")]
 mod client;
 
+mod acl;
+mod builder;
 #[cfg(feature = "client")]
 mod client_config;
 mod config;
+mod config_file;
 mod convert;
+mod error;
+mod event_loop;
+mod limits;
 mod log;
+mod netascii;
+mod observer;
 mod options;
 mod packet;
+mod provider;
+mod remap;
+mod report;
+mod roots;
 mod server;
 mod socket;
+mod transfer;
 mod window;
 mod worker;
 
 #[cfg(feature = "debug_drop")]
 mod drop;
 
+pub use acl::AccessList;
+pub use acl::IpNetwork;
+#[cfg(all(feature = "async", feature = "client"))]
+pub use async_client::AsyncClient;
+#[cfg(feature = "async")]
+pub use async_server::AsyncServer;
+#[cfg(feature = "async")]
+pub use async_socket::AsyncSocket;
+pub use builder::ServerBuilder;
+pub use builder::ServerHandle;
 #[cfg(feature = "client")]
 pub use client::Client;
 #[cfg(feature = "client")]
 pub use client::Mode;
 #[cfg(feature = "client")]
 pub use client_config::ClientConfig;
+#[cfg(feature = "client")]
+pub use client_config::IpPreference;
 pub use config::Config;
 pub use convert::Convert;
+pub use error::Error;
+pub use limits::UploadLimits;
+pub use log::log_to_stderr;
 pub use log::verbosity;
+pub use netascii::NetasciiDecoder;
+pub use netascii::NetasciiEncoder;
+pub use observer::Direction;
+pub use observer::Observer;
+pub use observer::TransferInfo;
 pub use options::OptionType;
+pub use options::OptionsProtocol;
+pub use options::Rollover;
 pub use options::TransferOption;
 pub use packet::ErrorCode;
 pub use packet::Opcode;
 pub use packet::Packet;
+pub use packet::TransferMode;
+pub use provider::DirectoryProvider;
+pub use provider::FileProvider;
+pub use provider::FileSink;
+pub use provider::FileSource;
+pub use provider::FileWriter;
+pub use provider::GeneratedFile;
+pub use provider::SymlinkPolicy;
+pub use provider::WriteSink;
+pub use remap::RemapRules;
+pub use report::TransferReport;
+pub use roots::ClientRoots;
 pub use server::Server;
 pub use socket::ServerSocket;
 pub use socket::Socket;
+pub use transfer::ReceiveTransfer;
+pub use transfer::SendTransfer;
+pub use transfer::Transfer;
 pub use window::WindowRead;
 pub use window::WindowWrite;
 pub use worker::Worker;
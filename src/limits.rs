@@ -0,0 +1,127 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::Error;
+
+/// UploadLimits `struct` holds the maximum sizes of the files received by
+/// the server, for the whole receive directory and for some of its
+/// directories. The directories are relative to the receive directory of
+/// the client, see [`crate::ClientRoots`]. The limit of the deepest directory
+/// holding a file applies, and `None` means no limit.
+///
+/// # Example
+///
+/// ```rust
+/// use std::path::Path;
+/// use tftpd::UploadLimits;
+///
+/// let mut limits = UploadLimits::default();
+/// limits.set_max_size(Some(1024));
+/// limits.limit_directory("firmware".into(), Some(64 * 1024 * 1024));
+/// limits.limit_directory("firmware/logs".into(), None);
+///
+/// assert_eq!(limits.max_size(Path::new("notes.txt")), Some(1024));
+/// assert_eq!(limits.max_size(Path::new("firmware/image.bin")), Some(64 * 1024 * 1024));
+/// assert_eq!(limits.max_size(Path::new("firmware/logs/boot.log")), None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UploadLimits {
+    max_size: Option<u64>,
+    directories: Vec<(PathBuf, Option<u64>)>,
+}
+
+impl UploadLimits {
+    /// Sets the maximum size of the files outside of the limited directories.
+    pub fn set_max_size(&mut self, max_size: Option<u64>) {
+        self.max_size = max_size;
+    }
+
+    /// Sets the maximum size of the files in `directory`, relative to the
+    /// receive directory, replacing its previous limit.
+    pub fn limit_directory(&mut self, directory: PathBuf, max_size: Option<u64>) {
+        let directory = normalize(&directory);
+        self.directories
+            .retain(|(limited, _)| *limited != directory);
+        self.directories.push((directory, max_size));
+    }
+
+    /// Returns the maximum size of the file at `path`, relative to the
+    /// receive directory.
+    pub fn max_size(&self, path: &Path) -> Option<u64> {
+        let path = normalize(path);
+        self.directories
+            .iter()
+            .filter(|(directory, _)| path.starts_with(directory))
+            .max_by_key(|(directory, _)| directory.components().count())
+            .map_or(self.max_size, |(_, max_size)| *max_size)
+    }
+}
+
+/// Removes the `.` components of `path`, which would not match the limited
+/// directories.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+/// Parses an amount of bytes, with an optional `K`, `M` or `G` binary suffix.
+pub(crate) fn parse_size(value: &str) -> Result<u64, Error> {
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((index, 'K' | 'k')) => (&value[..index], 1 << 10),
+        Some((index, 'M' | 'm')) => (&value[..index], 1 << 20),
+        Some((index, 'G' | 'g')) => (&value[..index], 1 << 30),
+        _ => (value, 1),
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or(Error::Config(format!("Invalid size {value}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1500").unwrap(), 1500);
+        assert_eq!(parse_size("64K").unwrap(), 64 * 1024);
+        assert_eq!(parse_size("2m").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_size("1G").unwrap(), 1024 * 1024 * 1024);
+
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("-1").is_err());
+        assert!(parse_size("1T").is_err());
+        assert!(parse_size("99999999999999999G").is_err());
+    }
+
+    #[test]
+    fn limits_directories() {
+        let mut limits = UploadLimits::default();
+        assert_eq!(limits.max_size(Path::new("file.bin")), None);
+
+        limits.limit_directory("images".into(), Some(100));
+        limits.limit_directory("images/large".into(), Some(1000));
+        assert_eq!(limits.max_size(Path::new("file.bin")), None);
+        assert_eq!(limits.max_size(Path::new("images/file.bin")), Some(100));
+        assert_eq!(
+            limits.max_size(Path::new("images/large/file.bin")),
+            Some(1000)
+        );
+        // Directories match whole components only
+        assert_eq!(limits.max_size(Path::new("images2/file.bin")), None);
+        assert_eq!(limits.max_size(Path::new("./images/file.bin")), Some(100));
+        assert_eq!(
+            limits.max_size(Path::new("images/./large/file.bin")),
+            Some(1000)
+        );
+
+        limits.set_max_size(Some(10));
+        limits.limit_directory("images".into(), Some(200));
+        assert_eq!(limits.max_size(Path::new("file.bin")), Some(10));
+        assert_eq!(limits.max_size(Path::new("images/file.bin")), Some(200));
+    }
+}
@@ -1,9 +1,11 @@
 #![allow(unused_imports)]
 
 use std::cmp::max;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::OnceLock;
 
 static VERBOSITY: OnceLock<usize> = OnceLock::new();
+static STDERR: AtomicBool = AtomicBool::new(false);
 
 /// Verbosity should be set once at program start.
 pub fn verbosity_set(verbosity: isize) {
@@ -15,29 +17,50 @@ pub fn verbosity() -> usize {
     *VERBOSITY.get().unwrap_or(&1)
 }
 
+/// Sends all logs to the standard error, when the standard output carries
+/// the transferred data.
+#[cfg(feature = "client")]
+pub fn log_to_stderr_set() {
+    STDERR.store(true, Ordering::Relaxed);
+}
+
+/// Helper function to retrieve the output of following macros
+pub fn log_to_stderr() -> bool {
+    STDERR.load(Ordering::Relaxed)
+}
+
 /// Report error logs
 #[macro_export]
 macro_rules! log_err {
     ($($x:tt)*) => { eprintln!($($x)*) }
 }
 
+/// Print logs to the standard output, or to the standard error if set
+#[macro_export]
+#[doc(hidden)]
+macro_rules! log_print {
+    ($($x:tt)*) => {
+        if $crate::log_to_stderr() { eprintln!($($x)*) } else { println!($($x)*) }
+    }
+}
+
 /// Report warning logs
 #[macro_export]
 macro_rules! log_warn {
-    ($($x:tt)*) => { if  0 < $crate::verbosity() { println!($($x)*)} }
+    ($($x:tt)*) => { if  0 < $crate::verbosity() { $crate::log_print!($($x)*)} }
 }
 
 /// Report info logs
 #[macro_export]
 macro_rules! log_info {
-    ($($x:tt)*) => { if  1 < $crate::verbosity() { println!($($x)*)} }
+    ($($x:tt)*) => { if  1 < $crate::verbosity() { $crate::log_print!($($x)*)} }
 }
 
 /// Report debug logs
 #[macro_export]
 #[cfg(debug_assertions)]
 macro_rules! log_dbg {
-    ($($x:tt)*) => { if  2 < $crate::verbosity() { println!($($x)*)} }
+    ($($x:tt)*) => { if  2 < $crate::verbosity() { $crate::log_print!($($x)*)} }
 }
 
 /// Do not compile debug logs with release target
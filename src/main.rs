@@ -6,7 +6,8 @@ fn main() {
 }
 
 fn server<T: Iterator<Item = String>>(args: T) {
-    let config = Config::new(args).unwrap_or_else(|err| {
+    let args: Vec<String> = args.collect();
+    let config = Config::new(args.iter().cloned()).unwrap_or_else(|err| {
         log_err!("Problem parsing arguments: {err}");
         process::exit(1)
     });
@@ -35,8 +36,30 @@ fn server<T: Iterator<Item = String>>(args: T) {
         );
     }
 
+    if config.max_transfers.is_some() || config.max_client_transfers.is_some() {
+        log_warn!(
+            "Transfer limits: {} in total, {} per client, requests queued for {:?}",
+            config
+                .max_transfers
+                .map_or("unlimited".to_string(), |max| max.to_string()),
+            config
+                .max_client_transfers
+                .map_or("unlimited".to_string(), |max| max.to_string()),
+            config.queue_timeout
+        );
+    }
+
     // Catch Ctrl-C to exit cleanly by sending error msg to active cnx
     signal_hook::flag::register(signal_hook::consts::SIGINT, server.get_abort_flag()).unwrap();
+    // Finish running transfers before exiting on SIGTERM
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, server.get_shutdown_flag()).unwrap();
+
+    // Reload the arguments and the config file on SIGHUP
+    #[cfg(unix)]
+    {
+        server.set_config_loader(move || Config::new(args.iter().cloned()));
+        signal_hook::flag::register(signal_hook::consts::SIGHUP, server.get_reload_flag()).unwrap();
+    }
 
     server.listen();
 }
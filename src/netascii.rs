@@ -0,0 +1,212 @@
+use std::io::{self, Read};
+
+const CR: u8 = b'\r';
+const LF: u8 = b'\n';
+const NUL: u8 = 0x00;
+
+/// NetasciiEncoder `struct` is used to translate local text into the
+/// [RFC 1350](https://www.rfc-editor.org/rfc/rfc1350) netascii format,
+/// where `LF` becomes `CR LF` and a bare `CR` becomes `CR NUL`.
+///
+/// The encoder keeps the second byte of a translated sequence between
+/// calls, so a sequence can straddle two data blocks.
+///
+/// # Example
+///
+/// ```rust
+/// use tftpd::NetasciiEncoder;
+///
+/// let mut encoder = NetasciiEncoder::default();
+/// let mut input = &b"a\nb"[..];
+/// let mut buf = [0; 2];
+///
+/// assert_eq!(encoder.read(&mut input, &mut buf).unwrap(), 2);
+/// assert_eq!(buf, [b'a', b'\r']);
+/// assert_eq!(encoder.read(&mut input, &mut buf).unwrap(), 2);
+/// assert_eq!(buf, [b'\n', b'b']);
+/// ```
+#[derive(Default, Debug)]
+pub struct NetasciiEncoder {
+    pending: Option<u8>,
+}
+
+impl NetasciiEncoder {
+    /// Reads from `reader` and fills `buf` with translated bytes. Returns the
+    /// amount of bytes written, which is less than the size of `buf` only when
+    /// the end of the reader has been reached.
+    pub fn read<R: Read>(&mut self, reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+        let mut size = 0;
+        let mut byte = [0; 1];
+
+        while size < buf.len() {
+            if let Some(pending) = self.pending.take() {
+                buf[size] = pending;
+                size += 1;
+                continue;
+            }
+
+            if reader.read(&mut byte)? == 0 {
+                break;
+            }
+
+            buf[size] = match byte[0] {
+                LF => {
+                    self.pending = Some(LF);
+                    CR
+                }
+                CR => {
+                    self.pending = Some(NUL);
+                    CR
+                }
+                other => other,
+            };
+            size += 1;
+        }
+
+        Ok(size)
+    }
+}
+
+/// NetasciiDecoder `struct` is used to translate received
+/// [RFC 1350](https://www.rfc-editor.org/rfc/rfc1350) netascii data back
+/// into local text, where `CR LF` becomes `LF` and `CR NUL` becomes `CR`.
+///
+/// A `CR` received at the end of a block is kept until the next block
+/// arrives, or until [`NetasciiDecoder::finish()`] is called.
+///
+/// # Example
+///
+/// ```rust
+/// use tftpd::NetasciiDecoder;
+///
+/// let mut decoder = NetasciiDecoder::default();
+///
+/// assert_eq!(decoder.decode(b"a\r"), b"a");
+/// assert_eq!(decoder.decode(b"\nb\r\0"), b"\nb\r");
+/// assert!(decoder.finish().is_empty());
+/// ```
+#[derive(Default, Debug)]
+pub struct NetasciiDecoder {
+    pending_cr: bool,
+}
+
+impl NetasciiDecoder {
+    /// Translates a chunk of netascii data into local text.
+    pub fn decode(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut decoded = Vec::with_capacity(data.len());
+
+        for &byte in data {
+            if self.pending_cr {
+                self.pending_cr = false;
+                match byte {
+                    LF => {
+                        decoded.push(LF);
+                        continue;
+                    }
+                    NUL => {
+                        decoded.push(CR);
+                        continue;
+                    }
+                    // Malformed sequence, keep the bare CR
+                    _ => decoded.push(CR),
+                }
+            }
+
+            if byte == CR {
+                self.pending_cr = true;
+            } else {
+                decoded.push(byte);
+            }
+        }
+
+        decoded
+    }
+
+    /// Returns the remaining bytes at the end of the transfer. A trailing `CR`
+    /// is not valid netascii, but is kept as is.
+    pub fn finish(&mut self) -> Vec<u8> {
+        if self.pending_cr {
+            self.pending_cr = false;
+            vec![CR]
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// Returns the size of the data of `reader` once translated to netascii.
+pub fn netascii_len<R: Read>(reader: R) -> io::Result<u64> {
+    let mut size = 0;
+
+    for byte in io::BufReader::new(reader).bytes() {
+        size += match byte? {
+            LF | CR => 2,
+            _ => 1,
+        };
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(data: &[u8], chunk_size: usize) -> Vec<Vec<u8>> {
+        let mut encoder = NetasciiEncoder::default();
+        let mut reader = data;
+        let mut chunks = vec![];
+
+        loop {
+            let mut chunk = vec![0; chunk_size];
+            let size = encoder.read(&mut reader, &mut chunk).unwrap();
+            chunk.truncate(size);
+            chunks.push(chunk);
+            if size < chunk_size {
+                return chunks;
+            }
+        }
+    }
+
+    #[test]
+    fn encodes_line_endings() {
+        assert_eq!(
+            encode(b"one\ntwo\rthree", 32),
+            vec![b"one\r\ntwo\r\0three".to_vec()]
+        );
+    }
+
+    #[test]
+    fn encodes_across_chunks() {
+        assert_eq!(
+            encode(b"ab\ncd\r", 3),
+            vec![b"ab\r".to_vec(), b"\ncd".to_vec(), b"\r\0".to_vec()]
+        );
+        assert_eq!(encode(b"abc\n", 4), vec![b"abc\r".to_vec(), b"\n".to_vec()]);
+    }
+
+    #[test]
+    fn decodes_across_chunks() {
+        let mut decoder = NetasciiDecoder::default();
+
+        assert_eq!(decoder.decode(b"ab\r"), b"ab");
+        assert_eq!(decoder.decode(b"\ncd\r"), b"\ncd");
+        assert_eq!(decoder.decode(b"\0"), b"\r");
+        assert_eq!(decoder.decode(b"e\r"), b"e");
+        assert_eq!(decoder.finish(), b"\r");
+        assert!(decoder.finish().is_empty());
+    }
+
+    #[test]
+    fn decodes_malformed_cr() {
+        let mut decoder = NetasciiDecoder::default();
+
+        assert_eq!(decoder.decode(b"a\rb"), b"a\rb");
+    }
+
+    #[test]
+    fn computes_netascii_len() {
+        assert_eq!(netascii_len(&b"one\ntwo\rthree"[..]).unwrap(), 15);
+        assert_eq!(netascii_len(&b""[..]).unwrap(), 0);
+    }
+}
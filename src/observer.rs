@@ -0,0 +1,299 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::options::OptionsProtocol;
+use crate::{Error, Packet, Transfer, TransferReport};
+
+/// Observer `trait` is used for following the requests and transfers of a
+/// [`crate::Server`] from the application embedding it, in addition to the
+/// logs. Every method does nothing by default.
+///
+/// The methods are called from the threads running the transfers, and
+/// should return quickly.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use tftpd::{Config, Observer, Server, TransferInfo, TransferReport};
+///
+/// struct Completed;
+///
+/// impl Observer for Completed {
+///     fn transfer_completed(&self, transfer: &TransferInfo, report: &TransferReport) {
+///         println!("{}: {report}", transfer.file_path.display());
+///     }
+/// }
+///
+/// let args = ["/", "-p", "1237"].iter().map(|s| s.to_string());
+/// let config = Config::new(args).unwrap();
+/// let mut server = Server::new(&config).unwrap();
+///
+/// server.set_observer(Arc::new(Completed));
+/// ```
+pub trait Observer: Send + Sync {
+    /// Called when a read or write request is received from `peer`, before
+    /// it is checked.
+    fn request_received(&self, _packet: &Packet, _peer: &SocketAddr) {}
+    /// Called when a request is accepted, with the options used by its
+    /// transfer.
+    fn options_negotiated(&self, _transfer: &TransferInfo, _options: &OptionsProtocol) {}
+    /// Called when more data has been transferred, with the amount of bytes
+    /// transferred so far, and the negotiated transfer size if any.
+    fn transfer_progress(&self, _transfer: &TransferInfo, _bytes: u64, _total: Option<u64>) {}
+    /// Called when a transfer has completed, with its [`TransferReport`].
+    fn transfer_completed(&self, _transfer: &TransferInfo, _report: &TransferReport) {}
+    /// Called when a transfer has failed.
+    fn transfer_failed(&self, _transfer: &TransferInfo, _error: &Error) {}
+}
+
+/// TransferInfo `struct` identifies a transfer given to an [`Observer`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransferInfo {
+    /// Path of the file sent or received
+    pub file_path: PathBuf,
+    /// Address of the remote peer
+    pub peer: SocketAddr,
+    /// Whether the file is sent or received
+    pub direction: Direction,
+}
+
+/// Direction `enum` tells whether the local side of a transfer sends or
+/// receives the file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    /// The file is sent to the remote peer
+    Send,
+    /// The file is received from the remote peer
+    Receive,
+}
+
+/// Follows a running transfer, building its [`TransferReport`], and
+/// notifies the [`Observer`] if one is set.
+pub(crate) struct TransferTracker {
+    info: TransferInfo,
+    report: TransferReport,
+    observer: Option<Arc<dyn Observer>>,
+    started: Instant,
+}
+
+impl TransferTracker {
+    pub(crate) fn new(
+        info: TransferInfo,
+        options: OptionsProtocol,
+        observer: Option<Arc<dyn Observer>>,
+    ) -> TransferTracker {
+        TransferTracker {
+            info,
+            report: TransferReport {
+                options,
+                ..Default::default()
+            },
+            observer,
+            started: Instant::now(),
+        }
+    }
+
+    pub(crate) fn info(&self) -> &TransferInfo {
+        &self.info
+    }
+
+    /// Updates the report from `transfer`, and reports its progress if more
+    /// data was transferred since the last update.
+    pub(crate) fn update<X: Transfer + ?Sized>(&mut self, transfer: &X) {
+        let report = transfer.report();
+        let progressed = report.bytes != self.report.bytes;
+        self.report = report;
+
+        if let (true, Some(observer)) = (progressed, &self.observer) {
+            observer.transfer_progress(
+                &self.info,
+                self.report.bytes,
+                self.report.options.transfer_size,
+            );
+        }
+    }
+
+    /// Returns the final report of a completed transfer of `bytes`.
+    pub(crate) fn completed(&mut self, bytes: u64) -> TransferReport {
+        self.report.bytes = bytes;
+        self.report.elapsed = self.started.elapsed();
+        self.report.error = None;
+        if let Some(observer) = &self.observer {
+            observer.transfer_completed(&self.info, &self.report);
+        }
+
+        self.report.clone()
+    }
+
+    /// Returns the final report of a transfer which failed with `err`.
+    pub(crate) fn failed(&mut self, err: &Error) -> TransferReport {
+        if let Some(observer) = &self.observer {
+            observer.transfer_failed(&self.info, err);
+        }
+        self.report.elapsed = self.started.elapsed();
+        self.report.error = Some(err.clone());
+
+        self.report.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::net::UdpSocket;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use crate::{ErrorCode, OptionType, ServerBuilder, Socket, TransferOption};
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl Recorder {
+        fn record(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn events_of(&self, file_name: &str) -> Vec<String> {
+            let events = self.events.lock().unwrap();
+            events
+                .iter()
+                .filter(|event| event.starts_with(file_name))
+                .cloned()
+                .collect()
+        }
+    }
+
+    fn file_name(transfer: &TransferInfo) -> String {
+        transfer
+            .file_path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string()
+    }
+
+    impl Observer for Recorder {
+        fn request_received(&self, packet: &Packet, _peer: &SocketAddr) {
+            let filename = match packet {
+                Packet::Rrq { filename, .. } | Packet::Wrq { filename, .. } => filename,
+                _ => "",
+            };
+            self.record(format!("{filename} request"));
+        }
+
+        fn options_negotiated(&self, transfer: &TransferInfo, options: &OptionsProtocol) {
+            self.record(format!(
+                "{} options {:?} {:?}",
+                file_name(transfer),
+                transfer.direction,
+                options.transfer_size
+            ));
+        }
+
+        fn transfer_progress(&self, transfer: &TransferInfo, bytes: u64, total: Option<u64>) {
+            self.record(format!(
+                "{} progress {bytes} {total:?}",
+                file_name(transfer)
+            ));
+        }
+
+        fn transfer_completed(&self, transfer: &TransferInfo, report: &TransferReport) {
+            self.record(format!(
+                "{} completed {} {}",
+                file_name(transfer),
+                report.bytes,
+                report.retransmits
+            ));
+        }
+
+        fn transfer_failed(&self, transfer: &TransferInfo, error: &Error) {
+            let file_name = file_name(transfer);
+            match error {
+                Error::Protocol(code, _) => self.record(format!("{file_name} failed {code}")),
+                err => self.record(format!("{file_name} failed {err}")),
+            }
+        }
+    }
+
+    #[test]
+    fn observes_transfers() {
+        const DIR_NAME: &str = "target/test/observes_transfers";
+        let _ = fs::create_dir_all(DIR_NAME);
+        fs::write(format!("{DIR_NAME}/hello.txt"), "Hello, world!").unwrap();
+
+        let recorder = Arc::new(Recorder::default());
+        let handle = ServerBuilder::new()
+            .ip_address([127, 0, 0, 1].into())
+            .port(0)
+            .directory(DIR_NAME)
+            .overwrite(true)
+            .observer(recorder.clone())
+            .start()
+            .unwrap();
+        let address = handle.local_addr();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let request = Packet::Rrq {
+            filename: "hello.txt".to_string(),
+            mode: "octet".to_string(),
+            options: vec![TransferOption {
+                option: OptionType::TransferSize,
+                value: 0,
+            }],
+        };
+        Socket::send_to(&client, &request, &address).unwrap();
+        let (_, from) = client.recv_from_with_size(512).unwrap();
+        Socket::send_to(&client, &Packet::Ack(0), &from).unwrap();
+        client.recv_from_with_size(512).unwrap();
+        Socket::send_to(&client, &Packet::Ack(1), &from).unwrap();
+
+        let request = Packet::Wrq {
+            filename: "upload.txt".to_string(),
+            mode: "octet".to_string(),
+            options: vec![],
+        };
+        Socket::send_to(&client, &request, &address).unwrap();
+        let (packet, from) = client.recv_from_with_size(512).unwrap();
+        assert_eq!(packet, Packet::Ack(0));
+        let error = Packet::Error {
+            code: ErrorCode::DiskFull,
+            msg: "disk full".to_string(),
+        };
+        Socket::send_to(&client, &error, &from).unwrap();
+
+        handle.shutdown();
+        handle.join().unwrap();
+
+        // Each transfer runs in its own thread
+        assert_eq!(
+            recorder.events_of("hello.txt"),
+            [
+                "hello.txt request",
+                "hello.txt options Send Some(13)",
+                "hello.txt progress 13 Some(13)",
+                "hello.txt completed 13 0",
+            ]
+        );
+        assert_eq!(
+            recorder.events_of("upload.txt"),
+            [
+                "upload.txt request",
+                "upload.txt options Receive None",
+                "upload.txt failed Disk Full",
+            ]
+        );
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+}
@@ -1,9 +1,8 @@
-use std::error::Error;
 use std::fmt;
 use std::str::FromStr;
 use std::time::Duration;
 
-use crate::{log::*, server::RequestType};
+use crate::{log::*, server::RequestType, Error};
 
 pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
 pub const DEFAULT_BLOCK_SIZE: u16 = 512;
@@ -28,16 +27,19 @@ pub enum Rollover {
 /// Local options `struct` used for storing and passing options for client and server
 /// set directly from executable arguments. Though present on both sides of the
 /// transfer, they can differ and are independent.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct OptionsPrivate {
     /// Duplicate all packets sent from the server. (default: 0)
     pub repeat_count: u8,
-    /// Should clean (delete) files after receiving errors. (default: true)
+    /// Should clean (discard) the data of a [`crate::FileSink`] after receiving errors.
+    /// Files on disk are written atomically and always cleaned. (default: true)
     pub clean_on_error: bool,
     /// Max count of retires (default: 6)
     pub max_retries: usize,
     /// Block counter roll-over policy  (default: Enforce0)
     pub rollover: Rollover,
+    /// Maximum size of a received file, checked during the transfer (default: unlimited)
+    pub max_size: Option<u64>,
 }
 
 impl Default for OptionsPrivate {
@@ -47,6 +49,7 @@ impl Default for OptionsPrivate {
             clean_on_error: true,
             max_retries: DEFAULT_MAX_RETRIES,
             rollover: DEFAULT_ROLLOVER,
+            max_size: None,
         }
     }
 }
@@ -71,6 +74,7 @@ pub struct OptionsProtocol {
 }
 
 impl OptionsProtocol {
+    /// Returns the options to send in a request.
     pub fn prepare(&self) -> Vec<TransferOption> {
         let mut options = vec![
             TransferOption {
@@ -109,10 +113,10 @@ impl OptionsProtocol {
         options
     }
 
-    pub fn parse(
+    pub(crate) fn parse(
         options: &mut [TransferOption],
         request_type: RequestType,
-    ) -> Result<OptionsProtocol, &'static str> {
+    ) -> Result<OptionsProtocol, Error> {
         let mut opt_common = OptionsProtocol::default();
 
         for option in options {
@@ -179,7 +183,8 @@ impl OptionsProtocol {
         Ok(opt_common)
     }
 
-    pub fn apply(&mut self, options: &Vec<TransferOption>) -> Result<(), Box<dyn Error>> {
+    /// Applies the options acknowledged by the remote peer.
+    pub fn apply(&mut self, options: &Vec<TransferOption>) -> Result<(), Error> {
         for option in options {
             match option.option {
                 OptionType::BlockSize => self.block_size = option.value as u16,
@@ -302,10 +307,10 @@ impl OptionType {
 }
 
 impl FromStr for OptionType {
-    type Err = &'static str;
+    type Err = Error;
 
     /// Converts a [`str`] to an [`OptionType`].
-    fn from_str(value: &str) -> Result<Self, &'static str> {
+    fn from_str(value: &str) -> Result<Self, Error> {
         match value {
             "blksize" => Ok(OptionType::BlockSize),
             "tsize" => Ok(OptionType::TransferSize),
@@ -313,7 +318,9 @@ impl FromStr for OptionType {
             "utimeout" => Ok(OptionType::UTimeout),
             "windowsize" => Ok(OptionType::WindowSize),
             "windowwait" => Ok(OptionType::WindowWait),
-            _ => Err("Invalid option type"),
+            _ => Err(Error::OptionRejected(format!(
+                "Invalid option type {value}"
+            ))),
         }
     }
 }
@@ -1,8 +1,7 @@
-use std::error::Error;
 use std::fmt;
 use std::str::FromStr;
 
-use crate::{Convert, OptionType, TransferOption};
+use crate::{Convert, Error, OptionType, TransferOption};
 
 /// Packet `enum` represents the valid TFTP packet types.
 ///
@@ -60,9 +59,11 @@ pub enum Packet {
 
 impl Packet {
     /// Deserializes a [`u8`] slice into a [`Packet`].
-    pub fn deserialize(buf: &[u8]) -> Result<Packet, Box<dyn Error>> {
+    pub fn deserialize(buf: &[u8]) -> Result<Packet, Error> {
         if buf.len() < 2 {
-            return Err("Buffer too short to serialize".into());
+            return Err(Error::MalformedPacket(
+                "Buffer too short to serialize".to_string(),
+            ));
         }
         let opcode = Opcode::from_u16(Convert::to_u16(&buf[0..=1])?)?;
 
@@ -76,7 +77,7 @@ impl Packet {
     }
 
     /// Serializes a [`Packet`] into a [`Vec<u8>`].
-    pub fn serialize(&self) -> Result<Vec<u8>, &'static str> {
+    pub fn serialize(&self) -> Result<Vec<u8>, Error> {
         match self {
             Packet::Rrq {
                 filename,
@@ -128,7 +129,7 @@ pub enum Opcode {
 
 impl Opcode {
     /// Converts a [`u16`] to an [`Opcode`].
-    pub fn from_u16(val: u16) -> Result<Opcode, &'static str> {
+    pub fn from_u16(val: u16) -> Result<Opcode, Error> {
         match val {
             0x0001 => Ok(Opcode::Rrq),
             0x0002 => Ok(Opcode::Wrq),
@@ -136,7 +137,7 @@ impl Opcode {
             0x0004 => Ok(Opcode::Ack),
             0x0005 => Ok(Opcode::Error),
             0x0006 => Ok(Opcode::Oack),
-            _ => Err("Invalid opcode"),
+            _ => Err(Error::MalformedPacket(format!("Invalid opcode {val}"))),
         }
     }
 
@@ -146,6 +147,72 @@ impl Opcode {
     }
 }
 
+/// TransferMode `enum` represents the transfer modes used in the TFTP definition.
+///
+/// This `enum` has function implementations for conversion between
+/// [`TransferMode`]s and [`str`]s. Parsing is case-insensitive, as required
+/// by [RFC 1350](https://www.rfc-editor.org/rfc/rfc1350).
+///
+/// # Example
+///
+/// ```rust
+/// use tftpd::TransferMode;
+///
+/// assert_eq!(TransferMode::Netascii, "NetASCII".parse().unwrap());
+/// assert_eq!("octet", TransferMode::Octet.as_str());
+/// ```
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum TransferMode {
+    /// Netascii transfer mode, translating line endings
+    Netascii,
+    /// Octet transfer mode, sending raw bytes
+    #[default]
+    Octet,
+    /// Mail transfer mode, obsolete and not supported
+    Mail,
+}
+
+impl TransferMode {
+    /// Converts a [`TransferMode`] to a [`str`].
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransferMode::Netascii => "netascii",
+            TransferMode::Octet => "octet",
+            TransferMode::Mail => "mail",
+        }
+    }
+
+    /// Returns `true` if the [`TransferMode`] can be used for transfers.
+    pub fn is_supported(&self) -> bool {
+        match self {
+            TransferMode::Netascii | TransferMode::Octet => true,
+            TransferMode::Mail => false,
+        }
+    }
+}
+
+impl FromStr for TransferMode {
+    type Err = Error;
+
+    /// Converts a [`str`] to a [`TransferMode`], ignoring case.
+    fn from_str(value: &str) -> Result<Self, Error> {
+        match value.to_lowercase().as_str() {
+            "netascii" => Ok(TransferMode::Netascii),
+            "octet" => Ok(TransferMode::Octet),
+            "mail" => Ok(TransferMode::Mail),
+            _ => Err(Error::MalformedPacket(format!(
+                "Invalid transfer mode {value}"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for TransferMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 /// ErrorCode `enum` represents the error codes used in the TFTP definition.
 ///
 /// This `enum` has function implementations for converting [`u16`]s to
@@ -184,7 +251,7 @@ pub enum ErrorCode {
 
 impl ErrorCode {
     /// Converts a [`u16`] to an [`ErrorCode`].
-    pub fn from_u16(code: u16) -> Result<ErrorCode, &'static str> {
+    pub fn from_u16(code: u16) -> Result<ErrorCode, Error> {
         match code {
             0 => Ok(ErrorCode::NotDefined),
             1 => Ok(ErrorCode::FileNotFound),
@@ -195,7 +262,7 @@ impl ErrorCode {
             6 => Ok(ErrorCode::FileExists),
             7 => Ok(ErrorCode::NoSuchUser),
             8 => Ok(ErrorCode::RefusedOption),
-            _ => Err("Invalid error code"),
+            _ => Err(Error::MalformedPacket(format!("Invalid error code {code}"))),
         }
     }
 
@@ -221,7 +288,7 @@ impl fmt::Display for ErrorCode {
     }
 }
 
-fn parse_rq(buf: &[u8], opcode: Opcode) -> Result<Packet, Box<dyn Error>> {
+fn parse_rq(buf: &[u8], opcode: Opcode) -> Result<Packet, Error> {
     let mut options = vec![];
     let filename: String;
     let mode: String;
@@ -239,7 +306,7 @@ fn parse_rq(buf: &[u8], opcode: Opcode) -> Result<Packet, Box<dyn Error>> {
         if let Ok(option) = OptionType::from_str(option.to_lowercase().as_str()) {
             options.push(TransferOption {
                 option,
-                value: value.parse()?,
+                value: parse_value(&value)?,
             });
         }
     }
@@ -255,22 +322,22 @@ fn parse_rq(buf: &[u8], opcode: Opcode) -> Result<Packet, Box<dyn Error>> {
             mode,
             options,
         }),
-        _ => Err("Non request opcode".into()),
+        _ => Err(Error::MalformedPacket("Non request opcode".to_string())),
     }
 }
 
-fn parse_data(buf: &[u8]) -> Result<Packet, Box<dyn Error>> {
+fn parse_data(buf: &[u8]) -> Result<Packet, Error> {
     Ok(Packet::Data {
         block_num: Convert::to_u16(&buf[2..])?,
         data: buf[4..].to_vec(),
     })
 }
 
-fn parse_ack(buf: &[u8]) -> Result<Packet, Box<dyn Error>> {
+fn parse_ack(buf: &[u8]) -> Result<Packet, Error> {
     Ok(Packet::Ack(Convert::to_u16(&buf[2..])?))
 }
 
-fn parse_oack(buf: &[u8]) -> Result<Packet, Box<dyn Error>> {
+fn parse_oack(buf: &[u8]) -> Result<Packet, Error> {
     let mut options = vec![];
     let mut value: String;
     let mut option;
@@ -282,7 +349,7 @@ fn parse_oack(buf: &[u8]) -> Result<Packet, Box<dyn Error>> {
         if let Ok(option) = OptionType::from_str(option.to_lowercase().as_str()) {
             options.push(TransferOption {
                 option,
-                value: value.parse()?,
+                value: parse_value(&value)?,
             });
         }
     }
@@ -290,7 +357,7 @@ fn parse_oack(buf: &[u8]) -> Result<Packet, Box<dyn Error>> {
     Ok(Packet::Oack(options))
 }
 
-fn parse_error(buf: &[u8]) -> Result<Packet, Box<dyn Error>> {
+fn parse_error(buf: &[u8]) -> Result<Packet, Error> {
     let code = ErrorCode::from_u16(Convert::to_u16(&buf[2..])?)?;
     if let Ok((msg, _)) = Convert::to_string(buf, 4) {
         Ok(Packet::Error { code, msg })
@@ -302,6 +369,12 @@ fn parse_error(buf: &[u8]) -> Result<Packet, Box<dyn Error>> {
     }
 }
 
+fn parse_value(value: &str) -> Result<u64, Error> {
+    value
+        .parse()
+        .map_err(|_| Error::MalformedPacket(format!("Invalid option value {value}")))
+}
+
 fn serialize_rrq(filename: &String, mode: &String, options: &Vec<TransferOption>) -> Vec<u8> {
     let mut buf = [
         &Opcode::Rrq.as_bytes(),
@@ -641,6 +714,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parses_transfer_mode() {
+        assert_eq!(
+            TransferMode::from_str("octet").unwrap(),
+            TransferMode::Octet
+        );
+        assert_eq!(
+            TransferMode::from_str("OCTET").unwrap(),
+            TransferMode::Octet
+        );
+        assert_eq!(
+            TransferMode::from_str("NetAscii").unwrap(),
+            TransferMode::Netascii
+        );
+        assert_eq!(TransferMode::from_str("mail").unwrap(), TransferMode::Mail);
+        assert!(!TransferMode::Mail.is_supported());
+        assert!(TransferMode::from_str("foo").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        let invalid_opcode = Packet::deserialize(&[0x00, 0x09, 0x00, 0x01]);
+        assert!(matches!(invalid_opcode, Err(Error::MalformedPacket(_))));
+
+        let unterminated_rrq = Packet::deserialize(&[0x00, 0x01, 0x61, 0x62]);
+        assert!(matches!(unterminated_rrq, Err(Error::MalformedPacket(_))));
+
+        let invalid_value = [
+            0x00, 0x01, 0x61, 0x00, 0x6F, 0x63, 0x74, 0x65, 0x74, 0x00, 0x74, 0x73, 0x69, 0x7A,
+            0x65, 0x00, 0x78, 0x00,
+        ];
+        assert!(matches!(
+            Packet::deserialize(&invalid_value),
+            Err(Error::MalformedPacket(detail)) if detail == "Invalid option value x"
+        ));
+    }
+
     #[test]
     fn serializes_rrq() {
         let serialized_data = vec![
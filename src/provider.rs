@@ -0,0 +1,962 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Cursor, ErrorKind, Read, Write};
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::netascii::netascii_len;
+use crate::{Error, ErrorCode, TransferMode};
+
+/// File opened for sending by a [`FileProvider`], along with its size if known.
+pub type FileSource = (Box<dyn Read + Send>, Option<u64>);
+
+/// Hook generating the content of a read request, see [`crate::Server::add_read_hook()`].
+pub type ReadHook = dyn Fn(&str, &SocketAddr) -> Option<GeneratedFile> + Send + Sync;
+
+/// GeneratedFile `enum` represents the content generated for a read request
+/// by a hook registered with [`crate::Server::add_read_hook()`].
+pub enum GeneratedFile {
+    /// In-memory content
+    Bytes(Vec<u8>),
+    /// Content read from a reader, with its size if known. Without a size,
+    /// the transfer size option is not acknowledged. In netascii mode, a
+    /// reader of a known size is read into memory to compute its encoded size.
+    Reader(Box<dyn Read + Send>, Option<u64>),
+}
+
+impl GeneratedFile {
+    /// Converts the [`GeneratedFile`] into a [`FileSource`], with the size of
+    /// the data sent on the wire for the supplied [`TransferMode`].
+    pub fn into_source(self, mode: TransferMode) -> Result<FileSource, Error> {
+        match self {
+            GeneratedFile::Bytes(data) => {
+                let size = match mode {
+                    TransferMode::Netascii => netascii_len(data.as_slice())?,
+                    _ => data.len() as u64,
+                };
+                Ok((Box::new(Cursor::new(data)), Some(size)))
+            }
+            // The netascii size is only known once the whole content is read
+            GeneratedFile::Reader(reader, Some(size)) if mode == TransferMode::Netascii => {
+                let mut data = Vec::with_capacity(size.try_into().unwrap_or_default());
+                reader.take(size).read_to_end(&mut data)?;
+                GeneratedFile::Bytes(data).into_source(mode)
+            }
+            GeneratedFile::Reader(reader, size) => Ok((reader, size)),
+        }
+    }
+}
+
+/// FileProvider `trait` is used to allow serving files from other sources
+/// than a directory, such as an in-memory map, an archive or generated
+/// content.
+///
+/// Paths given to a [`FileProvider`] are the relative paths requested by the
+/// clients, already normalized for the local platform.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{collections::HashMap, io::{Cursor, ErrorKind}, path::{Path, PathBuf}};
+/// use tftpd::{Error, ErrorCode, FileProvider, FileSink, FileSource};
+///
+/// struct MemoryProvider {
+///     files: HashMap<PathBuf, Vec<u8>>,
+/// }
+///
+/// impl FileProvider for MemoryProvider {
+///     fn check_read(&self, path: &Path) -> ErrorCode {
+///         if self.files.contains_key(path) {
+///             ErrorCode::FileExists
+///         } else {
+///             ErrorCode::FileNotFound
+///         }
+///     }
+///
+///     fn check_write(&self, _path: &Path) -> ErrorCode {
+///         ErrorCode::AccessViolation
+///     }
+///
+///     fn open(&self, path: &Path) -> Result<FileSource, Error> {
+///         let data = self.files.get(path).ok_or(std::io::Error::from(ErrorKind::NotFound))?;
+///         let data = data.clone();
+///         let size = data.len() as u64;
+///         Ok((Box::new(Cursor::new(data)), Some(size)))
+///     }
+///
+///     fn create(&self, _path: &Path) -> Result<Box<dyn FileSink>, Error> {
+///         Err(Error::AccessDenied("provider is read-only".to_string()))
+///     }
+/// }
+/// ```
+pub trait FileProvider: Send + Sync {
+    /// Checks whether the file at `path` can be sent. Returns
+    /// [`ErrorCode::FileExists`] if it can, [`ErrorCode::FileNotFound`] if it
+    /// does not exist, and [`ErrorCode::AccessViolation`] if it is not allowed.
+    fn check_read(&self, path: &Path) -> ErrorCode;
+    /// Checks whether the file at `path` can be received. Returns
+    /// [`ErrorCode::FileExists`] if it already exists, [`ErrorCode::FileNotFound`]
+    /// if it does not, and [`ErrorCode::AccessViolation`] if it is not allowed.
+    fn check_write(&self, path: &Path) -> ErrorCode;
+    /// Opens the file at `path` for sending, and returns its size if known.
+    fn open(&self, path: &Path) -> Result<FileSource, Error>;
+    /// Creates the file at `path` for receiving.
+    fn create(&self, path: &Path) -> Result<Box<dyn FileSink>, Error>;
+    /// Returns the space available for receiving the file at `path`, if
+    /// known. Requests announcing a larger file are refused.
+    fn available_space(&self, _path: &Path) -> Option<u64> {
+        None
+    }
+}
+
+/// FileSink `trait` represents the destination of a received file.
+pub trait FileSink: Write + Send {
+    /// Called once the whole file has been received.
+    fn commit(self: Box<Self>) -> Result<(), Error> {
+        Ok(())
+    }
+    /// Called when the transfer fails, to clean the partially received data.
+    fn discard(self: Box<Self>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Counter making the names of the temporary files of a process unique
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// FileWriter `struct` is a [`FileSink`] writing to a file on disk
+/// atomically. The data is written to a hidden temporary file in the same
+/// directory, which replaces the file once committed, and is removed if the
+/// transfer fails, so that readers never see a partial file.
+pub struct FileWriter {
+    file: File,
+    path: PathBuf,
+    temp_path: PathBuf,
+    created_dirs: Vec<PathBuf>,
+    overwrite: bool,
+    done: bool,
+}
+
+impl FileWriter {
+    /// Creates the temporary file of `path`, which is only replaced once the
+    /// whole file has been received.
+    pub fn create(path: &Path) -> Result<FileWriter, Error> {
+        let file_name = path
+            .file_name()
+            .ok_or(Error::io(ErrorKind::InvalidInput, "Invalid file name"))?;
+        let temp_path = path.with_file_name(format!(
+            ".{}.{}-{}.tmp",
+            file_name.to_string_lossy(),
+            process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        Ok(FileWriter {
+            file: OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temp_path)?,
+            path: path.to_path_buf(),
+            temp_path,
+            created_dirs: Vec::new(),
+            overwrite: true,
+            done: false,
+        })
+    }
+
+    /// Sets whether committing replaces a file created at the same path in
+    /// the meantime, which is the default. Otherwise, that file is kept, and
+    /// committing fails with [`ErrorCode::FileExists`].
+    pub fn set_overwrite(&mut self, overwrite: bool) {
+        self.overwrite = overwrite;
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl FileSink for FileWriter {
+    fn commit(mut self: Box<Self>) -> Result<(), Error> {
+        // The data must be on disk before the file gets its final name
+        self.file.sync_all()?;
+        if self.overwrite {
+            fs::rename(&self.temp_path, &self.path)?;
+        } else {
+            // Linking fails instead of replacing a file received meanwhile
+            match fs::hard_link(&self.temp_path, &self.path) {
+                Ok(()) => fs::remove_file(&self.temp_path)?,
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    return Err(Error::Protocol(
+                        ErrorCode::FileExists,
+                        "requested file already exists".to_string(),
+                    ));
+                }
+                // Filesystems without hard links
+                Err(_) if !self.path.exists() => fs::rename(&self.temp_path, &self.path)?,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        self.done = true;
+
+        Ok(())
+    }
+
+    fn discard(mut self: Box<Self>) -> Result<(), Error> {
+        self.done = true;
+        let result = fs::remove_file(&self.temp_path);
+        remove_directories(&self.created_dirs);
+        result?;
+
+        Ok(())
+    }
+}
+
+impl Drop for FileWriter {
+    fn drop(&mut self) {
+        // Neither committed nor discarded: the transfer failed
+        if !self.done {
+            let _ = fs::remove_file(&self.temp_path);
+            remove_directories(&self.created_dirs);
+        }
+    }
+}
+
+/// WriteSink `struct` is a [`FileSink`] writing to any writer, such as the
+/// standard output, which is flushed once the whole file has been received.
+///
+/// # Example
+///
+/// ```rust
+/// use std::io;
+/// use tftpd::{FileSink, WriteSink};
+///
+/// let sink: Box<dyn FileSink> = Box::new(WriteSink::new(io::stdout()));
+/// ```
+pub struct WriteSink<W: Write + Send> {
+    writer: W,
+}
+
+impl<W: Write + Send> WriteSink<W> {
+    /// Creates a [`WriteSink`] writing to `writer`.
+    pub fn new(writer: W) -> WriteSink<W> {
+        WriteSink { writer }
+    }
+}
+
+impl<W: Write + Send> Write for WriteSink<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write + Send> FileSink for WriteSink<W> {
+    fn commit(mut self: Box<Self>) -> Result<(), Error> {
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// DirectoryProvider `struct` is the default [`FileProvider`], sending files
+/// from and receiving files to directories, and disallowing access outside
+/// of them.
+///
+/// # Example
+///
+/// ```rust
+/// use std::path::{Path, PathBuf};
+/// use tftpd::{DirectoryProvider, ErrorCode, FileProvider};
+///
+/// let provider = DirectoryProvider::new(PathBuf::from("."), PathBuf::from("."));
+///
+/// assert_eq!(provider.check_read(Path::new("Cargo.toml")), ErrorCode::FileExists);
+/// assert_eq!(provider.check_read(Path::new("../Cargo.toml")), ErrorCode::AccessViolation);
+/// ```
+pub struct DirectoryProvider {
+    send_directory: PathBuf,
+    receive_directory: PathBuf,
+    dir_mode: Option<u32>,
+    symlink_policy: SymlinkPolicy,
+    overwrite: bool,
+}
+
+/// SymlinkPolicy `enum` describes how a [`DirectoryProvider`] handles the
+/// symbolic links found in the requested paths.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Follow all symbolic links, even those leading out of the directory
+    Follow,
+    /// Follow the symbolic links leading inside of the directory
+    #[default]
+    FollowWithinRoot,
+    /// Refuse the paths going through a symbolic link
+    Refuse,
+}
+
+impl DirectoryProvider {
+    /// Creates a new [`DirectoryProvider`] sending files from `send_directory`
+    /// and receiving files to `receive_directory`.
+    pub fn new(send_directory: PathBuf, receive_directory: PathBuf) -> DirectoryProvider {
+        DirectoryProvider {
+            send_directory,
+            receive_directory,
+            dir_mode: None,
+            symlink_policy: Default::default(),
+            overwrite: true,
+        }
+    }
+
+    /// Sets whether received files replace the files created during their
+    /// transfer, which is the default, see [`FileWriter::set_overwrite()`].
+    pub fn set_overwrite(&mut self, overwrite: bool) {
+        self.overwrite = overwrite;
+    }
+
+    /// Sets how the symbolic links found in the requested paths are handled.
+    pub fn set_symlink_policy(&mut self, symlink_policy: SymlinkPolicy) {
+        self.symlink_policy = symlink_policy;
+    }
+
+    /// Creates the missing directories of the received files with the `mode`
+    /// permissions, masked by the umask and ignored outside of Unix. The
+    /// directories are removed if the transfer fails. `None` disables it.
+    pub fn set_create_dirs(&mut self, mode: Option<u32>) {
+        self.dir_mode = mode;
+    }
+}
+
+impl FileProvider for DirectoryProvider {
+    fn check_read(&self, path: &Path) -> ErrorCode {
+        check_file_exists(
+            &self.send_directory.join(path),
+            &self.send_directory,
+            self.symlink_policy,
+        )
+    }
+
+    fn check_write(&self, path: &Path) -> ErrorCode {
+        check_file_exists(
+            &self.receive_directory.join(path),
+            &self.receive_directory,
+            self.symlink_policy,
+        )
+    }
+
+    fn open(&self, path: &Path) -> Result<FileSource, Error> {
+        let path = self.send_directory.join(path);
+        let file = open_options(self.symlink_policy).read(true).open(&path)?;
+        let size = check_opened(&file, &path, &self.send_directory, self.symlink_policy)?.len();
+
+        Ok((Box::new(file), Some(size)))
+    }
+
+    fn create(&self, path: &Path) -> Result<Box<dyn FileSink>, Error> {
+        let path = self.receive_directory.join(path);
+        let created_dirs = match self.dir_mode {
+            Some(mode) => create_directories(&path, &self.receive_directory, mode)?,
+            None => Vec::new(),
+        };
+
+        match FileWriter::create(&path) {
+            Ok(mut writer) => {
+                writer.created_dirs = created_dirs;
+                writer.set_overwrite(self.overwrite);
+                // Dropping the writer removes the file and directories
+                check_opened(
+                    &writer.file,
+                    &writer.temp_path,
+                    &self.receive_directory,
+                    self.symlink_policy,
+                )?;
+                Ok(Box::new(writer))
+            }
+            Err(err) => {
+                remove_directories(&created_dirs);
+                Err(err)
+            }
+        }
+    }
+
+    fn available_space(&self, path: &Path) -> Option<u64> {
+        // The nearest existing directory, which can be another mount point
+        let file = self.receive_directory.join(path);
+        let directory = file.ancestors().skip(1).find(|parent| parent.is_dir())?;
+        available_space(directory)
+    }
+}
+
+/// Returns the space available to unprivileged users on the filesystem of
+/// `directory`.
+#[cfg(unix)]
+fn available_space(directory: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(directory.as_os_str().as_bytes()).ok()?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid C string, and `stat` is only read once
+    // statvfs has succeeded and initialized it
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+
+    // The field types differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    Some((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+fn available_space(_directory: &Path) -> Option<u64> {
+    None
+}
+
+/// Creates the missing parent directories of `file` inside of `directory`,
+/// and returns them from the outermost one.
+fn create_directories(file: &Path, directory: &Path, mode: u32) -> Result<Vec<PathBuf>, Error> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, mode);
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    let missing: Vec<&Path> = file
+        .ancestors()
+        .skip(1)
+        .take_while(|parent| *parent != directory && !parent.exists())
+        .collect();
+
+    let mut created = Vec::new();
+    for parent in missing.into_iter().rev() {
+        match builder.create(parent) {
+            Ok(()) => created.push(parent.to_path_buf()),
+            // Created by a concurrent transfer
+            Err(err) if err.kind() == ErrorKind::AlreadyExists && parent.is_dir() => {}
+            Err(err) => {
+                remove_directories(&created);
+                return Err(err.into());
+            }
+        }
+    }
+
+    Ok(created)
+}
+
+/// Removes the `directories` created for a file, from the innermost one.
+/// Directories other transfers have written to are not empty, and are kept.
+fn remove_directories(directories: &[PathBuf]) {
+    for directory in directories.iter().rev() {
+        if fs::remove_dir(directory).is_err() {
+            break;
+        }
+    }
+}
+
+/// Returns the options opening the files of a [`DirectoryProvider`]. The
+/// special files are opened without blocking, to be refused once opened.
+fn open_options(policy: SymlinkPolicy) -> OpenOptions {
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let no_follow = match policy {
+            SymlinkPolicy::Refuse => libc::O_NOFOLLOW,
+            _ => 0,
+        };
+        options.custom_flags(libc::O_NONBLOCK | no_follow);
+    }
+    #[cfg(not(unix))]
+    let _ = policy;
+
+    options
+}
+
+/// Checks that the opened `file` is a regular file, and that `path` is still
+/// valid and leads to it, as the path may have been replaced since it was
+/// checked. Returns the metadata of the file.
+fn check_opened(
+    file: &File,
+    path: &Path,
+    directory: &Path,
+    policy: SymlinkPolicy,
+) -> Result<fs::Metadata, Error> {
+    let opened = file.metadata()?;
+    let unchanged = opened.is_file()
+        && validate_file_path(path, directory, policy)
+        && fs::metadata(path).is_ok_and(|metadata| is_same_file(&metadata, &opened));
+    if !unchanged {
+        return Err(Error::AccessDenied(format!(
+            "{} changed while being opened",
+            path.display()
+        )));
+    }
+
+    Ok(opened)
+}
+
+#[cfg(unix)]
+fn is_same_file(first: &fs::Metadata, second: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    first.dev() == second.dev() && first.ino() == second.ino()
+}
+
+/// Without inode numbers, only the path is checked again.
+#[cfg(not(unix))]
+fn is_same_file(_first: &fs::Metadata, _second: &fs::Metadata) -> bool {
+    true
+}
+
+/// Returns `path` relative to `directory` once its symbolic links are
+/// resolved, or `None` if it leads out of the directory.
+pub(crate) fn resolve_path(directory: &Path, path: &Path) -> Option<PathBuf> {
+    let root = directory.canonicalize().ok()?;
+    let file = directory.join(path);
+    // The missing components are created as plain files and directories
+    let (resolved, missing) = file.ancestors().find_map(|ancestor| {
+        let resolved = ancestor.canonicalize().ok()?;
+        Some((resolved, file.strip_prefix(ancestor).ok()?))
+    })?;
+
+    Some(resolved.strip_prefix(root).ok()?.join(missing))
+}
+
+fn check_file_exists(file: &Path, directory: &Path, policy: SymlinkPolicy) -> ErrorCode {
+    // Partially received files are neither sent nor replaced
+    if !validate_file_path(file, directory, policy) || is_temp_file(file) {
+        return ErrorCode::AccessViolation;
+    }
+
+    match fs::metadata(file) {
+        Ok(metadata) if metadata.is_file() => ErrorCode::FileExists,
+        // Directories, devices, FIFOs and sockets
+        Ok(_) => ErrorCode::AccessViolation,
+        Err(_) => ErrorCode::FileNotFound,
+    }
+}
+
+/// Returns `true` if `file` is named like the temporary files of
+/// [`FileWriter`], `.{name}.{pid}-{counter}.tmp`.
+fn is_temp_file(file: &Path) -> bool {
+    let Some(name) = file.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let Some(stem) = name
+        .strip_prefix('.')
+        .and_then(|name| name.strip_suffix(".tmp"))
+    else {
+        return false;
+    };
+    let is_number = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+
+    stem.rsplit_once('.')
+        .and_then(|(_, suffix)| suffix.split_once('-'))
+        .is_some_and(|(pid, counter)| is_number(pid) && is_number(counter))
+}
+
+fn validate_file_path(file: &Path, directory: &Path, policy: SymlinkPolicy) -> bool {
+    let Ok(relative) = file.strip_prefix(directory) else {
+        return false;
+    };
+    // Names such as `v1..2.bin` are allowed, only `..` components escape
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return false;
+    }
+
+    match policy {
+        SymlinkPolicy::Follow => true,
+        SymlinkPolicy::FollowWithinRoot => {
+            let Ok(root) = directory.canonicalize() else {
+                return false;
+            };
+            // The deepest existing ancestor holds all the links of the path,
+            // the missing components are created as plain files and directories
+            file.ancestors()
+                .find_map(|ancestor| ancestor.canonicalize().ok())
+                .is_some_and(|resolved| resolved.starts_with(root))
+        }
+        SymlinkPolicy::Refuse => {
+            let mut path = directory.to_path_buf();
+            for component in relative.components() {
+                path.push(component);
+                match fs::symlink_metadata(&path) {
+                    Ok(metadata) if metadata.file_type().is_symlink() => return false,
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_file_path() {
+        assert!(validate_file_path(
+            &PathBuf::from("/dir/test/file"),
+            &PathBuf::from("/dir/test"),
+            SymlinkPolicy::Follow
+        ));
+
+        assert!(validate_file_path(
+            &PathBuf::from("/dir/test/v1..2.bin"),
+            &PathBuf::from("/dir/test"),
+            SymlinkPolicy::Follow
+        ));
+
+        assert!(!validate_file_path(
+            &PathBuf::from("/system/data.txt"),
+            &PathBuf::from("/dir/test"),
+            SymlinkPolicy::Follow
+        ));
+
+        assert!(!validate_file_path(
+            &PathBuf::from("~/some_data.txt"),
+            &PathBuf::from("/dir/test"),
+            SymlinkPolicy::Follow
+        ));
+
+        assert!(!validate_file_path(
+            &PathBuf::from("/dir/test/../file"),
+            &PathBuf::from("/dir/test"),
+            SymlinkPolicy::Follow
+        ));
+
+        assert!(!validate_file_path(
+            &PathBuf::from("/dir/test/sub/../../file"),
+            &PathBuf::from("/dir/test"),
+            SymlinkPolicy::Follow
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn applies_symlink_policies() {
+        use std::os::unix::fs::symlink;
+
+        const DIR_NAME: &str = "target/test/applies_symlink_policies";
+        let _ = fs::remove_dir_all(DIR_NAME);
+        let root = Path::new(DIR_NAME).join("root");
+        let outside = Path::new(DIR_NAME).join("outside");
+        fs::create_dir_all(root.join("images")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(root.join("images/v1..2.bin"), "image").unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        symlink("images/v1..2.bin", root.join("latest.bin")).unwrap();
+        symlink("../outside/secret.txt", root.join("secret.txt")).unwrap();
+        symlink("../outside", root.join("outside")).unwrap();
+
+        let mut provider = DirectoryProvider::new(root.clone(), root.clone());
+        let check = |provider: &DirectoryProvider, path: &str| provider.check_read(Path::new(path));
+
+        // Follow within root, by default
+        assert_eq!(check(&provider, "images/v1..2.bin"), ErrorCode::FileExists);
+        assert_eq!(check(&provider, "latest.bin"), ErrorCode::FileExists);
+        assert_eq!(check(&provider, "secret.txt"), ErrorCode::AccessViolation);
+        assert_eq!(
+            check(&provider, "outside/secret.txt"),
+            ErrorCode::AccessViolation
+        );
+        assert_eq!(
+            check(&provider, "images/missing.bin"),
+            ErrorCode::FileNotFound
+        );
+        assert_eq!(
+            provider.check_write(Path::new("outside/new.txt")),
+            ErrorCode::AccessViolation
+        );
+        assert_eq!(
+            provider.check_write(Path::new("new/dir/file.txt")),
+            ErrorCode::FileNotFound
+        );
+
+        provider.set_symlink_policy(SymlinkPolicy::Refuse);
+        assert_eq!(check(&provider, "images/v1..2.bin"), ErrorCode::FileExists);
+        assert_eq!(check(&provider, "latest.bin"), ErrorCode::AccessViolation);
+        assert_eq!(check(&provider, "secret.txt"), ErrorCode::AccessViolation);
+        assert_eq!(
+            provider.check_write(Path::new("outside/new.txt")),
+            ErrorCode::AccessViolation
+        );
+
+        provider.set_symlink_policy(SymlinkPolicy::Follow);
+        assert_eq!(check(&provider, "latest.bin"), ErrorCode::FileExists);
+        assert_eq!(check(&provider, "secret.txt"), ErrorCode::FileExists);
+        assert_eq!(
+            check(&provider, "outside/secret.txt"),
+            ErrorCode::FileExists
+        );
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_special_files() {
+        use std::os::unix::fs::symlink;
+        use std::os::unix::net::UnixListener;
+
+        const DIR_NAME: &str = "target/test/refuses_special_files";
+        let _ = fs::remove_dir_all(DIR_NAME);
+        fs::create_dir_all(Path::new(DIR_NAME).join("directory")).unwrap();
+        let _listener = UnixListener::bind(Path::new(DIR_NAME).join("socket")).unwrap();
+        symlink("/dev/null", Path::new(DIR_NAME).join("device")).unwrap();
+
+        let mut provider = DirectoryProvider::new(PathBuf::from(DIR_NAME), PathBuf::from(DIR_NAME));
+        provider.set_symlink_policy(SymlinkPolicy::Follow);
+        for path in ["directory", "socket", "device"] {
+            assert_eq!(
+                provider.check_read(Path::new(path)),
+                ErrorCode::AccessViolation
+            );
+            assert_eq!(
+                provider.check_write(Path::new(path)),
+                ErrorCode::AccessViolation
+            );
+        }
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn checks_opened_files() {
+        use std::ffi::CString;
+        use std::os::unix::fs::symlink;
+
+        const DIR_NAME: &str = "target/test/checks_opened_files";
+        let _ = fs::remove_dir_all(DIR_NAME);
+        let root = Path::new(DIR_NAME).join("root");
+        let outside = Path::new(DIR_NAME).join("outside");
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(root.join("file.txt"), "file").unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        symlink("file.txt", root.join("latest.txt")).unwrap();
+        // Links replacing the checked paths before they are opened
+        symlink("../outside/secret.txt", root.join("secret.txt")).unwrap();
+        symlink("../outside", root.join("outside")).unwrap();
+        let fifo = CString::new(format!("{DIR_NAME}/root/fifo")).unwrap();
+        // SAFETY: `fifo` is a valid C string
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+
+        let mut provider = DirectoryProvider::new(root.clone(), root.clone());
+        let opens =
+            |provider: &DirectoryProvider, path: &str| provider.open(Path::new(path)).is_ok();
+        assert!(opens(&provider, "file.txt"));
+        assert!(opens(&provider, "latest.txt"));
+        assert!(!opens(&provider, "secret.txt"));
+        assert!(provider.create(Path::new("outside/new.txt")).is_err());
+        assert_eq!(fs::read_dir(&outside).unwrap().count(), 1);
+
+        provider.set_symlink_policy(SymlinkPolicy::Refuse);
+        assert!(!opens(&provider, "latest.txt"));
+
+        // Special files are refused without blocking
+        provider.set_symlink_policy(SymlinkPolicy::Follow);
+        assert!(opens(&provider, "secret.txt"));
+        assert!(!opens(&provider, "fifo"));
+
+        // A file replaced after being opened
+        let file = File::open(root.join("file.txt")).unwrap();
+        let policy = SymlinkPolicy::Follow;
+        assert!(check_opened(&file, &root.join("latest.txt"), &root, policy).is_ok());
+        assert!(check_opened(&file, &root.join("secret.txt"), &root, policy).is_err());
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+
+    #[test]
+    fn converts_generated_files() {
+        let (_, size) = GeneratedFile::Bytes(b"a\nb".to_vec())
+            .into_source(TransferMode::Octet)
+            .unwrap();
+        assert_eq!(size, Some(3));
+
+        let (_, size) = GeneratedFile::Bytes(b"a\nb".to_vec())
+            .into_source(TransferMode::Netascii)
+            .unwrap();
+        assert_eq!(size, Some(4));
+
+        let reader = Box::new(Cursor::new(b"a\nb".to_vec()));
+        let (_, size) = GeneratedFile::Reader(reader, Some(3))
+            .into_source(TransferMode::Octet)
+            .unwrap();
+        assert_eq!(size, Some(3));
+
+        let reader = Box::new(Cursor::new(b"a\nb".to_vec()));
+        let (mut source, size) = GeneratedFile::Reader(reader, Some(3))
+            .into_source(TransferMode::Netascii)
+            .unwrap();
+        assert_eq!(size, Some(4));
+        let mut data = Vec::new();
+        source.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"a\nb");
+
+        let reader = Box::new(Cursor::new(b"a\nb".to_vec()));
+        let (_, size) = GeneratedFile::Reader(reader, None)
+            .into_source(TransferMode::Netascii)
+            .unwrap();
+        assert_eq!(size, None);
+    }
+
+    #[test]
+    fn creates_and_discards_files() {
+        const DIR_NAME: &str = "target/test/creates_and_discards_files";
+        let _ = fs::create_dir_all(DIR_NAME);
+
+        let provider = DirectoryProvider::new(PathBuf::from(DIR_NAME), PathBuf::from(DIR_NAME));
+        let path = Path::new("file.txt");
+        assert_eq!(provider.check_write(path), ErrorCode::FileNotFound);
+
+        let mut file = provider.create(path).unwrap();
+        file.write_all(b"Hello, world!").unwrap();
+        file.commit().unwrap();
+        assert_eq!(provider.check_write(path), ErrorCode::FileExists);
+
+        let (mut file, size) = provider.open(path).unwrap();
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "Hello, world!");
+        assert_eq!(size, Some(13));
+
+        // The file is only replaced once the new one is committed
+        let mut file = provider.create(path).unwrap();
+        file.write_all(b"Partial").unwrap();
+        assert_eq!(
+            fs::read(Path::new(DIR_NAME).join(path)).unwrap(),
+            b"Hello, world!"
+        );
+        // Nor is the temporary file served
+        let temp_file = fs::read_dir(DIR_NAME)
+            .unwrap()
+            .map(|entry| PathBuf::from(entry.unwrap().file_name()))
+            .find(|name| name != path)
+            .unwrap();
+        assert!(is_temp_file(&temp_file));
+        assert_eq!(provider.check_read(&temp_file), ErrorCode::AccessViolation);
+        assert_eq!(provider.check_write(&temp_file), ErrorCode::AccessViolation);
+        assert!(!is_temp_file(Path::new(".notes.txt")));
+        assert!(!is_temp_file(Path::new(".file.txt.12-a.tmp")));
+        file.discard().unwrap();
+        assert_eq!(
+            fs::read(Path::new(DIR_NAME).join(path)).unwrap(),
+            b"Hello, world!"
+        );
+
+        // Dropped without being committed, as when errors are kept
+        let mut file = provider.create(path).unwrap();
+        file.write_all(b"Partial").unwrap();
+        drop(file);
+        assert_eq!(fs::read_dir(DIR_NAME).unwrap().count(), 1);
+
+        let mut file = provider.create(path).unwrap();
+        file.write_all(b"Replaced").unwrap();
+        file.commit().unwrap();
+        assert_eq!(
+            fs::read(Path::new(DIR_NAME).join(path)).unwrap(),
+            b"Replaced"
+        );
+
+        fs::remove_file(Path::new(DIR_NAME).join(path)).unwrap();
+        fs::remove_dir(DIR_NAME).unwrap();
+    }
+
+    #[test]
+    fn keeps_files_received_meanwhile() {
+        const DIR_NAME: &str = "target/test/keeps_files_received_meanwhile";
+        let _ = fs::remove_dir_all(DIR_NAME);
+        fs::create_dir_all(DIR_NAME).unwrap();
+
+        let mut provider = DirectoryProvider::new(PathBuf::from(DIR_NAME), PathBuf::from(DIR_NAME));
+        provider.set_overwrite(false);
+        let path = Path::new("file.txt");
+
+        // Both requests are accepted before either file is committed
+        let mut first = provider.create(path).unwrap();
+        let mut second = provider.create(path).unwrap();
+        first.write_all(b"first").unwrap();
+        second.write_all(b"second").unwrap();
+        first.commit().unwrap();
+        assert!(matches!(
+            second.commit(),
+            Err(Error::Protocol(ErrorCode::FileExists, _))
+        ));
+        assert_eq!(fs::read(Path::new(DIR_NAME).join(path)).unwrap(), b"first");
+        assert_eq!(fs::read_dir(DIR_NAME).unwrap().count(), 1);
+
+        provider.set_overwrite(true);
+        let mut file = provider.create(path).unwrap();
+        file.write_all(b"third").unwrap();
+        file.commit().unwrap();
+        assert_eq!(fs::read(Path::new(DIR_NAME).join(path)).unwrap(), b"third");
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+
+    #[test]
+    fn creates_missing_directories() {
+        const DIR_NAME: &str = "target/test/creates_missing_directories";
+        let _ = fs::remove_dir_all(DIR_NAME);
+        fs::create_dir_all(format!("{DIR_NAME}/logs")).unwrap();
+
+        let mut provider = DirectoryProvider::new(PathBuf::from(DIR_NAME), PathBuf::from(DIR_NAME));
+        let path = Path::new("logs/switch42/today/config.txt");
+        assert_eq!(provider.check_write(path), ErrorCode::FileNotFound);
+        assert!(provider.create(path).is_err());
+        #[cfg(unix)]
+        assert!(provider.available_space(path).is_some());
+
+        provider.set_create_dirs(Some(0o750));
+        let file = provider.create(path).unwrap();
+        assert!(Path::new(DIR_NAME).join("logs/switch42/today").is_dir());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(Path::new(DIR_NAME).join("logs/switch42")).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777 & !0o750, 0);
+        }
+
+        // Only the created directories are removed when the transfer fails
+        file.discard().unwrap();
+        assert!(!Path::new(DIR_NAME).join("logs/switch42").exists());
+        assert!(Path::new(DIR_NAME).join("logs").is_dir());
+
+        let file = provider.create(path).unwrap();
+        drop(file);
+        assert!(!Path::new(DIR_NAME).join("logs/switch42").exists());
+
+        let mut file = provider.create(path).unwrap();
+        file.write_all(b"hostname switch42").unwrap();
+        file.commit().unwrap();
+        assert_eq!(
+            fs::read(Path::new(DIR_NAME).join(path)).unwrap(),
+            b"hostname switch42"
+        );
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+}
@@ -0,0 +1,311 @@
+use std::fs;
+use std::path::Path;
+
+use regex_lite::{Captures, Regex};
+
+use crate::Error;
+
+/// Maximum amount of rule applications, to break `restart` loops
+const MAX_REMAP_STEPS: usize = 4096;
+
+/// RemapRules `struct` holds an ordered list of filename remapping rules,
+/// applied to the requested filenames before they are looked up. The rules
+/// file format follows the `-m` option of tftp-hpa, where each line has the
+/// form `<flags> <regex> [replacement]`, and `#` starts a comment.
+///
+/// The supported flags are:
+///
+/// - `r`: rewrite the matched part of the filename with the replacement,
+///   where `\0` is the whole match and `\1` to `\9` are the capture groups
+/// - `g`: rewrite every match instead of only the first one
+/// - `i`: match case-insensitively
+/// - `e`: stop processing the rules if this rule matches
+/// - `s`: restart from the first rule if this rule matches
+/// - `a`: deny the request if this rule matches
+/// - `G`: only apply this rule to read requests
+/// - `P`: only apply this rule to write requests
+///
+/// # Example
+///
+/// ```rust
+/// use tftpd::RemapRules;
+///
+/// let rules = RemapRules::parse(
+///     r"
+///     rg  \\          /
+///     re  ^/tftpboot/
+///     a   \.\./
+///     ",
+/// )
+/// .unwrap();
+///
+/// assert_eq!(rules.apply(r"\tftpboot\pxelinux.0", false), Some("pxelinux.0".to_string()));
+/// assert_eq!(rules.apply("../etc/passwd", false), None);
+/// ```
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct RemapRules {
+    rules: Vec<RemapRule>,
+}
+
+#[derive(Debug, Clone)]
+struct RemapRule {
+    regex: Regex,
+    replacement: Option<String>,
+    global: bool,
+    stop: bool,
+    restart: bool,
+    deny: bool,
+    read: bool,
+    write: bool,
+}
+
+impl RemapRules {
+    /// Reads and parses the rules file at `path`.
+    pub fn from_file(path: &Path) -> Result<RemapRules, Error> {
+        let contents = fs::read_to_string(path).map_err(|err| {
+            Error::Config(format!(
+                "Could not read remap file {}: {err}",
+                path.display()
+            ))
+        })?;
+
+        RemapRules::parse(&contents)
+    }
+
+    /// Parses the rules from the contents of a rules file.
+    pub fn parse(contents: &str) -> Result<RemapRules, Error> {
+        let mut rules = vec![];
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            rules.push(RemapRule::parse(line).map_err(|err| {
+                Error::Config(format!("Invalid remap rule on line {}: {err}", index + 1))
+            })?);
+        }
+
+        Ok(RemapRules { rules })
+    }
+
+    /// Returns `true` if there are no rules.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Applies the rules to `filename`, for a write request if `write` is
+    /// `true` and a read request otherwise. Returns the remapped filename,
+    /// or [`None`] if a rule denied the request.
+    pub fn apply(&self, filename: &str, write: bool) -> Option<String> {
+        let mut filename = filename.to_string();
+        let mut index = 0;
+        let mut steps = 0;
+
+        while let Some(rule) = self.rules.get(index) {
+            index += 1;
+            if (write && !rule.write) || (!write && !rule.read) {
+                continue;
+            }
+            if !rule.regex.is_match(&filename) {
+                continue;
+            }
+
+            if rule.deny {
+                return None;
+            }
+            if let Some(replacement) = &rule.replacement {
+                let limit = if rule.global { 0 } else { 1 };
+                filename = rule
+                    .regex
+                    .replacen(&filename, limit, |caps: &Captures| {
+                        expand(replacement, caps)
+                    })
+                    .into_owned();
+            }
+            if rule.stop {
+                break;
+            }
+            if rule.restart {
+                steps += 1;
+                if steps >= MAX_REMAP_STEPS {
+                    break;
+                }
+                index = 0;
+            }
+        }
+
+        Some(filename)
+    }
+}
+
+impl PartialEq for RemapRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.regex.as_str() == other.regex.as_str()
+            && self.replacement == other.replacement
+            && self.global == other.global
+            && self.stop == other.stop
+            && self.restart == other.restart
+            && self.deny == other.deny
+            && self.read == other.read
+            && self.write == other.write
+    }
+}
+
+impl RemapRule {
+    fn parse(line: &str) -> Result<RemapRule, String> {
+        let mut parts = line.split_whitespace();
+        let (Some(flags), Some(pattern)) = (parts.next(), parts.next()) else {
+            return Err("missing regular expression".to_string());
+        };
+        let replacement = parts.next();
+        if parts.next().is_some() {
+            return Err("too many fields".to_string());
+        }
+
+        let mut rule_flags = [false; 8];
+        for flag in flags.chars() {
+            match "rgiesaGP".find(flag) {
+                Some(position) => rule_flags[position] = true,
+                None if flag == '-' => {}
+                None => return Err(format!("unknown flag '{flag}'")),
+            }
+        }
+        let [rewrite, global, insensitive, stop, restart, deny, read, write] = rule_flags;
+
+        let replacement = match (rewrite, replacement) {
+            (true, Some(replacement)) => Some(replacement.to_string()),
+            (true, None) => Some(String::new()),
+            (false, Some(_)) => return Err("replacement without the 'r' flag".to_string()),
+            (false, None) => None,
+        };
+
+        let pattern = if insensitive {
+            format!("(?i){pattern}")
+        } else {
+            pattern.to_string()
+        };
+        let regex = Regex::new(&pattern).map_err(|err| err.to_string())?;
+
+        Ok(RemapRule {
+            regex,
+            replacement,
+            global,
+            stop,
+            restart,
+            deny,
+            // Rules apply to both request types unless restricted
+            read: read || !write,
+            write: write || !read,
+        })
+    }
+}
+
+fn expand(replacement: &str, caps: &Captures) -> String {
+    let mut expanded = String::new();
+    let mut chars = replacement.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            expanded.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some(digit @ '0'..='9') => {
+                let group = digit.to_digit(10).unwrap() as usize;
+                if let Some(capture) = caps.get(group) {
+                    expanded.push_str(capture.as_str());
+                }
+            }
+            Some(other) => expanded.push(other),
+            None => expanded.push('\\'),
+        }
+    }
+
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrites_filenames() {
+        let rules = RemapRules::parse(
+            r"
+            # comment
+            rgi  FOO       bar
+            r    ^/+
+            r    (\w+)\.img  images/\1.img
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            rules.apply("/Foo/foo.txt", false),
+            Some("bar/bar.txt".to_string())
+        );
+        assert_eq!(
+            rules.apply("//kernel.img", true),
+            Some("images/kernel.img".to_string())
+        );
+    }
+
+    #[test]
+    fn stops_and_denies() {
+        let rules = RemapRules::parse(
+            r"
+            e   ^boot/
+            a   \.\.
+            aP  ^readonly/
+            rG  ^config$   config.default
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(rules.apply("boot/..", false), Some("boot/..".to_string()));
+        assert_eq!(rules.apply("a/../b", false), None);
+        assert_eq!(rules.apply("readonly/file", true), None);
+        assert_eq!(
+            rules.apply("readonly/file", false),
+            Some("readonly/file".to_string())
+        );
+        assert_eq!(
+            rules.apply("config", false),
+            Some("config.default".to_string())
+        );
+        assert_eq!(rules.apply("config", true), Some("config".to_string()));
+    }
+
+    #[test]
+    fn restarts_rules() {
+        let rules = RemapRules::parse(
+            r"
+            r   ^done$  end
+            rs  ^x
+            r   ^$      done
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(rules.apply("xxx", false), Some("done".to_string()));
+        assert_eq!(rules.apply("xa", false), Some("a".to_string()));
+
+        let looping = RemapRules::parse(r"rs  ^  x").unwrap();
+        assert_eq!(looping.apply("", false).unwrap().len(), MAX_REMAP_STEPS);
+    }
+
+    #[test]
+    fn reports_invalid_rules() {
+        let err = RemapRules::parse("r a b\nq a").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid remap rule on line 2: unknown flag 'q'"
+        );
+        assert!(RemapRules::parse("r").is_err());
+        assert!(RemapRules::parse("e a b").is_err());
+        assert!(RemapRules::parse("r ( b").is_err());
+    }
+}
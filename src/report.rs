@@ -0,0 +1,73 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::options::OptionsProtocol;
+use crate::Error;
+
+/// TransferReport `struct` describes how a transfer went. It is returned by
+/// the [`crate::Worker`] and the client once the transfer has finished, and
+/// is given to the [`crate::Observer`] of the server.
+///
+/// # Example
+///
+/// ```rust
+/// use tftpd::TransferReport;
+///
+/// let report = TransferReport {
+///     bytes: 1024,
+///     blocks: 2,
+///     ..Default::default()
+/// };
+///
+/// assert!(report.is_success());
+/// println!("{report}");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TransferReport {
+    /// Amount of data transferred, as sent on the wire
+    pub bytes: u64,
+    /// Amount of data blocks transferred
+    pub blocks: u64,
+    /// Options negotiated for the transfer
+    pub options: OptionsProtocol,
+    /// Amount of packets sent again, after a timeout or a loss
+    pub retransmits: u64,
+    /// Amount of expired timeouts
+    pub timeouts: u64,
+    /// Amount of acknowledgements received again, when sending
+    pub duplicate_acks: u64,
+    /// Time spent from the start to the end of the transfer
+    pub elapsed: Duration,
+    /// Error which ended the transfer, if it failed
+    pub error: Option<Error>,
+}
+
+impl TransferReport {
+    /// Returns `true` if the transfer completed.
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+impl fmt::Display for TransferReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes in {} blocks in {:.3}s, blksize {}, windowsize {}, \
+            {} retransmissions, {} timeouts, {} duplicate acks",
+            self.bytes,
+            self.blocks,
+            self.elapsed.as_secs_f64(),
+            self.options.block_size,
+            self.options.window_size,
+            self.retransmits,
+            self.timeouts,
+            self.duplicate_acks
+        )?;
+        if let Some(err) = &self.error {
+            write!(f, ", failed: {err}")?;
+        }
+
+        Ok(())
+    }
+}
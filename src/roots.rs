@@ -0,0 +1,123 @@
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use crate::IpNetwork;
+
+/// ClientRoots `struct` holds the directories sending and receiving the
+/// files of the clients of some networks, instead of the send and receive
+/// directories of the server. The directories of the most specific network
+/// holding a client apply, and clients of no network use the server ones.
+///
+/// # Example
+///
+/// ```rust
+/// use std::path::Path;
+/// use tftpd::ClientRoots;
+///
+/// let mut roots = ClientRoots::default();
+/// roots.add_send_directory("10.1.0.0/16".parse().unwrap(), "/srv/tftp/lab".into());
+/// roots.add_send_directory("10.1.2.0/24".parse().unwrap(), "/srv/tftp/team2".into());
+/// roots.add_receive_directory("10.1.0.0/16".parse().unwrap(), "/srv/tftp/uploads".into());
+///
+/// let client = "10.1.2.3".parse().unwrap();
+/// assert_eq!(roots.send_directory(&client), Some(Path::new("/srv/tftp/team2")));
+/// assert_eq!(roots.receive_directory(&client), Some(Path::new("/srv/tftp/uploads")));
+/// assert_eq!(roots.send_directory(&"10.2.0.1".parse().unwrap()), None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientRoots {
+    send: Vec<(IpNetwork, PathBuf)>,
+    receive: Vec<(IpNetwork, PathBuf)>,
+}
+
+impl ClientRoots {
+    /// Sends the files requested by the clients of `network` from
+    /// `directory`, replacing its previous send directory.
+    pub fn add_send_directory(&mut self, network: IpNetwork, directory: PathBuf) {
+        add_directory(&mut self.send, network, directory);
+    }
+
+    /// Receives the files sent by the clients of `network` to `directory`,
+    /// replacing its previous receive directory.
+    pub fn add_receive_directory(&mut self, network: IpNetwork, directory: PathBuf) {
+        add_directory(&mut self.receive, network, directory);
+    }
+
+    /// Returns the directory sending files to the client `address`, if any.
+    pub fn send_directory(&self, address: &IpAddr) -> Option<&Path> {
+        find_directory(&self.send, address)
+    }
+
+    /// Returns the directory receiving files from the client `address`, if any.
+    pub fn receive_directory(&self, address: &IpAddr) -> Option<&Path> {
+        find_directory(&self.receive, address)
+    }
+
+    /// Returns `true` if no network has its own directories.
+    pub fn is_empty(&self) -> bool {
+        self.send.is_empty() && self.receive.is_empty()
+    }
+}
+
+fn add_directory(
+    directories: &mut Vec<(IpNetwork, PathBuf)>,
+    network: IpNetwork,
+    directory: PathBuf,
+) {
+    directories.retain(|(other, _)| *other != network);
+    directories.push((network, directory));
+}
+
+fn find_directory<'a>(
+    directories: &'a [(IpNetwork, PathBuf)],
+    address: &IpAddr,
+) -> Option<&'a Path> {
+    directories
+        .iter()
+        .filter(|(network, _)| network.contains(address))
+        .max_by_key(|(network, _)| network.prefix())
+        .map(|(_, directory)| directory.as_path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn selects_most_specific_network() {
+        let mut roots = ClientRoots::default();
+        assert!(roots.is_empty());
+        assert_eq!(roots.send_directory(&ip("10.0.0.1")), None);
+
+        roots.add_send_directory("10.0.0.0/8".parse().unwrap(), "wide".into());
+        roots.add_send_directory("10.1.0.0/16".parse().unwrap(), "narrow".into());
+        roots.add_send_directory("fd00::/8".parse().unwrap(), "v6".into());
+        assert!(!roots.is_empty());
+
+        assert_eq!(
+            roots.send_directory(&ip("10.2.0.1")),
+            Some(Path::new("wide"))
+        );
+        assert_eq!(
+            roots.send_directory(&ip("10.1.0.1")),
+            Some(Path::new("narrow"))
+        );
+        assert_eq!(
+            roots.send_directory(&ip("::ffff:10.1.0.1")),
+            Some(Path::new("narrow"))
+        );
+        assert_eq!(roots.send_directory(&ip("fd00::1")), Some(Path::new("v6")));
+        assert_eq!(roots.send_directory(&ip("192.168.0.1")), None);
+        assert_eq!(roots.receive_directory(&ip("10.1.0.1")), None);
+
+        roots.add_send_directory("10.1.0.0/16".parse().unwrap(), "replaced".into());
+        assert_eq!(
+            roots.send_directory(&ip("10.1.0.1")),
+            Some(Path::new("replaced"))
+        );
+    }
+}
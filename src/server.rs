@@ -1,25 +1,38 @@
 use std::cmp::max;
-use std::collections::HashMap;
-use std::error::Error;
-use std::net::{SocketAddr, UdpSocket};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::io::{ErrorKind, Read};
+use std::mem;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
 use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
-use std::sync::{atomic::AtomicBool, Arc};
-use std::time::Duration;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
+use crate::event_loop::EventLoop;
+use crate::observer::TransferTracker;
 #[cfg(debug_assertions)]
 use crate::options::OptionFmt;
 use crate::options::{OptionsPrivate, OptionsProtocol, DEFAULT_BLOCK_SIZE};
-use crate::{log::*, ServerSocket, Socket, TransferOption, Worker};
-use crate::{Config, ErrorCode, Packet};
-
-#[cfg(test)]
-use crate::OptionType;
+use crate::provider::{resolve_path, ReadHook};
+use crate::{
+    log::*, AccessList, ClientRoots, Direction, DirectoryProvider, FileProvider, FileSink,
+    GeneratedFile, Observer, ReceiveTransfer, RemapRules, SendTransfer, ServerSocket, Socket,
+    SymlinkPolicy, TransferInfo, TransferOption, TransferReport, UploadLimits, Worker,
+};
+use crate::{Config, Error, ErrorCode, OptionType, Packet, TransferMode};
 
 /// Server `struct` is used for handling incoming TFTP requests.
 ///
-/// This `struct` is meant to be created by [`Server::new()`]. See its
-/// documentation for more.
+/// This `struct` is meant to be created by [`Server::new()`], or by a
+/// [`crate::ServerBuilder`] when embedding the server. See their
+/// documentation for more. Files are served by a [`DirectoryProvider`]
+/// unless another [`FileProvider`] is set with [`Server::set_provider()`].
+///
+/// Each transfer is driven by a [`Worker`] thread, or, in event loop mode,
+/// by the listening thread itself.
 ///
 /// # Example
 ///
@@ -33,89 +46,365 @@ use crate::OptionType;
 /// ```
 pub struct Server {
     socket: UdpSocket,
-    receive_directory: PathBuf,
-    send_directory: PathBuf,
+    provider: Box<dyn FileProvider>,
+    directories: Option<(PathBuf, PathBuf)>,
+    create_dirs: Option<u32>,
+    symlink_policy: SymlinkPolicy,
+    client_roots: ClientRoots,
+    read_hooks: Vec<Box<ReadHook>>,
+    observer: Option<Arc<dyn Observer>>,
     single_port: bool,
+    event_loop: bool,
     read_only: bool,
     overwrite: bool,
+    remap_rules: RemapRules,
+    read_acl: AccessList,
+    write_acl: AccessList,
+    upload_limits: UploadLimits,
     largest_block_size: u16,
     clients: HashMap<SocketAddr, Sender<Packet>>,
     opt_local: OptionsPrivate,
+    workers: Vec<(SocketAddr, JoinHandle<TransferReport>)>,
+    sessions: EventLoop,
+    queue: VecDeque<QueuedRequest>,
+    max_transfers: Option<usize>,
+    max_client_transfers: Option<usize>,
+    queue_timeout: Duration,
+    completed: usize,
+    failed: usize,
+    drain_timeout: Duration,
     abort: Arc<AtomicBool>,
+    shutdown: Arc<AtomicBool>,
+    reload: Arc<AtomicBool>,
+    config_loader: Option<Box<ConfigLoader>>,
+}
+
+type ConfigLoader = dyn Fn() -> Result<Config, Error> + Send;
+
+// Bounds the memory used by a flood of requests
+const MAX_QUEUED_REQUESTS: usize = 256;
+// To check the flags every second
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+// A zero read timeout is invalid
+const MIN_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Outcome of the checks of a request, see [`Server::check_request()`].
+pub(crate) enum Decision {
+    /// Refuse the request with the error packet
+    Refuse(Packet),
+    /// Drop the request without answering
+    Ignore,
+    /// Send the file
+    Send(Accepted, Box<dyn Read + Send>),
+    /// Receive the file
+    Receive(Accepted, Box<dyn FileSink>),
+}
+
+/// Accepted request, before its transfer is started.
+pub(crate) struct Accepted {
+    pub(crate) file_path: PathBuf,
+    pub(crate) mode: TransferMode,
+    pub(crate) options: OptionsProtocol,
+    /// OACK or ACK answering the request, if any
+    pub(crate) reply: Option<Packet>,
+    /// Maximum size of a received file
+    pub(crate) max_size: Option<u64>,
+}
+
+impl Accepted {
+    /// Returns the [`TransferInfo`] of the transfer with `peer`.
+    pub(crate) fn info(&self, peer: SocketAddr, direction: Direction) -> TransferInfo {
+        TransferInfo {
+            file_path: self.file_path.clone(),
+            peer,
+            direction,
+        }
+    }
+
+    /// Returns the local options of the transfer, based on those of the
+    /// server.
+    pub(crate) fn opt_local(&self, opt_local: &OptionsPrivate) -> OptionsPrivate {
+        OptionsPrivate {
+            max_size: self.max_size,
+            ..opt_local.clone()
+        }
+    }
+}
+
+struct QueuedRequest {
+    packet: Packet,
+    from: SocketAddr,
+    deadline: Instant,
 }
 
 impl Server {
     /// Creates the TFTP Server with the supplied [`Config`].
-    pub fn new(config: &Config) -> Result<Server, Box<dyn Error>> {
+    pub fn new(config: &Config) -> Result<Server, Error> {
         let socket = UdpSocket::bind(SocketAddr::from((config.ip_address, config.port)))?;
-        let server = Server {
+
+        Ok(Server::with_socket(socket, config))
+    }
+
+    /// Creates the TFTP Server listening on an already bound `socket`.
+    pub(crate) fn with_socket(socket: UdpSocket, config: &Config) -> Server {
+        Server {
             socket,
-            receive_directory: config.receive_directory.clone(),
-            send_directory: config.send_directory.clone(),
+            provider: Box::new(directory_provider(config)),
+            directories: Some((
+                config.send_directory.clone(),
+                config.receive_directory.clone(),
+            )),
+            create_dirs: config.create_dirs.then_some(config.dir_mode),
+            symlink_policy: config.symlink_policy,
+            client_roots: config.client_roots.clone(),
+            read_hooks: Vec::new(),
+            observer: None,
             single_port: config.single_port,
+            event_loop: config.event_loop,
             read_only: config.read_only,
             overwrite: config.overwrite,
+            remap_rules: config.remap_rules.clone(),
+            read_acl: config.read_acl.clone(),
+            write_acl: config.write_acl.clone(),
+            upload_limits: config.upload_limits.clone(),
             largest_block_size: DEFAULT_BLOCK_SIZE,
             clients: HashMap::new(),
             opt_local: config.opt_local.clone(),
+            workers: Vec::new(),
+            sessions: EventLoop::new(),
+            queue: VecDeque::new(),
+            max_transfers: config.max_transfers,
+            max_client_transfers: config.max_client_transfers,
+            queue_timeout: config.queue_timeout,
+            completed: 0,
+            failed: 0,
+            drain_timeout: config.drain_timeout,
             abort: Arc::new(AtomicBool::new(false)),
-        };
+            shutdown: Arc::new(AtomicBool::new(false)),
+            reload: Arc::new(AtomicBool::new(false)),
+            config_loader: None,
+        }
+    }
 
-        Ok(server)
+    /// Sets the [`FileProvider`] used for sending and receiving files. The
+    /// provider is kept when the configuration is reloaded, and serves all
+    /// clients, regardless of [`Config::client_roots`].
+    pub fn set_provider<P: FileProvider + 'static>(&mut self, provider: P) {
+        self.provider = Box::new(provider);
+        self.directories = None;
+    }
+
+    /// Sets the function loading the [`Config`] when the reload flag is
+    /// raised, see [`Server::get_reload_flag()`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tftpd::{Config, Server};
+    ///
+    /// let args: Vec<String> = ["/", "-p", "1236"].iter().map(|s| s.to_string()).collect();
+    /// let config = Config::new(args.iter().cloned()).unwrap();
+    /// let mut server = Server::new(&config).unwrap();
+    ///
+    /// server.set_config_loader(move || Config::new(args.iter().cloned()));
+    /// ```
+    pub fn set_config_loader<F>(&mut self, loader: F)
+    where
+        F: Fn() -> Result<Config, Error> + Send + 'static,
+    {
+        self.config_loader = Some(Box::new(loader));
+    }
+
+    /// Applies the settings of `config` to the requests received from now
+    /// on, leaving the running transfers untouched, and logs the changed
+    /// settings. The address of the server, the single port mode and the
+    /// event loop cannot be changed, as the running transfers depend on them.
+    pub fn reload(&mut self, config: &Config) {
+        let address = SocketAddr::from((config.ip_address, config.port));
+        if self
+            .socket
+            .local_addr()
+            .is_ok_and(|local_address| local_address != address)
+        {
+            log_warn!("  Cannot change the server address to {address} without a restart");
+        }
+
+        let directories = (
+            config.send_directory.clone(),
+            config.receive_directory.clone(),
+        );
+        match &self.directories {
+            Some(current) if *current != directories => {
+                log_warn!(
+                    "  directories: {} and {} -> {} and {}",
+                    current.0.display(),
+                    current.1.display(),
+                    directories.0.display(),
+                    directories.1.display()
+                );
+            }
+            _ => {}
+        }
+        // A custom provider is kept, the default one takes the new settings
+        if self.directories.is_some() {
+            self.provider = Box::new(directory_provider(config));
+            self.directories = Some(directories);
+        }
+        update_setting(
+            "create_dirs",
+            &mut self.create_dirs,
+            &config.create_dirs.then_some(config.dir_mode),
+        );
+        update_setting(
+            "symlink_policy",
+            &mut self.symlink_policy,
+            &config.symlink_policy,
+        );
+
+        if self.single_port != config.single_port {
+            log_warn!("  Cannot change single_port without a restart");
+        }
+        if self.event_loop != config.event_loop {
+            log_warn!("  Cannot change event_loop without a restart");
+        }
+        update_setting("read_only", &mut self.read_only, &config.read_only);
+        update_setting("overwrite", &mut self.overwrite, &config.overwrite);
+        update_setting("opt_local", &mut self.opt_local, &config.opt_local);
+        update_setting(
+            "max_transfers",
+            &mut self.max_transfers,
+            &config.max_transfers,
+        );
+        update_setting(
+            "max_client_transfers",
+            &mut self.max_client_transfers,
+            &config.max_client_transfers,
+        );
+        update_setting(
+            "queue_timeout",
+            &mut self.queue_timeout,
+            &config.queue_timeout,
+        );
+        update_setting(
+            "drain_timeout",
+            &mut self.drain_timeout,
+            &config.drain_timeout,
+        );
+        if self.remap_rules != config.remap_rules {
+            log_warn!("  remap_rules changed");
+            self.remap_rules = config.remap_rules.clone();
+        }
+        if self.client_roots != config.client_roots {
+            log_warn!("  client_roots changed");
+            self.client_roots = config.client_roots.clone();
+        }
+        if self.read_acl != config.read_acl {
+            log_warn!("  read_acl changed");
+            self.read_acl = config.read_acl.clone();
+        }
+        if self.write_acl != config.write_acl {
+            log_warn!("  write_acl changed");
+            self.write_acl = config.write_acl.clone();
+        }
+        if self.upload_limits != config.upload_limits {
+            log_warn!("  upload_limits changed");
+            self.upload_limits = config.upload_limits.clone();
+        }
+    }
+
+    /// Registers a hook generating the content of read requests. Hooks receive
+    /// the requested filename and the address of the client, and are called in
+    /// registration order until one returns a [`GeneratedFile`]. When all hooks
+    /// decline, the file is served by the [`FileProvider`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tftpd::{Config, GeneratedFile, Server};
+    ///
+    /// let args = ["/", "-p", "1235"].iter().map(|s| s.to_string());
+    /// let config = Config::new(args).unwrap();
+    /// let mut server = Server::new(&config).unwrap();
+    ///
+    /// server.add_read_hook(|filename, client| {
+    ///     if filename == "whoami.txt" {
+    ///         Some(GeneratedFile::Bytes(client.ip().to_string().into_bytes()))
+    ///     } else {
+    ///         None
+    ///     }
+    /// });
+    /// ```
+    pub fn add_read_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&str, &SocketAddr) -> Option<GeneratedFile> + Send + Sync + 'static,
+    {
+        self.read_hooks.push(Box::new(hook));
+    }
+
+    /// Sets the [`Observer`] notified of the requests and transfers of the
+    /// server. The observer is kept when the configuration is reloaded.
+    pub fn set_observer(&mut self, observer: Arc<dyn Observer>) {
+        self.observer = Some(observer);
     }
 
     /// Starts listening for connections. Note that this function does not finish running until termination.
+    ///
+    /// When the shutdown flag is raised, new requests are refused, and the
+    /// running transfers are given the drain timeout to finish before being
+    /// aborted. When the abort flag is raised, the running transfers are
+    /// aborted immediately.
     pub fn listen(&mut self) {
-        // To check abort flag every seconds
-        self.socket
-            .set_read_timeout(Some(Duration::from_secs(1)))
-            .unwrap();
+        let mut drain_deadline: Option<Instant> = None;
 
         loop {
-            let received = if self.single_port {
+            // Wake up for the earliest retransmission of the event loop
+            let timeout = self
+                .sessions
+                .next_deadline()
+                .map_or(IDLE_TIMEOUT, |deadline| {
+                    deadline
+                        .saturating_duration_since(Instant::now())
+                        .clamp(MIN_TIMEOUT, IDLE_TIMEOUT)
+                });
+            if self.socket.set_read_timeout(Some(timeout)).is_err() {
+                log_err!("Could not set the socket timeout");
+            }
+
+            let received = if self.single_port || self.event_loop {
                 self.socket
                     .recv_from_with_size(self.largest_block_size as usize)
             } else {
                 Socket::recv_from(&self.socket)
             };
 
+            self.sessions.handle_timeouts(&self.socket, Instant::now());
+
+            // Queued requests take the freed slots before new requests
+            self.reap_workers();
+            self.process_queue();
+
             if let Ok((packet, from)) = received {
                 match packet {
-                    Packet::Rrq {
-                        filename,
-                        mut options,
-                        ..
-                    } => {
-                        log_info!("Received Read request from {from}: {filename}");
-                        if let Err(err) = self.handle_rrq(filename.clone(), &mut options, &from) {
-                            log_err!("Error while sending file: {err}")
-                        }
+                    Packet::Rrq { .. } | Packet::Wrq { .. } if drain_deadline.is_some() => {
+                        if Socket::send_to(
+                            &self.socket,
+                            &Packet::Error {
+                                code: ErrorCode::NotDefined,
+                                msg: "server is shutting down".to_string(),
+                            },
+                            &from,
+                        )
+                        .is_err()
+                        {
+                            log_err!("Could not send error packet");
+                        };
+                        log_warn!("Refused request from {from} while shutting down");
                     }
-                    Packet::Wrq {
-                        filename,
-                        mut options,
-                        ..
-                    } => {
-                        if self.read_only {
-                            if Socket::send_to(
-                                &self.socket,
-                                &Packet::Error {
-                                    code: ErrorCode::AccessViolation,
-                                    msg: "server is read-only".to_string(),
-                                },
-                                &from,
-                            )
-                            .is_err()
-                            {
-                                log_err!("Could not send error packet");
-                            };
-                            log_warn!("Received write request while in read-only mode");
-                            continue;
-                        }
-                        log_info!("Received Write request from {from}: {filename}");
-                        if let Err(err) = self.handle_wrq(filename, &mut options, &from) {
-                            log_err!("Error while receiving file: {err}")
-                        }
+                    Packet::Rrq { .. } | Packet::Wrq { .. } => {
+                        self.accept_or_queue(packet, from);
+                    }
+                    _ if self.sessions.contains(&from) => {
+                        self.sessions.handle_packet(&self.socket, packet, from);
                     }
                     _ => {
                         if self.route_packet(packet, &from).is_err() {
@@ -137,172 +426,717 @@ impl Server {
                 };
             }
 
-            if self.abort.load(std::sync::atomic::Ordering::Relaxed) {
+            if self.abort.load(Ordering::Relaxed) {
                 log_err!("TFTP service aborted by user");
                 break;
             }
+
+            if drain_deadline.is_none() && self.shutdown.load(Ordering::Relaxed) {
+                log_warn!(
+                    "Shutting down, waiting up to {:?} for {} running transfers",
+                    self.drain_timeout,
+                    self.running()
+                );
+                drain_deadline = Some(Instant::now() + self.drain_timeout);
+                self.refuse_queue();
+            }
+
+            if let Some(deadline) = drain_deadline {
+                if self.running() == 0 {
+                    break;
+                }
+                if Instant::now() >= deadline {
+                    log_warn!(
+                        "Drain timeout reached, aborting {} transfers",
+                        self.running()
+                    );
+                    self.abort.store(true, Ordering::Relaxed);
+                    break;
+                }
+            }
+
+            if self.reload.swap(false, Ordering::Relaxed) {
+                self.reload_config();
+            }
+        }
+
+        // Running workers notice the abort flag and stop on their own
+        let aborted = self.workers.len()
+            + self
+                .sessions
+                .abort(&self.socket, "Transfert aborted by user");
+        for (_, handle) in self.workers.drain(..) {
+            let _ = handle.join();
         }
+
+        log_warn!(
+            "TFTP server stopped: {} transfers completed, {} failed, {} aborted",
+            self.completed,
+            self.failed,
+            aborted
+        );
     }
 
-    fn handle_rrq(
-        &mut self,
+    fn handle_request(&mut self, packet: Packet, from: SocketAddr) {
+        match self.check_request(packet, &from) {
+            Decision::Refuse(packet) => {
+                if Socket::send_to(&self.socket, &packet, &from).is_err() {
+                    log_err!("Could not send error packet");
+                };
+            }
+            Decision::Ignore => {}
+            Decision::Send(accepted, file) => {
+                if let Err(err) = self.start_send(accepted, file, &from) {
+                    log_err!("Error while sending file: {err}")
+                }
+            }
+            Decision::Receive(accepted, file) => {
+                if let Err(err) = self.start_receive(accepted, file, &from) {
+                    log_err!("Error while receiving file: {err}")
+                }
+            }
+        }
+    }
+
+    /// Checks a request against the settings of the server, and opens the
+    /// requested file if the request is accepted. No packet is sent.
+    pub(crate) fn check_request(&self, packet: Packet, from: &SocketAddr) -> Decision {
+        let Some(observer) = &self.observer else {
+            return self.check_packet(packet, from);
+        };
+
+        if matches!(packet, Packet::Rrq { .. } | Packet::Wrq { .. }) {
+            observer.request_received(&packet, from);
+        }
+        let decision = self.check_packet(packet, from);
+        match &decision {
+            Decision::Send(accepted, _) => observer
+                .options_negotiated(&accepted.info(*from, Direction::Send), &accepted.options),
+            Decision::Receive(accepted, _) => observer
+                .options_negotiated(&accepted.info(*from, Direction::Receive), &accepted.options),
+            _ => {}
+        }
+
+        decision
+    }
+
+    /// Returns the [`TransferTracker`] following the transfer of an accepted
+    /// request.
+    pub(crate) fn tracker(
+        &self,
+        accepted: &Accepted,
+        peer: SocketAddr,
+        direction: Direction,
+    ) -> TransferTracker {
+        TransferTracker::new(
+            accepted.info(peer, direction),
+            accepted.options.clone(),
+            self.observer.clone(),
+        )
+    }
+
+    fn check_packet(&self, packet: Packet, from: &SocketAddr) -> Decision {
+        match packet {
+            Packet::Rrq {
+                filename,
+                mode,
+                mut options,
+            } => {
+                log_info!("Received Read request from {from}: {filename}");
+                let (filename, mode) = match self.check_common(filename, &mode, false, from) {
+                    Ok(checked) => checked,
+                    Err(packet) => return Decision::Refuse(packet),
+                };
+                self.check_rrq(filename, mode, &mut options, from)
+                    .unwrap_or_else(|err| {
+                        log_err!("Error while sending file: {err}");
+                        Decision::Refuse(refusal(&err))
+                    })
+            }
+            Packet::Wrq {
+                filename,
+                mode,
+                mut options,
+            } => {
+                if self.read_only {
+                    log_warn!("Received write request while in read-only mode");
+                    return Decision::Refuse(Packet::Error {
+                        code: ErrorCode::AccessViolation,
+                        msg: "server is read-only".to_string(),
+                    });
+                }
+                log_info!("Received Write request from {from}: {filename}");
+                let (filename, mode) = match self.check_common(filename, &mode, true, from) {
+                    Ok(checked) => checked,
+                    Err(packet) => return Decision::Refuse(packet),
+                };
+                self.check_wrq(filename, mode, &mut options, from)
+                    .unwrap_or_else(|err| {
+                        log_err!("Error while receiving file: {err}");
+                        Decision::Refuse(refusal(&err))
+                    })
+            }
+            _ => Decision::Ignore,
+        }
+    }
+
+    fn accept_or_queue(&mut self, packet: Packet, from: SocketAddr) {
+        if self.has_capacity(&from.ip()) {
+            self.handle_request(packet, from);
+        } else if self.queue_timeout.is_zero() || self.queue.len() >= MAX_QUEUED_REQUESTS {
+            self.refuse_busy(&from);
+        } else if !self.queue.iter().any(|request| request.from == from) {
+            // Retransmitted requests are already queued
+            log_info!(
+                "Queued request from {from}, {} transfers running",
+                self.running()
+            );
+            self.queue.push_back(QueuedRequest {
+                packet,
+                from,
+                deadline: Instant::now() + self.queue_timeout,
+            });
+        }
+    }
+
+    fn process_queue(&mut self) {
+        for request in mem::take(&mut self.queue) {
+            if self.has_capacity(&request.from.ip()) {
+                self.handle_request(request.packet, request.from);
+            } else if Instant::now() >= request.deadline {
+                self.refuse_busy(&request.from);
+            } else {
+                self.queue.push_back(request);
+            }
+        }
+    }
+
+    fn refuse_queue(&mut self) {
+        for request in mem::take(&mut self.queue) {
+            if Socket::send_to(
+                &self.socket,
+                &Packet::Error {
+                    code: ErrorCode::NotDefined,
+                    msg: "server is shutting down".to_string(),
+                },
+                &request.from,
+            )
+            .is_err()
+            {
+                log_err!("Could not send error packet");
+            };
+        }
+    }
+
+    fn has_capacity(&self, ip: &IpAddr) -> bool {
+        let total = self.running();
+        let client = self
+            .workers
+            .iter()
+            .filter(|(address, _)| address.ip() == *ip)
+            .count()
+            + self.sessions.client_len(ip);
+
+        self.max_transfers.map_or(true, |max| total < max)
+            && self.max_client_transfers.map_or(true, |max| client < max)
+    }
+
+    fn refuse_busy(&self, from: &SocketAddr) {
+        if Socket::send_to(
+            &self.socket,
+            &Packet::Error {
+                code: ErrorCode::NotDefined,
+                msg: "too many transfers, try again later".to_string(),
+            },
+            from,
+        )
+        .is_err()
+        {
+            log_err!("Could not send error packet");
+        };
+        log_warn!(
+            "Refused request from {from}: {} transfers running (limit: {}, {} per client)",
+            self.running(),
+            limit_display(self.max_transfers),
+            limit_display(self.max_client_transfers)
+        );
+    }
+
+    fn reap_workers(&mut self) {
+        let (finished, running): (Vec<_>, Vec<_>) = mem::take(&mut self.workers)
+            .into_iter()
+            .partition(|(_, handle)| handle.is_finished());
+        self.workers = running;
+
+        for (_, handle) in finished {
+            if handle.join().is_ok_and(|report| report.is_success()) {
+                self.completed += 1;
+            } else {
+                self.failed += 1;
+            }
+        }
+
+        let (completed, failed) = self.sessions.take_finished();
+        self.completed += completed;
+        self.failed += failed;
+    }
+
+    fn running(&self) -> usize {
+        self.workers.len() + self.sessions.len()
+    }
+
+    fn reload_config(&mut self) {
+        let Some(loader) = &self.config_loader else {
+            log_warn!("Cannot reload the configuration without a loader");
+            return;
+        };
+
+        match loader() {
+            Ok(config) => {
+                log_warn!("Reloading configuration");
+                self.reload(&config);
+            }
+            Err(err) => log_err!("Could not reload configuration, keeping the current one: {err}"),
+        }
+    }
+
+    fn check_rrq(
+        &self,
         filename: String,
-        options: &mut [TransferOption],
+        mode: TransferMode,
+        options: &mut Vec<TransferOption>,
         to: &SocketAddr,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<Decision, Error> {
         let file_path = convert_file_path(&filename);
-        let file_path = &self.send_directory.join(file_path);
-        match check_file_exists(file_path, &self.send_directory) {
-            ErrorCode::FileNotFound => {
-                log_warn!("Cannot find requested file: {}", file_path.display());
-                Socket::send_to(
-                    &self.socket,
-                    &Packet::Error {
+        let generated = self.read_hooks.iter().find_map(|hook| hook(&filename, to));
+
+        let client_provider = self.client_provider(&to.ip());
+        let provider = match &client_provider {
+            Some(client_provider) => client_provider,
+            None => self.provider.as_ref(),
+        };
+
+        let (file, file_size) = if let Some(generated) = generated {
+            log_info!("  Sending generated content for {filename}");
+            generated.into_source(mode)?
+        } else {
+            match provider.check_read(&file_path) {
+                ErrorCode::FileNotFound => {
+                    log_warn!("Cannot find requested file: {}", file_path.display());
+                    return Ok(Decision::Refuse(Packet::Error {
                         code: ErrorCode::FileNotFound,
                         msg: format!("file {} does not exist", file_path.display()),
-                    },
-                    to,
-                )
-            }
-            ErrorCode::AccessViolation => {
-                log_warn!("Cannot access requested file: {}", file_path.display());
-                Socket::send_to(
-                    &self.socket,
-                    &Packet::Error {
+                    }));
+                }
+                ErrorCode::AccessViolation => {
+                    log_warn!("Cannot access requested file: {}", file_path.display());
+                    return Ok(Decision::Refuse(Packet::Error {
                         code: ErrorCode::AccessViolation,
                         msg: format!("file access violation: {}", file_path.display()),
-                    },
-                    to,
-                )
-            }
-            ErrorCode::FileExists => {
-                let worker_options = OptionsProtocol::parse(
-                    options,
-                    RequestType::Read(file_path.metadata()?.len()),
-                )?;
-                let mut socket: Box<dyn Socket>;
-
-                if self.single_port {
-                    let single_socket =
-                        create_single_socket(&self.socket, to, worker_options.timeout)?;
-                    self.clients.insert(*to, single_socket.sender());
-                    self.largest_block_size =
-                        max(self.largest_block_size, worker_options.block_size);
-
-                    socket = Box::new(single_socket);
-                } else {
-                    socket = Box::new(create_multi_socket(&self.socket.local_addr()?, to)?);
+                    }));
+                }
+                ErrorCode::FileExists => {
+                    let (file, file_size) = provider.open(&file_path)?;
+                    // tsize reflects the size of the data sent on the wire,
+                    // which in netascii mode takes reading the whole file
+                    let file_size = file_size.filter(|_| mode != TransferMode::Netascii);
+                    (file, file_size)
                 }
+                code => {
+                    return Err(Error::io(
+                        ErrorKind::Other,
+                        format!("Unexpected error code {code} when checking file"),
+                    ))
+                }
+            }
+        };
 
-                socket.set_read_timeout(worker_options.timeout)?;
-                socket.set_write_timeout(worker_options.timeout)?;
+        if file_size.is_none() {
+            // tsize cannot be answered without knowing the file size
+            options.retain(|option| option.option != OptionType::TransferSize);
+        }
+        let file_size = file_size.unwrap_or_default();
+        let worker_options = OptionsProtocol::parse(options, RequestType::Read(file_size))?;
+        log_dbg!("  Accepted options: {}", OptionFmt(options));
 
-                log_dbg!("  Accepted options: {}", OptionFmt(options));
+        let accepted = Accepted {
+            file_path,
+            mode,
+            options: worker_options,
+            reply: accept_packet(options, RequestType::Read(file_size)),
+            max_size: None,
+        };
+        Ok(Decision::Send(accepted, file))
+    }
 
-                accept_request(
-                    &socket,
-                    options,
-                    RequestType::Read(file_path.metadata()?.len()),
-                )?;
+    fn check_wrq(
+        &self,
+        filename: String,
+        mode: TransferMode,
+        options: &mut [TransferOption],
+        from: &SocketAddr,
+    ) -> Result<Decision, Error> {
+        let file_path = convert_file_path(&filename);
+        let client_provider = self.client_provider(&from.ip());
+        let provider = match &client_provider {
+            Some(client_provider) => client_provider,
+            None => self.provider.as_ref(),
+        };
 
-                let worker = Worker::new(
-                    socket,
-                    file_path.clone(),
-                    self.opt_local.clone(),
-                    worker_options.clone(),
-                    self.abort.clone(),
-                );
-                worker.send(!options.is_empty())?;
-                Ok(())
+        match provider.check_write(&file_path) {
+            ErrorCode::FileExists if !self.overwrite => {
+                log_err!("File {} already exists", file_path.display());
+                return Ok(Decision::Refuse(Packet::Error {
+                    code: ErrorCode::FileExists,
+                    msg: "requested file already exists".to_string(),
+                }));
+            }
+            ErrorCode::AccessViolation => {
+                log_err!("Access violation detected for file {}", file_path.display());
+                return Ok(Decision::Refuse(Packet::Error {
+                    code: ErrorCode::AccessViolation,
+                    msg: format!("file access violation: {}", file_path.display()),
+                }));
+            }
+            ErrorCode::FileExists | ErrorCode::FileNotFound => {}
+            code => {
+                return Err(Error::io(
+                    ErrorKind::Other,
+                    format!("Unexpected error code {code} when checking file"),
+                ))
             }
-            _ => Err("Unexpected error code when checking file".into()),
         }
+
+        let worker_options = OptionsProtocol::parse(options, RequestType::Write)?;
+        // A link into a limited directory gets its limit
+        let limited_path = self
+            .receive_directory(&from.ip())
+            .and_then(|directory| resolve_path(directory, &file_path))
+            .unwrap_or_else(|| file_path.clone());
+        let max_size = self.upload_limits.max_size(&limited_path);
+        if let Err(packet) =
+            check_size(provider, &file_path, worker_options.transfer_size, max_size)
+        {
+            return Ok(Decision::Refuse(packet));
+        }
+
+        let file = provider.create(&file_path)?;
+        log_dbg!("  Accepted options: {}", OptionFmt(options));
+
+        let accepted = Accepted {
+            file_path,
+            mode,
+            options: worker_options,
+            reply: accept_packet(options, RequestType::Write),
+            max_size,
+        };
+        Ok(Decision::Receive(accepted, file))
     }
 
-    fn handle_wrq(
+    /// Returns a [`DirectoryProvider`] of the directories of the client at
+    /// `address`, when it has its own and the server is not using a custom
+    /// [`FileProvider`].
+    fn client_provider(&self, address: &IpAddr) -> Option<DirectoryProvider> {
+        let (send_directory, receive_directory) = self.directories.as_ref()?;
+        let send = self.client_roots.send_directory(address);
+        let receive = self.client_roots.receive_directory(address);
+        if send.is_none() && receive.is_none() {
+            return None;
+        }
+
+        let mut provider = DirectoryProvider::new(
+            send.unwrap_or(send_directory).to_path_buf(),
+            receive.unwrap_or(receive_directory).to_path_buf(),
+        );
+        provider.set_create_dirs(self.create_dirs);
+        provider.set_symlink_policy(self.symlink_policy);
+        provider.set_overwrite(self.overwrite);
+        Some(provider)
+    }
+
+    /// Returns the directory receiving the files of the client at `address`,
+    /// unless the server is using a custom provider.
+    fn receive_directory(&self, address: &IpAddr) -> Option<&Path> {
+        let (_, receive_directory) = self.directories.as_ref()?;
+        Some(
+            self.client_roots
+                .receive_directory(address)
+                .unwrap_or(receive_directory),
+        )
+    }
+
+    fn start_send(
         &mut self,
-        filename: String,
-        options: &mut [TransferOption],
+        accepted: Accepted,
+        file: Box<dyn Read + Send>,
         to: &SocketAddr,
-    ) -> Result<(), Box<dyn Error>> {
-        let file_path = convert_file_path(&filename);
-        let file_path = &self.receive_directory.join(file_path);
-        let initialize_write = &mut || -> Result<(), Box<dyn Error>> {
-            let worker_options = OptionsProtocol::parse(options, RequestType::Write)?;
-            let mut socket: Box<dyn Socket>;
-
-            if self.single_port {
-                let single_socket = create_single_socket(&self.socket, to, worker_options.timeout)?;
-                self.clients.insert(*to, single_socket.sender());
-                self.largest_block_size = max(self.largest_block_size, worker_options.block_size);
+    ) -> Result<(), Error> {
+        let check_response = accepted.reply.is_some();
 
-                socket = Box::new(single_socket);
-            } else {
-                socket = Box::new(create_multi_socket(&self.socket.local_addr()?, to)?);
+        if self.event_loop {
+            self.largest_block_size = max(self.largest_block_size, accepted.options.block_size);
+            let tracker = self.tracker(&accepted, *to, Direction::Send);
+            let transfer = SendTransfer::new(
+                file,
+                accepted.mode,
+                accepted.options,
+                self.opt_local.clone(),
+                check_response,
+                Instant::now(),
+            )?;
+            if let Some(packet) = &accepted.reply {
+                Socket::send_to(&self.socket, packet, to)?;
             }
+            self.sessions
+                .start_send(&self.socket, *to, transfer, tracker, self.opt_local.clone());
+            return Ok(());
+        }
 
-            socket.set_read_timeout(worker_options.timeout)?;
-            socket.set_write_timeout(worker_options.timeout)?;
+        let socket = self.create_socket(&accepted, to)?;
+        let mut worker = Worker::new(
+            socket,
+            accepted.file_path,
+            accepted.mode,
+            self.opt_local.clone(),
+            accepted.options,
+            self.abort.clone(),
+        );
+        if let Some(observer) = &self.observer {
+            worker.set_observer(observer.clone());
+        }
+        self.workers.push((*to, worker.send(file, check_response)?));
+        Ok(())
+    }
 
-            log_dbg!("  Accepted options: {}", OptionFmt(options));
-            accept_request(&socket, options, RequestType::Write)?;
+    fn start_receive(
+        &mut self,
+        accepted: Accepted,
+        file: Box<dyn FileSink>,
+        to: &SocketAddr,
+    ) -> Result<(), Error> {
+        let opt_local = accepted.opt_local(&self.opt_local);
 
-            let worker = Worker::new(
-                socket,
-                file_path.clone(),
-                self.opt_local.clone(),
-                worker_options.clone(),
-                self.abort.clone(),
+        if self.event_loop {
+            self.largest_block_size = max(self.largest_block_size, accepted.options.block_size);
+            if let Some(packet) = &accepted.reply {
+                Socket::send_to(&self.socket, packet, to)?;
+            }
+            let tracker = self.tracker(&accepted, *to, Direction::Receive);
+            let transfer = ReceiveTransfer::new(
+                file,
+                accepted.mode,
+                accepted.options,
+                opt_local.clone(),
+                Instant::now(),
             );
-            worker.receive()?;
-            Ok(())
+            self.sessions
+                .start_receive(&self.socket, *to, transfer, tracker, opt_local);
+            return Ok(());
+        }
+
+        let socket = self.create_socket(&accepted, to)?;
+        let mut worker = Worker::new(
+            socket,
+            accepted.file_path,
+            accepted.mode,
+            opt_local,
+            accepted.options,
+            self.abort.clone(),
+        );
+        if let Some(observer) = &self.observer {
+            worker.set_observer(observer.clone());
+        }
+        self.workers.push((*to, worker.receive(file)?));
+        Ok(())
+    }
+
+    /// Creates the socket of a worker, and answers the request through it.
+    fn create_socket(
+        &mut self,
+        accepted: &Accepted,
+        to: &SocketAddr,
+    ) -> Result<Box<dyn Socket>, Error> {
+        let timeout = accepted.options.timeout;
+        let mut socket: Box<dyn Socket>;
+
+        if self.single_port {
+            let single_socket = create_single_socket(&self.socket, to, timeout)?;
+            self.clients.insert(*to, single_socket.sender());
+            self.largest_block_size = max(self.largest_block_size, accepted.options.block_size);
+
+            socket = Box::new(single_socket);
+        } else {
+            socket = Box::new(create_multi_socket(&self.socket.local_addr()?, to)?);
+        }
+
+        socket.set_read_timeout(timeout)?;
+        socket.set_write_timeout(timeout)?;
+
+        if let Some(packet) = &accepted.reply {
+            socket.send(packet)?;
+        }
+
+        Ok(socket)
+    }
+
+    fn check_common(
+        &self,
+        filename: String,
+        mode: &str,
+        write: bool,
+        from: &SocketAddr,
+    ) -> Result<(String, TransferMode), Packet> {
+        self.check_access(write, &filename, from)?;
+        let mode = parse_transfer_mode(mode)?;
+        let filename = self.remap_filename(filename, write, from)?;
+
+        Ok((filename, mode))
+    }
+
+    fn check_access(&self, write: bool, filename: &str, from: &SocketAddr) -> Result<(), Packet> {
+        let acl = if write {
+            &self.write_acl
+        } else {
+            &self.read_acl
         };
+        if acl.is_allowed(&from.ip()) {
+            return Ok(());
+        }
+
+        log_warn!(
+            "Refused {} request from {from} by access list: {filename}",
+            if write { "write" } else { "read" }
+        );
+        Err(Packet::Error {
+            code: ErrorCode::AccessViolation,
+            msg: "access denied".to_string(),
+        })
+    }
 
-        match check_file_exists(file_path, &self.receive_directory) {
-            ErrorCode::FileExists => {
-                if self.overwrite {
-                    initialize_write()
-                } else {
-                    log_err!("File {} already exists", file_path.display());
-                    Socket::send_to(
-                        &self.socket,
-                        &Packet::Error {
-                            code: ErrorCode::FileExists,
-                            msg: "requested file already exists".to_string(),
-                        },
-                        to,
-                    )
+    fn remap_filename(
+        &self,
+        filename: String,
+        write: bool,
+        from: &SocketAddr,
+    ) -> Result<String, Packet> {
+        if self.remap_rules.is_empty() {
+            return Ok(filename);
+        }
+
+        match self.remap_rules.apply(&filename, write) {
+            Some(remapped) => {
+                if remapped != filename {
+                    log_dbg!("  Remapped {filename} to {remapped}");
                 }
+                Ok(remapped)
             }
-            ErrorCode::AccessViolation => {
-                log_err!("Access violation detected for file {}", file_path.display());
-                Socket::send_to(
-                    &self.socket,
-                    &Packet::Error {
-                        code: ErrorCode::AccessViolation,
-                        msg: format!("file access violation: {}", file_path.display()),
-                    },
-                    to,
-                )
+            None => {
+                log_warn!("Remap rules denied request from {from}: {filename}");
+                Err(Packet::Error {
+                    code: ErrorCode::AccessViolation,
+                    msg: format!("file access violation: {filename}"),
+                })
             }
-            ErrorCode::FileNotFound => initialize_write(),
-            _ => Err("Unexpected error code when checking file".into()),
         }
     }
 
-    fn route_packet(&self, packet: Packet, to: &SocketAddr) -> Result<(), Box<dyn Error>> {
+    fn route_packet(&self, packet: Packet, to: &SocketAddr) -> Result<(), Error> {
         if self.clients.contains_key(to) {
-            self.clients[to].send(packet)?;
-            Ok(())
+            self.clients[to]
+                .send(packet)
+                .map_err(|_| Error::io(ErrorKind::BrokenPipe, "Transfer of client has ended"))
         } else {
-            Err("No client found for packet".into())
+            Err(Error::io(ErrorKind::NotFound, "No client found for packet"))
         }
     }
 
+    /// Returns the local [`SocketAddr`] the server listens on.
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
     /// Retrieve a ref to the abort flag
     pub fn get_abort_flag(&self) -> Arc<AtomicBool> {
         self.abort.clone()
     }
+
+    /// Retrieve a ref to the shutdown flag, which stops the server once the
+    /// running transfers finish or the drain timeout expires
+    pub fn get_shutdown_flag(&self) -> Arc<AtomicBool> {
+        self.shutdown.clone()
+    }
+
+    /// Retrieve a ref to the reload flag, which reloads the configuration
+    /// with the loader set by [`Server::set_config_loader()`]
+    pub fn get_reload_flag(&self) -> Arc<AtomicBool> {
+        self.reload.clone()
+    }
+}
+
+fn limit_display(limit: Option<usize>) -> String {
+    limit.map_or("unlimited".to_string(), |limit| limit.to_string())
+}
+
+/// Checks the size announced by the transfer size option of a write
+/// request against the upload limits and the available space. The
+/// limits are checked again during the transfer. Without a transfer size,
+/// at least a byte of space is needed.
+fn check_size(
+    provider: &dyn FileProvider,
+    file_path: &Path,
+    size: Option<u64>,
+    max_size: Option<u64>,
+) -> Result<(), Packet> {
+    if let (Some(size), Some(max_size)) = (size, max_size) {
+        if size > max_size {
+            log_warn!(
+                "Refused {} of {size} bytes, over the maximum size of {max_size} bytes",
+                file_path.display()
+            );
+            return Err(Packet::Error {
+                code: ErrorCode::AccessViolation,
+                msg: format!("file exceeds the maximum size of {max_size} bytes"),
+            });
+        }
+    }
+
+    let needed = size.unwrap_or(1);
+    match provider.available_space(file_path) {
+        Some(available) if available < needed => {
+            log_warn!(
+                "Refused {} of {needed} bytes, only {available} bytes are available",
+                file_path.display()
+            );
+            Err(Packet::Error {
+                code: ErrorCode::DiskFull,
+                msg: format!("not enough space for {needed} bytes"),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+fn directory_provider(config: &Config) -> DirectoryProvider {
+    let mut provider = DirectoryProvider::new(
+        config.send_directory.clone(),
+        config.receive_directory.clone(),
+    );
+    provider.set_create_dirs(config.create_dirs.then_some(config.dir_mode));
+    provider.set_symlink_policy(config.symlink_policy);
+    provider.set_overwrite(config.overwrite);
+    provider
+}
+
+fn update_setting<T: PartialEq + Clone + Debug>(name: &str, setting: &mut T, value: &T) {
+    if setting != value {
+        log_warn!("  {name}: {setting:?} -> {value:?}");
+        setting.clone_from(value);
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -333,56 +1167,124 @@ fn create_single_socket(
     socket: &UdpSocket,
     remote: &SocketAddr,
     timeout: Duration,
-) -> Result<ServerSocket, Box<dyn Error>> {
+) -> Result<ServerSocket, Error> {
     let socket = ServerSocket::new(socket.try_clone()?, *remote, timeout);
 
     Ok(socket)
 }
 
-fn create_multi_socket(
-    addr: &SocketAddr,
-    remote: &SocketAddr,
-) -> Result<UdpSocket, Box<dyn Error>> {
+fn create_multi_socket(addr: &SocketAddr, remote: &SocketAddr) -> Result<UdpSocket, Error> {
     let socket = UdpSocket::bind(SocketAddr::from((addr.ip(), 0)))?;
     socket.connect(remote)?;
 
     Ok(socket)
 }
 
-fn accept_request<T: Socket>(
-    socket: &T,
-    options: &[TransferOption],
-    request_type: RequestType,
-) -> Result<(), Box<dyn Error>> {
-    if !options.is_empty() {
-        socket.send(&Packet::Oack(options.to_vec()))?;
-    } else if request_type == RequestType::Write {
-        socket.send(&Packet::Ack(0))?;
+fn parse_transfer_mode(mode: &str) -> Result<TransferMode, Packet> {
+    match mode.parse::<TransferMode>() {
+        Ok(mode) if mode.is_supported() => Ok(mode),
+        _ => {
+            log_warn!("Received request with unsupported transfer mode: {mode}");
+            Err(Packet::Error {
+                code: ErrorCode::IllegalOperation,
+                msg: format!("unsupported transfer mode: {mode}"),
+            })
+        }
     }
-
-    Ok(())
 }
 
-fn check_file_exists(file: &Path, directory: &PathBuf) -> ErrorCode {
-    if !validate_file_path(file, directory) {
-        return ErrorCode::AccessViolation;
-    }
-
-    if !file.exists() {
-        return ErrorCode::FileNotFound;
-    }
+/// Returns the error packet refusing a request which failed with `err`, so
+/// that the client does not wait for an answer until it times out.
+fn refusal(err: &Error) -> Packet {
+    let (code, msg) = match err {
+        Error::Protocol(code, msg) => (*code, msg.clone()),
+        // The details of a denied access stay in the logs of the server
+        Error::AccessDenied(_) => (ErrorCode::AccessViolation, "access denied".to_string()),
+        Error::Io(err) if err.kind() == ErrorKind::PermissionDenied => {
+            (ErrorCode::AccessViolation, "access denied".to_string())
+        }
+        _ => (
+            ErrorCode::NotDefined,
+            "could not access the file".to_string(),
+        ),
+    };
 
-    ErrorCode::FileExists
+    Packet::Error { code, msg }
 }
 
-fn validate_file_path(file: &Path, directory: &PathBuf) -> bool {
-    !file.to_str().unwrap().contains("..") && file.ancestors().any(|a| a == directory)
+fn accept_packet(options: &[TransferOption], request_type: RequestType) -> Option<Packet> {
+    if !options.is_empty() {
+        Some(Packet::Oack(options.to_vec()))
+    } else if request_type == RequestType::Write {
+        Some(Packet::Ack(0))
+    } else {
+        None
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+
     use super::*;
 
+    #[test]
+    fn reloads_config() {
+        let config = Config::new(["/", "-p", "0"].iter().map(|s| s.to_string())).unwrap();
+        let mut server = Server::new(&config).unwrap();
+
+        let config = Config::new(
+            [
+                "/",
+                "-p",
+                "0",
+                "-r",
+                "--overwrite",
+                "--deny-write",
+                "::/0",
+                "--create-dirs",
+                "-s",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        )
+        .unwrap();
+        server.reload(&config);
+
+        // The running transfers depend on the single port mode
+        assert!(!server.single_port);
+        assert!(server.read_only);
+        assert!(server.overwrite);
+        assert_eq!(server.write_acl, config.write_acl);
+        assert_eq!(server.read_acl, config.read_acl);
+        assert_eq!(server.create_dirs, Some(0o755));
+    }
+
+    #[test]
+    fn keeps_config_on_failed_reload() {
+        const DIR_NAME: &str = "target/test/keeps_config_on_failed_reload";
+        let _ = fs::create_dir_all(DIR_NAME);
+        let file = format!("{DIR_NAME}/tftpd.toml");
+        fs::write(&file, "read-only = true\n").unwrap();
+        let args: Vec<String> = ["/", "-p", "0", "--config", &file]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let mut server = Server::new(&Config::new(args.iter().cloned()).unwrap()).unwrap();
+        server.set_config_loader(move || Config::new(args.iter().cloned()));
+
+        // Printing the help would exit the server instead
+        fs::write(&file, "help = true\nread-only = false\n").unwrap();
+        server.reload_config();
+        assert!(server.read_only);
+
+        fs::write(&file, "read-only = false\n").unwrap();
+        server.reload_config();
+        assert!(!server.read_only);
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+
     #[test]
     fn converts_file_path() {
         let path = convert_file_path("test.file");
@@ -419,29 +1321,6 @@ mod tests {
         assert_eq!(path, correct_path);
     }
 
-    #[test]
-    fn validates_file_path() {
-        assert!(validate_file_path(
-            &PathBuf::from("/dir/test/file"),
-            &PathBuf::from("/dir/test")
-        ));
-
-        assert!(!validate_file_path(
-            &PathBuf::from("/system/data.txt"),
-            &PathBuf::from("/dir/test")
-        ));
-
-        assert!(!validate_file_path(
-            &PathBuf::from("~/some_data.txt"),
-            &PathBuf::from("/dir/test")
-        ));
-
-        assert!(!validate_file_path(
-            &PathBuf::from("/dir/test/../file"),
-            &PathBuf::from("/dir/test")
-        ));
-    }
-
     #[test]
     fn parses_write_options() {
         let mut options = vec![
@@ -501,4 +1380,319 @@ mod tests {
             OptionsProtocol::default(),
         );
     }
+
+    #[test]
+    fn refuses_oversized_uploads() {
+        const DIR_NAME: &str = "target/test/refuses_oversized_uploads";
+        let _ = fs::create_dir_all(DIR_NAME);
+        let config = Config::new(
+            ["/", "-p", "0", "-d", DIR_NAME, "--max-upload-size", "10"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .unwrap();
+        let server = Server::new(&config).unwrap();
+        let client = SocketAddr::from(([127, 0, 0, 1], 6969));
+        let tsize = |value| {
+            vec![TransferOption {
+                option: OptionType::TransferSize,
+                value,
+            }]
+        };
+
+        let decision = server
+            .check_wrq(
+                "file.bin".into(),
+                TransferMode::Octet,
+                &mut tsize(11),
+                &client,
+            )
+            .unwrap();
+        assert!(matches!(
+            decision,
+            Decision::Refuse(Packet::Error {
+                code: ErrorCode::AccessViolation,
+                ..
+            })
+        ));
+
+        let decision = server
+            .check_wrq(
+                "file.bin".into(),
+                TransferMode::Octet,
+                &mut tsize(10),
+                &client,
+            )
+            .unwrap();
+        let Decision::Receive(accepted, _) = decision else {
+            panic!("upload within the limit was refused");
+        };
+        assert_eq!(accepted.max_size, Some(10));
+
+        let _ = fs::create_dir_all(format!("{DIR_NAME}/images"));
+        #[cfg(unix)]
+        let _ = std::os::unix::fs::symlink("images", format!("{DIR_NAME}/link"));
+        let config = Config::new(
+            [
+                "/",
+                "-p",
+                "0",
+                "-d",
+                DIR_NAME,
+                "--max-upload-size-in",
+                "images=10",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        )
+        .unwrap();
+        let server = Server::new(&config).unwrap();
+        let mut paths = vec!["images/file.bin", "./images/file.bin"];
+        if cfg!(unix) {
+            paths.push("link/file.bin");
+        }
+        for path in paths {
+            let decision = server
+                .check_wrq(path.into(), TransferMode::Octet, &mut tsize(11), &client)
+                .unwrap();
+            assert!(
+                matches!(
+                    decision,
+                    Decision::Refuse(Packet::Error {
+                        code: ErrorCode::AccessViolation,
+                        ..
+                    })
+                ),
+                "{path} was not limited"
+            );
+        }
+
+        #[cfg(unix)]
+        {
+            let config = Config::new(
+                ["/", "-p", "0", "-d", DIR_NAME]
+                    .iter()
+                    .map(|s| s.to_string()),
+            )
+            .unwrap();
+            let server = Server::new(&config).unwrap();
+            let decision = server
+                .check_wrq(
+                    "file.bin".into(),
+                    TransferMode::Octet,
+                    &mut tsize(u64::MAX),
+                    &client,
+                )
+                .unwrap();
+            assert!(matches!(
+                decision,
+                Decision::Refuse(Packet::Error {
+                    code: ErrorCode::DiskFull,
+                    ..
+                })
+            ));
+        }
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+
+    #[test]
+    fn omits_netascii_transfer_size() {
+        const DIR_NAME: &str = "target/test/omits_netascii_transfer_size";
+        let _ = fs::create_dir_all(DIR_NAME);
+        fs::write(format!("{DIR_NAME}/file.txt"), "a\nb\n").unwrap();
+        let config = Config::new(
+            ["/", "-p", "0", "-d", DIR_NAME]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .unwrap();
+        let server = Server::new(&config).unwrap();
+        let client = SocketAddr::from(([127, 0, 0, 1], 6969));
+
+        for (mode, tsize) in [
+            (TransferMode::Octet, Some(4)),
+            (TransferMode::Netascii, None),
+        ] {
+            let mut options = vec![TransferOption {
+                option: OptionType::TransferSize,
+                value: 0,
+            }];
+            let decision = server
+                .check_rrq("file.txt".into(), mode, &mut options, &client)
+                .unwrap();
+            assert!(matches!(decision, Decision::Send(..)));
+            let answered = options
+                .iter()
+                .find(|option| option.option == OptionType::TransferSize)
+                .map(|option| option.value);
+            assert_eq!(answered, tsize);
+        }
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
+
+    #[test]
+    fn refuses_failed_requests() {
+        use crate::{FileSink, FileSource, ServerBuilder, Socket};
+
+        struct FailingProvider;
+
+        impl FileProvider for FailingProvider {
+            fn check_read(&self, _path: &Path) -> ErrorCode {
+                ErrorCode::FileExists
+            }
+
+            fn check_write(&self, _path: &Path) -> ErrorCode {
+                ErrorCode::FileNotFound
+            }
+
+            fn open(&self, _path: &Path) -> Result<FileSource, Error> {
+                Err(Error::io(ErrorKind::PermissionDenied, "permission denied"))
+            }
+
+            fn create(&self, _path: &Path) -> Result<Box<dyn FileSink>, Error> {
+                Err(Error::AccessDenied("file.bin changed".to_string()))
+            }
+        }
+
+        let handle = ServerBuilder::new()
+            .ip_address([127, 0, 0, 1].into())
+            .port(0)
+            .provider(FailingProvider)
+            .start()
+            .unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        // The client is answered instead of waiting until it times out
+        let requests = [
+            Packet::Rrq {
+                filename: "file.bin".to_string(),
+                mode: "octet".to_string(),
+                options: vec![],
+            },
+            Packet::Wrq {
+                filename: "file.bin".to_string(),
+                mode: "octet".to_string(),
+                options: vec![],
+            },
+        ];
+        for request in requests {
+            Socket::send_to(&client, &request, &handle.local_addr()).unwrap();
+            let (packet, _) = client.recv_from_with_size(512).unwrap();
+            assert!(matches!(
+                packet,
+                Packet::Error {
+                    code: ErrorCode::AccessViolation,
+                    ..
+                }
+            ));
+        }
+
+        handle.shutdown();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn serves_client_roots() {
+        const DIR_NAME: &str = "target/test/serves_client_roots";
+        let _ = fs::create_dir_all(format!("{DIR_NAME}/default"));
+        let _ = fs::create_dir_all(format!("{DIR_NAME}/lab"));
+        fs::write(format!("{DIR_NAME}/default/default.txt"), "default").unwrap();
+        fs::write(format!("{DIR_NAME}/lab/lab.txt"), "lab").unwrap();
+        let config = Config::new(
+            [
+                "/",
+                "-p",
+                "0",
+                "-d",
+                &format!("{DIR_NAME}/default"),
+                "--client-directory",
+                &format!("10.1.0.0/16={DIR_NAME}/lab"),
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        )
+        .unwrap();
+        let server = Server::new(&config).unwrap();
+        let lab_client = SocketAddr::from(([10, 1, 2, 3], 6969));
+        let other_client = SocketAddr::from(([127, 0, 0, 1], 6969));
+
+        let read = |filename: &str, client| {
+            server
+                .check_rrq(filename.into(), TransferMode::Octet, &mut vec![], client)
+                .unwrap()
+        };
+        assert!(matches!(read("lab.txt", &lab_client), Decision::Send(..)));
+        assert!(matches!(
+            read("default.txt", &lab_client),
+            Decision::Refuse(Packet::Error {
+                code: ErrorCode::FileNotFound,
+                ..
+            })
+        ));
+        assert!(matches!(
+            read("default.txt", &other_client),
+            Decision::Send(..)
+        ));
+        assert!(matches!(
+            read("lab.txt", &other_client),
+            Decision::Refuse(Packet::Error {
+                code: ErrorCode::FileNotFound,
+                ..
+            })
+        ));
+        // The jail applies to the selected root
+        assert!(matches!(
+            read("../default/default.txt", &lab_client),
+            Decision::Refuse(Packet::Error {
+                code: ErrorCode::AccessViolation,
+                ..
+            })
+        ));
+
+        let decision = server
+            .check_wrq(
+                "upload.txt".into(),
+                TransferMode::Octet,
+                &mut [],
+                &lab_client,
+            )
+            .unwrap();
+        let Decision::Receive(_, file) = decision else {
+            panic!("upload of the lab client was refused");
+        };
+        file.commit().unwrap();
+        assert!(Path::new(DIR_NAME).join("lab/upload.txt").exists());
+        assert!(!Path::new(DIR_NAME).join("default/upload.txt").exists());
+
+        // The space is measured in the directory of the client
+        #[cfg(unix)]
+        {
+            let mut tsize = [TransferOption {
+                option: OptionType::TransferSize,
+                value: u64::MAX,
+            }];
+            let decision = server
+                .check_wrq(
+                    "big.bin".into(),
+                    TransferMode::Octet,
+                    &mut tsize,
+                    &lab_client,
+                )
+                .unwrap();
+            assert!(matches!(
+                decision,
+                Decision::Refuse(Packet::Error {
+                    code: ErrorCode::DiskFull,
+                    ..
+                })
+            ));
+        }
+
+        fs::remove_dir_all(DIR_NAME).unwrap();
+    }
 }
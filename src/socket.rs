@@ -1,7 +1,6 @@
-use crate::Packet;
+use crate::{Error, Packet};
 use std::{
-    error::Error,
-    io::{Error as IoError, ErrorKind},
+    io::ErrorKind,
     net::{SocketAddr, UdpSocket},
     sync::{
         mpsc::{self, Receiver, Sender},
@@ -16,89 +15,89 @@ const MAX_REQUEST_PACKET_SIZE: usize = 512;
 /// TFTP communication.
 pub trait Socket: Send + Sync + 'static {
     /// Sends a [`Packet`] to the socket's connected remote [`Socket`].
-    fn send(&self, packet: &Packet) -> Result<(), Box<dyn Error>>;
+    fn send(&self, packet: &Packet) -> Result<(), Error>;
     /// Sends a [`Packet`] to the specified remote [`Socket`].
-    fn send_to(&self, packet: &Packet, to: &SocketAddr) -> Result<(), Box<dyn Error>>;
+    fn send_to(&self, packet: &Packet, to: &SocketAddr) -> Result<(), Error>;
     /// Receives a [`Packet`] from the socket's connected remote [`Socket`]. This
     /// function cannot handle large data packets due to the limited buffer size,
     /// so it is intended for only accepting incoming requests. For handling data
     /// packets, see [`Socket::recv_with_size()`].
-    fn recv(&self) -> Result<Packet, Box<dyn Error>> {
+    fn recv(&self) -> Result<Packet, Error> {
         self.recv_with_size(MAX_REQUEST_PACKET_SIZE)
     }
     /// Receives a data packet from the socket's connected remote, and returns the
     /// parsed [`Packet`]. The received packet can actually be of any type, however,
     /// this function also allows supplying the buffer size for an incoming request.
-    fn recv_with_size(&self, size: usize) -> Result<Packet, Box<dyn Error>>;
+    fn recv_with_size(&self, size: usize) -> Result<Packet, Error>;
     /// Receives a [`Packet`] from any remote [`Socket`] and returns the [`SocketAddr`]
     /// of the remote [`Socket`]. This function cannot handle large data packets
     /// due to the limited buffer size, so it is intended for only accepting incoming
     /// requests. For handling data packets, see [`Socket::recv_from_with_size()`].
-    fn recv_from(&self) -> Result<(Packet, SocketAddr), Box<dyn Error>> {
+    fn recv_from(&self) -> Result<(Packet, SocketAddr), Error> {
         self.recv_from_with_size(MAX_REQUEST_PACKET_SIZE)
     }
     /// Receives a data packet from any incoming remote request, and returns the
     /// parsed [`Packet`] and the requesting [`SocketAddr`]. The received packet can
     /// actually be of any type, however, this function also allows supplying the
     /// buffer size for an incoming request.
-    fn recv_from_with_size(&self, size: usize) -> Result<(Packet, SocketAddr), Box<dyn Error>>;
+    fn recv_from_with_size(&self, size: usize) -> Result<(Packet, SocketAddr), Error>;
     /// Returns the remote [`SocketAddr`] if it exists.
-    fn remote_addr(&self) -> Result<SocketAddr, Box<dyn Error>>;
+    fn remote_addr(&self) -> Result<SocketAddr, Error>;
     /// Sets the read timeout for the [`Socket`].
-    fn set_read_timeout(&mut self, dur: Duration) -> Result<(), Box<dyn Error>>;
+    fn set_read_timeout(&mut self, dur: Duration) -> Result<(), Error>;
     /// Sets the write timeout for the [`Socket`].
-    fn set_write_timeout(&mut self, dur: Duration) -> Result<(), Box<dyn Error>>;
+    fn set_write_timeout(&mut self, dur: Duration) -> Result<(), Error>;
 
     /// Sets the [`Socket`] as blocking or not.
-    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), Box<dyn Error>>;
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), Error>;
 }
 
 impl Socket for UdpSocket {
-    fn send(&self, packet: &Packet) -> Result<(), Box<dyn Error>> {
+    fn send(&self, packet: &Packet) -> Result<(), Error> {
         self.send(&packet.serialize()?)?;
 
         Ok(())
     }
 
-    fn send_to(&self, packet: &Packet, to: &SocketAddr) -> Result<(), Box<dyn Error>> {
+    fn send_to(&self, packet: &Packet, to: &SocketAddr) -> Result<(), Error> {
         self.send_to(&packet.serialize()?, to)?;
 
         Ok(())
     }
 
-    fn recv_with_size(&self, size: usize) -> Result<Packet, Box<dyn Error>> {
+    fn recv_with_size(&self, size: usize) -> Result<Packet, Error> {
         let mut buf = vec![0; size + 4];
-        let amt = self.recv(&mut buf)?;
+        let amt = self.recv(&mut buf).map_err(Error::from_recv)?;
         let packet = Packet::deserialize(&buf[..amt])?;
 
         Ok(packet)
     }
 
-    fn recv_from_with_size(&self, size: usize) -> Result<(Packet, SocketAddr), Box<dyn Error>> {
+    fn recv_from_with_size(&self, size: usize) -> Result<(Packet, SocketAddr), Error> {
         let mut buf = vec![0; size + 4];
-        let (amt, addr) = self.recv_from(&mut buf)?;
+        let (amt, addr) = self.recv_from(&mut buf).map_err(Error::from_recv)?;
         let packet = Packet::deserialize(&buf[..amt])?;
 
         Ok((packet, addr))
     }
 
-    fn remote_addr(&self) -> Result<SocketAddr, Box<dyn Error>> {
+    fn remote_addr(&self) -> Result<SocketAddr, Error> {
         Ok(self.peer_addr()?)
     }
 
-    fn set_read_timeout(&mut self, dur: Duration) -> Result<(), Box<dyn Error>> {
+    fn set_read_timeout(&mut self, dur: Duration) -> Result<(), Error> {
         UdpSocket::set_read_timeout(self, Some(dur))?;
 
         Ok(())
     }
 
-    fn set_write_timeout(&mut self, dur: Duration) -> Result<(), Box<dyn Error>> {
+    fn set_write_timeout(&mut self, dur: Duration) -> Result<(), Error> {
         UdpSocket::set_write_timeout(self, Some(dur))?;
 
         Ok(())
     }
 
-    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), Box<dyn Error>> {
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), Error> {
         UdpSocket::set_nonblocking(self, nonblocking)?;
 
         Ok(())
@@ -134,55 +133,55 @@ pub struct ServerSocket {
 }
 
 impl Socket for ServerSocket {
-    fn send(&self, packet: &Packet) -> Result<(), Box<dyn Error>> {
+    fn send(&self, packet: &Packet) -> Result<(), Error> {
         self.send_to(packet, &self.remote)
     }
 
-    fn send_to(&self, packet: &Packet, to: &SocketAddr) -> Result<(), Box<dyn Error>> {
+    fn send_to(&self, packet: &Packet, to: &SocketAddr) -> Result<(), Error> {
         self.socket.send_to(&packet.serialize()?, to)?;
 
         Ok(())
     }
 
-    fn recv_with_size(&self, _size: usize) -> Result<Packet, Box<dyn Error>> {
+    fn recv_with_size(&self, _size: usize) -> Result<Packet, Error> {
         if let Ok(receiver) = self.receiver.lock() {
             if self.nonblocking {
                 if let Ok(packet) = receiver.try_recv() {
                     Ok(packet)
                 } else {
-                    Err(IoError::from(ErrorKind::WouldBlock).into())
+                    Err(Error::Timeout("No packet received".to_string()))
                 }
             } else if let Ok(packet) = receiver.recv_timeout(self.timeout) {
                 Ok(packet)
             } else {
-                Err("Failed to receive".into())
+                Err(Error::Timeout("Failed to receive".to_string()))
             }
         } else {
-            Err("Failed to lock mutex".into())
+            Err(Error::io(ErrorKind::Other, "Failed to lock mutex"))
         }
     }
 
-    fn recv_from_with_size(&self, _size: usize) -> Result<(Packet, SocketAddr), Box<dyn Error>> {
+    fn recv_from_with_size(&self, _size: usize) -> Result<(Packet, SocketAddr), Error> {
         Ok((self.recv()?, self.remote))
     }
 
-    fn remote_addr(&self) -> Result<SocketAddr, Box<dyn Error>> {
+    fn remote_addr(&self) -> Result<SocketAddr, Error> {
         Ok(self.remote)
     }
 
-    fn set_read_timeout(&mut self, dur: Duration) -> Result<(), Box<dyn Error>> {
+    fn set_read_timeout(&mut self, dur: Duration) -> Result<(), Error> {
         self.timeout = dur;
 
         Ok(())
     }
 
-    fn set_write_timeout(&mut self, dur: Duration) -> Result<(), Box<dyn Error>> {
+    fn set_write_timeout(&mut self, dur: Duration) -> Result<(), Error> {
         self.socket.set_write_timeout(Some(dur))?;
 
         Ok(())
     }
 
-    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), Box<dyn Error>> {
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), Error> {
         self.nonblocking = nonblocking;
         self.socket.set_nonblocking(nonblocking)?;
 
@@ -211,35 +210,35 @@ impl ServerSocket {
 }
 
 impl<T: Socket + ?Sized> Socket for Box<T> {
-    fn send(&self, packet: &Packet) -> Result<(), Box<dyn Error>> {
+    fn send(&self, packet: &Packet) -> Result<(), Error> {
         (**self).send(packet)
     }
 
-    fn send_to(&self, packet: &Packet, to: &SocketAddr) -> Result<(), Box<dyn Error>> {
+    fn send_to(&self, packet: &Packet, to: &SocketAddr) -> Result<(), Error> {
         (**self).send_to(packet, to)
     }
 
-    fn recv_with_size(&self, size: usize) -> Result<Packet, Box<dyn Error>> {
+    fn recv_with_size(&self, size: usize) -> Result<Packet, Error> {
         (**self).recv_with_size(size)
     }
 
-    fn recv_from_with_size(&self, size: usize) -> Result<(Packet, SocketAddr), Box<dyn Error>> {
+    fn recv_from_with_size(&self, size: usize) -> Result<(Packet, SocketAddr), Error> {
         (**self).recv_from_with_size(size)
     }
 
-    fn remote_addr(&self) -> Result<SocketAddr, Box<dyn Error>> {
+    fn remote_addr(&self) -> Result<SocketAddr, Error> {
         (**self).remote_addr()
     }
 
-    fn set_read_timeout(&mut self, dur: Duration) -> Result<(), Box<dyn Error>> {
+    fn set_read_timeout(&mut self, dur: Duration) -> Result<(), Error> {
         (**self).set_read_timeout(dur)
     }
 
-    fn set_write_timeout(&mut self, dur: Duration) -> Result<(), Box<dyn Error>> {
+    fn set_write_timeout(&mut self, dur: Duration) -> Result<(), Error> {
         (**self).set_write_timeout(dur)
     }
 
-    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), Box<dyn Error>> {
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), Error> {
         (**self).set_nonblocking(nonblocking)
     }
 }
@@ -0,0 +1,834 @@
+use std::{
+    collections::VecDeque,
+    io::{ErrorKind, Read, Write},
+    time::Instant,
+};
+
+use crate::log::*;
+use crate::options::{OptionsPrivate, OptionsProtocol, Rollover, DEFAULT_BLOCK_SIZE};
+use crate::{Error, ErrorCode, Packet, TransferMode, TransferReport, WindowRead, WindowWrite};
+
+// Chosen arbitrarily because not specified in RFC
+const MAX_ERROR_PACKET_SIZE: usize = 128;
+
+/// Transfer `trait` is the interface of the sans-IO transfer state machines,
+/// [`SendTransfer`] and [`ReceiveTransfer`]. They own neither a socket nor a
+/// timer: a driver feeds them the received packets and the expired
+/// deadlines, and sends the packets they emit. The [`crate::Worker`] drives
+/// one transfer per thread, while the server event loop drives all of them
+/// from the listening thread.
+///
+/// A driver repeats the following steps until [`Transfer::poll_result()`]
+/// returns the outcome of the transfer:
+///
+/// 1. Send the packets returned by [`Transfer::poll_transmit()`].
+/// 2. Wait for a packet until [`Transfer::poll_timeout()`], and pass it to
+///    [`Transfer::handle_packet()`], or call [`Transfer::handle_timeout()`]
+///    once the deadline has passed.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{io::Cursor, time::Instant};
+/// use tftpd::{Packet, ReceiveTransfer, SendTransfer, Transfer, TransferMode};
+///
+/// let now = Instant::now();
+/// let data = Cursor::new(b"Hello, world!".to_vec());
+/// let mut sender = SendTransfer::new(
+///     data,
+///     TransferMode::Octet,
+///     Default::default(),
+///     Default::default(),
+///     false,
+///     now,
+/// )
+/// .unwrap();
+/// let mut receiver = ReceiveTransfer::new(
+///     Vec::new(),
+///     TransferMode::Octet,
+///     Default::default(),
+///     Default::default(),
+///     now,
+/// );
+///
+/// while let Some(data) = sender.poll_transmit(now) {
+///     receiver.handle_packet(data, now);
+///     while let Some(ack) = receiver.poll_transmit(now) {
+///         sender.handle_packet(ack, now);
+///     }
+/// }
+///
+/// assert_eq!(sender.poll_result().unwrap().unwrap(), 13);
+/// assert_eq!(receiver.poll_result().unwrap().unwrap(), 13);
+/// assert_eq!(receiver.into_inner(), b"Hello, world!");
+/// ```
+pub trait Transfer {
+    /// Handles a [`Packet`] received from the remote peer.
+    fn handle_packet(&mut self, packet: Packet, now: Instant);
+    /// Handles the expiry of the deadline returned by
+    /// [`Transfer::poll_timeout()`]. Calling it early has no effect.
+    fn handle_timeout(&mut self, now: Instant);
+    /// Returns the next [`Packet`] to send, if any is due.
+    fn poll_transmit(&mut self, now: Instant) -> Option<Packet>;
+    /// Returns the deadline at which [`Transfer::handle_timeout()`] and
+    /// [`Transfer::poll_transmit()`] should be called, if the transfer is
+    /// still running.
+    fn poll_timeout(&self) -> Option<Instant>;
+    /// Returns the outcome of the transfer once it has finished and all its
+    /// packets have been transmitted, with the size of the data on the wire.
+    fn poll_result(&mut self) -> Option<Result<u64, Error>>;
+    /// Aborts the transfer, sending an error with `msg` to the remote peer.
+    fn abort(&mut self, msg: &str);
+    /// Returns the size of the buffer needed to receive the packets of the
+    /// transfer.
+    fn max_packet_size(&self) -> usize;
+    /// Returns the [`TransferReport`] of the transfer so far, with the data
+    /// acknowledged by the remote peer, or received from it. The elapsed time
+    /// is left to the driver.
+    fn report(&self) -> TransferReport;
+}
+
+type Outcome = Option<Result<u64, Error>>;
+
+/// SendTransfer `struct` is the sans-IO state machine sending the content of
+/// a [`Read`] source, see [`Transfer`] for how to drive it.
+pub struct SendTransfer<R: Read> {
+    window: WindowRead<R>,
+    more: bool,
+    opt_local: OptionsPrivate,
+    opt_common: OptionsProtocol,
+    awaiting_oack_ack: bool,
+    block_seq_win: u16,
+    win_idx: u16,
+    retry_cnt: usize,
+    size: u64,
+    blocks: u64,
+    sent: u16,
+    retransmits: u64,
+    timeouts: u64,
+    duplicate_acks: u64,
+    timeout_end: Instant,
+    next_send: Instant,
+    queued: VecDeque<Packet>,
+    outcome: Outcome,
+}
+
+impl<R: Read> SendTransfer<R> {
+    /// Creates a new [`SendTransfer`] reading from `file`, translated
+    /// according to the [`TransferMode`]. When `check_response` is `true`,
+    /// an OACK has been sent and the data is only sent after its
+    /// acknowledgement.
+    pub fn new(
+        file: R,
+        mode: TransferMode,
+        opt_common: OptionsProtocol,
+        opt_local: OptionsPrivate,
+        check_response: bool,
+        now: Instant,
+    ) -> Result<SendTransfer<R>, Error> {
+        let mut window = match mode {
+            TransferMode::Netascii => {
+                WindowRead::new_netascii(opt_common.window_size, opt_common.block_size, file)
+            }
+            _ => WindowRead::new(opt_common.window_size, opt_common.block_size, file),
+        };
+        let more = window.fill()?;
+
+        Ok(SendTransfer {
+            window,
+            more,
+            awaiting_oack_ack: check_response,
+            block_seq_win: 0,
+            win_idx: 0,
+            retry_cnt: 0,
+            size: 0,
+            blocks: 0,
+            sent: 0,
+            retransmits: 0,
+            timeouts: 0,
+            duplicate_acks: 0,
+            timeout_end: now + opt_common.timeout,
+            next_send: now,
+            queued: VecDeque::new(),
+            outcome: None,
+            opt_local,
+            opt_common,
+        })
+    }
+
+    fn handle_ack(&mut self, ack: u16, now: Instant) {
+        let mut diff = ack.wrapping_sub(self.block_seq_win);
+        if ack < self.block_seq_win && self.opt_local.rollover == Rollover::Enforce1 {
+            diff -= 1;
+        }
+
+        if diff == 0 {
+            // Duplicate acks are ignored to avoid the Sorcerer's Apprentice Syndrome
+            self.duplicate_acks += 1;
+        } else if diff <= self.opt_common.window_size && diff <= self.window.len() {
+            self.block_seq_win = ack;
+            self.blocks += diff as u64;
+            self.size += self
+                .window
+                .get_elements()
+                .iter()
+                .take(diff as usize)
+                .map(|frame| frame.len() as u64)
+                .sum::<u64>();
+            let _ = self.window.remove(diff);
+            self.sent = self.sent.saturating_sub(diff);
+            if !self.more && self.window.is_empty() {
+                self.outcome = Some(Ok(self.size));
+                return;
+            }
+            if self.more {
+                match self.window.fill() {
+                    Ok(more) => self.more = more,
+                    Err(err) => {
+                        self.outcome = Some(Err(err));
+                        return;
+                    }
+                }
+            }
+            self.win_idx = 0;
+            self.next_send = now;
+        } else {
+            log_dbg!(
+                "      Received Ack with unexpected seq {ack} (prev {})",
+                self.block_seq_win
+            );
+        }
+    }
+}
+
+impl<R: Read> Transfer for SendTransfer<R> {
+    fn handle_packet(&mut self, packet: Packet, now: Instant) {
+        if self.outcome.is_some() {
+            return;
+        }
+
+        match packet {
+            Packet::Error { code, msg } => {
+                self.outcome = Some(Err(Error::Protocol(
+                    code,
+                    format!("Received error code {code}: {msg}"),
+                )));
+            }
+            Packet::Ack(0) if self.awaiting_oack_ack => {
+                self.awaiting_oack_ack = false;
+                self.next_send = now;
+            }
+            packet if self.awaiting_oack_ack => {
+                self.queued.push_back(Packet::Error {
+                    code: ErrorCode::IllegalOperation,
+                    msg: "invalid oack response".to_string(),
+                });
+                self.outcome = Some(Err(Error::Protocol(
+                    ErrorCode::IllegalOperation,
+                    format!("Unexpected packet received instead of Ack(0): {packet:#?}"),
+                )));
+            }
+            Packet::Ack(ack) => self.handle_ack(ack, now),
+            _ => log_info!("  Received unexpected packet"),
+        }
+    }
+
+    fn handle_timeout(&mut self, now: Instant) {
+        if self.outcome.is_some() || now < self.timeout_end {
+            return;
+        }
+        self.timeouts += 1;
+
+        if self.awaiting_oack_ack {
+            self.outcome = Some(Err(Error::Timeout(
+                "Timed out waiting for the OACK acknowledgement".to_string(),
+            )));
+            return;
+        }
+
+        log_info!(
+            "  Ack timeout {}/{}",
+            self.retry_cnt,
+            self.opt_local.max_retries
+        );
+        if self.retry_cnt == self.opt_local.max_retries {
+            self.outcome = Some(Err(Error::Timeout(format!(
+                "Transfer timed out after {} tries",
+                self.opt_local.max_retries
+            ))));
+            return;
+        }
+        self.retry_cnt += 1;
+        self.timeout_end = now + self.opt_common.timeout;
+        self.win_idx = 0;
+        self.next_send = now;
+    }
+
+    fn poll_transmit(&mut self, now: Instant) -> Option<Packet> {
+        if let Some(packet) = self.queued.pop_front() {
+            return Some(packet);
+        }
+        if self.outcome.is_some() || self.awaiting_oack_ack || now < self.next_send {
+            return None;
+        }
+
+        let frame = self.window.get_elements().get(self.win_idx as usize)?;
+        let mut block_seq_tx = self.block_seq_win.wrapping_add(self.win_idx + 1);
+        if block_seq_tx < self.block_seq_win {
+            match self.opt_local.rollover {
+                Rollover::None => {
+                    self.outcome = Some(Err(rollover_failure()));
+                    return Some(rollover_error());
+                }
+                Rollover::Enforce0 | Rollover::DontCare => (),
+                Rollover::Enforce1 => block_seq_tx += 1,
+            }
+        }
+
+        let packet = Packet::Data {
+            block_num: block_seq_tx,
+            data: frame.to_vec(),
+        };
+        if self.win_idx < self.sent {
+            self.retransmits += 1;
+        } else {
+            self.sent = self.win_idx + 1;
+        }
+        self.win_idx += 1;
+        self.timeout_end = now + self.opt_common.timeout;
+
+        if self.win_idx < self.window.len() {
+            self.next_send = now + self.opt_common.window_wait;
+        } else if let Err(err) = self.window.prefill() {
+            self.outcome = Some(Err(err));
+        }
+
+        Some(packet)
+    }
+
+    fn poll_timeout(&self) -> Option<Instant> {
+        if self.outcome.is_some() {
+            None
+        } else if !self.awaiting_oack_ack && self.win_idx < self.window.len() {
+            Some(self.next_send.min(self.timeout_end))
+        } else {
+            Some(self.timeout_end)
+        }
+    }
+
+    fn poll_result(&mut self) -> Option<Result<u64, Error>> {
+        if !self.queued.is_empty() {
+            return None;
+        }
+
+        self.outcome.clone()
+    }
+
+    fn abort(&mut self, msg: &str) {
+        if self.outcome.is_none() {
+            self.queued.push_back(abort_error(msg));
+            self.outcome = Some(Err(Error::Aborted(msg.to_string())));
+        }
+    }
+
+    fn max_packet_size(&self) -> usize {
+        DEFAULT_BLOCK_SIZE as usize
+    }
+
+    fn report(&self) -> TransferReport {
+        TransferReport {
+            bytes: self.size,
+            blocks: self.blocks,
+            options: self.opt_common.clone(),
+            retransmits: self.retransmits,
+            timeouts: self.timeouts,
+            duplicate_acks: self.duplicate_acks,
+            error: outcome_error(&self.outcome),
+            ..Default::default()
+        }
+    }
+}
+
+/// ReceiveTransfer `struct` is the sans-IO state machine receiving data into
+/// a [`Write`] destination, see [`Transfer`] for how to drive it. When a
+/// transfer size was negotiated, it is checked against the received data.
+pub struct ReceiveTransfer<W: Write> {
+    window: WindowWrite<W>,
+    opt_local: OptionsPrivate,
+    opt_common: OptionsProtocol,
+    block_number: u16,
+    retry_cnt: usize,
+    size: u64,
+    last_ack: u16,
+    nacked: bool,
+    reacked: bool,
+    blocks: u64,
+    retransmits: u64,
+    timeouts: u64,
+    timeout_end: Instant,
+    queued: VecDeque<Packet>,
+    outcome: Outcome,
+}
+
+impl<W: Write> ReceiveTransfer<W> {
+    /// Creates a new [`ReceiveTransfer`] writing to `file`, translated
+    /// according to the [`TransferMode`]. The acknowledgement of the request
+    /// is expected to have been sent already.
+    pub fn new(
+        file: W,
+        mode: TransferMode,
+        opt_common: OptionsProtocol,
+        opt_local: OptionsPrivate,
+        now: Instant,
+    ) -> ReceiveTransfer<W> {
+        let window = match mode {
+            TransferMode::Netascii => WindowWrite::new_netascii(opt_common.window_size, file),
+            _ => WindowWrite::new(opt_common.window_size, file),
+        };
+
+        ReceiveTransfer {
+            window,
+            block_number: 0,
+            retry_cnt: 0,
+            size: 0,
+            last_ack: 0,
+            nacked: false,
+            reacked: false,
+            blocks: 0,
+            retransmits: 0,
+            timeouts: 0,
+            timeout_end: now + opt_common.timeout,
+            queued: VecDeque::new(),
+            outcome: None,
+            opt_local,
+            opt_common,
+        }
+    }
+
+    /// Consumes the [`ReceiveTransfer`], returning the underlying destination.
+    pub fn into_inner(self) -> W {
+        self.window.into_inner()
+    }
+
+    fn handle_data(&mut self, received_block_number: u16, data: Vec<u8>, now: Instant) {
+        let mut new_block_number = self.block_number.wrapping_add(1);
+        if new_block_number == 0 {
+            match self.opt_local.rollover {
+                Rollover::None => return self.fail_rollover(),
+                Rollover::Enforce0 => {
+                    if received_block_number == 1 {
+                        log_warn!(
+                            "  Warning: data packet 0 missed. Possible rollover policy mismatch."
+                        );
+                    }
+                }
+                Rollover::Enforce1 => {
+                    new_block_number = 1;
+                    if received_block_number == 0 {
+                        return self.fail_rollover();
+                    }
+                }
+                Rollover::DontCare => {
+                    if received_block_number == 1 {
+                        // Possible data loss if previous packet was 0 and lost
+                        log_dbg!("  Data packet 0 missed. Possible data loss.");
+                        new_block_number = 1;
+                    }
+                }
+            }
+        }
+
+        if received_block_number != new_block_number {
+            log_dbg!("  Data packet mismatch. Received {received_block_number} instead of {new_block_number}.");
+            if received_block_number.wrapping_sub(new_block_number) < self.opt_common.window_size {
+                // A packet was lost, a single ack makes the sender resume from the gap
+                if !self.nacked {
+                    self.nacked = true;
+                    self.acknowledge(now);
+                }
+            } else if !self.reacked {
+                // The sender may have missed the last ack, acknowledging the
+                // current block would make it send a window twice
+                self.reacked = true;
+                self.retransmits += 1;
+                self.queued.push_back(Packet::Ack(self.last_ack));
+            }
+            return;
+        }
+
+        self.block_number = received_block_number;
+        self.blocks += 1;
+        self.nacked = false;
+        self.reacked = false;
+        let last = data.len() < self.opt_common.block_size as usize;
+        self.size += data.len() as u64;
+        if let Some(max_size) = self.opt_local.max_size.filter(|max| self.size > *max) {
+            return self.fail_max_size(max_size);
+        }
+        if let Err(err) = self.window.add(data) {
+            self.outcome = Some(Err(err));
+            return;
+        }
+
+        if self.window.is_full() || last {
+            self.acknowledge(now);
+        }
+        if last && self.outcome.is_none() {
+            self.finish();
+        }
+    }
+
+    fn acknowledge(&mut self, now: Instant) {
+        if let Err(err) = self.window.empty() {
+            self.outcome = Some(Err(err));
+            return;
+        }
+
+        if self.block_number == self.last_ack {
+            self.retransmits += 1;
+        }
+        self.queued.push_back(Packet::Ack(self.block_number));
+        self.last_ack = self.block_number;
+        self.timeout_end = now + self.opt_common.timeout;
+    }
+
+    fn finish(&mut self) {
+        // we should wait and listen a bit more as per RFC 1350 section 6
+        if let Err(err) = self.window.finish() {
+            self.outcome = Some(Err(err));
+            return;
+        }
+
+        self.outcome = match self.opt_common.transfer_size {
+            Some(tsize) if tsize != self.size => Some(Err(Error::io(
+                ErrorKind::InvalidData,
+                format!(
+                    "Size mismatch, negotiated: {tsize}, transferred: {}",
+                    self.size
+                ),
+            ))),
+            _ => Some(Ok(self.size)),
+        };
+    }
+
+    fn fail_rollover(&mut self) {
+        self.queued.push_back(rollover_error());
+        self.outcome = Some(Err(rollover_failure()));
+    }
+
+    fn fail_max_size(&mut self, max_size: u64) {
+        let msg = format!("file exceeds the maximum size of {max_size} bytes");
+        self.queued.push_back(Packet::Error {
+            code: ErrorCode::AccessViolation,
+            msg: msg.clone(),
+        });
+        self.outcome = Some(Err(Error::Protocol(ErrorCode::AccessViolation, msg)));
+    }
+}
+
+impl<W: Write> Transfer for ReceiveTransfer<W> {
+    fn handle_packet(&mut self, packet: Packet, now: Instant) {
+        if self.outcome.is_some() {
+            return;
+        }
+
+        match packet {
+            Packet::Data { block_num, data } => self.handle_data(block_num, data, now),
+            Packet::Error { code, msg } => {
+                self.outcome = Some(Err(Error::Protocol(
+                    code,
+                    format!("Received error '{code}': {msg}"),
+                )));
+            }
+            _ => log_info!("  Received unexpected packet"),
+        }
+    }
+
+    fn handle_timeout(&mut self, now: Instant) {
+        if self.outcome.is_some() || now < self.timeout_end {
+            return;
+        }
+        self.timeouts += 1;
+
+        log_dbg!(
+            "  Ack timeout {}/{}",
+            self.retry_cnt,
+            self.opt_local.max_retries
+        );
+        if self.retry_cnt == self.opt_local.max_retries {
+            self.outcome = Some(Err(Error::Timeout(format!(
+                "Transfer timed out after {} tries",
+                self.opt_local.max_retries
+            ))));
+            return;
+        }
+        self.retry_cnt += 1;
+        self.acknowledge(now);
+    }
+
+    fn poll_transmit(&mut self, _now: Instant) -> Option<Packet> {
+        self.queued.pop_front()
+    }
+
+    fn poll_timeout(&self) -> Option<Instant> {
+        if self.outcome.is_some() {
+            None
+        } else {
+            Some(self.timeout_end)
+        }
+    }
+
+    fn poll_result(&mut self) -> Option<Result<u64, Error>> {
+        if !self.queued.is_empty() {
+            return None;
+        }
+
+        self.outcome.clone()
+    }
+
+    fn abort(&mut self, msg: &str) {
+        if self.outcome.is_none() {
+            self.queued.push_back(abort_error(msg));
+            self.outcome = Some(Err(Error::Aborted(msg.to_string())));
+        }
+    }
+
+    fn max_packet_size(&self) -> usize {
+        std::cmp::max(MAX_ERROR_PACKET_SIZE, self.opt_common.block_size as usize)
+    }
+
+    fn report(&self) -> TransferReport {
+        TransferReport {
+            bytes: self.size,
+            blocks: self.blocks,
+            options: self.opt_common.clone(),
+            retransmits: self.retransmits,
+            timeouts: self.timeouts,
+            error: outcome_error(&self.outcome),
+            ..Default::default()
+        }
+    }
+}
+
+fn outcome_error(outcome: &Outcome) -> Option<Error> {
+    match outcome {
+        Some(Err(err)) => Some(err.clone()),
+        _ => None,
+    }
+}
+
+fn rollover_error() -> Packet {
+    Packet::Error {
+        code: ErrorCode::IllegalOperation,
+        msg: "Block counter rollover error".to_string(),
+    }
+}
+
+fn rollover_failure() -> Error {
+    Error::Protocol(
+        ErrorCode::IllegalOperation,
+        "Block counter rollover error".to_string(),
+    )
+}
+
+fn abort_error(msg: &str) -> Packet {
+    Packet::Error {
+        code: ErrorCode::NotDefined,
+        msg: msg.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    fn options(block_size: u16, window_size: u16) -> OptionsProtocol {
+        OptionsProtocol {
+            block_size,
+            window_size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn transfers_with_windows() {
+        let now = Instant::now();
+        let content: Vec<u8> = (0..100).collect();
+        let mut sender = SendTransfer::new(
+            Cursor::new(content.clone()),
+            TransferMode::Octet,
+            options(8, 4),
+            Default::default(),
+            false,
+            now,
+        )
+        .unwrap();
+        let mut receiver = ReceiveTransfer::new(
+            Vec::new(),
+            TransferMode::Octet,
+            options(8, 4),
+            Default::default(),
+            now,
+        );
+
+        let mut dropped = false;
+        while sender.outcome.is_none() {
+            while let Some(packet) = sender.poll_transmit(now) {
+                // Losing a packet makes the receiver acknowledge up to the gap
+                if !dropped
+                    && packet
+                        == (Packet::Data {
+                            block_num: 6,
+                            data: (40..48).collect(),
+                        })
+                {
+                    dropped = true;
+                    continue;
+                }
+                receiver.handle_packet(packet, now);
+            }
+            while let Some(packet) = receiver.poll_transmit(now) {
+                sender.handle_packet(packet, now);
+            }
+        }
+
+        assert!(dropped);
+        let report = sender.report();
+        assert_eq!(report.retransmits, 3);
+        assert_eq!(report.blocks, 13);
+        assert_eq!(receiver.report().bytes, 100);
+        assert_eq!(sender.poll_result().unwrap().unwrap(), 100);
+        assert_eq!(receiver.poll_result().unwrap().unwrap(), 100);
+        assert_eq!(receiver.into_inner(), content);
+    }
+
+    #[test]
+    fn retransmits_on_timeout() {
+        let now = Instant::now();
+        let mut sender = SendTransfer::new(
+            Cursor::new(vec![1, 2, 3]),
+            TransferMode::Octet,
+            options(512, 1),
+            OptionsPrivate {
+                max_retries: 1,
+                ..Default::default()
+            },
+            false,
+            now,
+        )
+        .unwrap();
+
+        let data = sender.poll_transmit(now).unwrap();
+        assert!(sender.poll_transmit(now).is_none());
+
+        let deadline = sender.poll_timeout().unwrap();
+        assert_eq!(deadline, now + Duration::from_secs(5));
+        sender.handle_timeout(deadline - Duration::from_secs(1));
+        assert!(sender.poll_transmit(deadline).is_none());
+
+        sender.handle_timeout(deadline);
+        assert_eq!(sender.poll_transmit(deadline), Some(data));
+        assert_eq!(sender.report().retransmits, 1);
+
+        sender.handle_timeout(sender.poll_timeout().unwrap());
+        assert!(sender.poll_transmit(deadline).is_none());
+        let err = sender.poll_result().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "Transfer timed out after 1 tries");
+        assert!(matches!(err, Error::Timeout(_)));
+        assert!(sender.poll_timeout().is_none());
+        assert_eq!(sender.report().timeouts, 2);
+    }
+
+    #[test]
+    fn checks_oack_response_and_size() {
+        let now = Instant::now();
+        let mut sender = SendTransfer::new(
+            Cursor::new(vec![1]),
+            TransferMode::Octet,
+            Default::default(),
+            Default::default(),
+            true,
+            now,
+        )
+        .unwrap();
+        assert!(sender.poll_transmit(now).is_none());
+        sender.handle_packet(Packet::Ack(1), now);
+        assert!(matches!(
+            sender.poll_transmit(now),
+            Some(Packet::Error {
+                code: ErrorCode::IllegalOperation,
+                ..
+            })
+        ));
+        assert!(sender.poll_result().unwrap().is_err());
+
+        let mut receiver = ReceiveTransfer::new(
+            Vec::new(),
+            TransferMode::Octet,
+            OptionsProtocol {
+                transfer_size: Some(2),
+                ..Default::default()
+            },
+            Default::default(),
+            now,
+        );
+        receiver.handle_packet(
+            Packet::Data {
+                block_num: 1,
+                data: vec![1],
+            },
+            now,
+        );
+        assert_eq!(receiver.poll_transmit(now), Some(Packet::Ack(1)));
+        assert_eq!(
+            receiver.poll_result().unwrap().unwrap_err().to_string(),
+            "Size mismatch, negotiated: 2, transferred: 1"
+        );
+    }
+
+    #[test]
+    fn aborts_receiving_beyond_max_size() {
+        let now = Instant::now();
+        let mut receiver = ReceiveTransfer::new(
+            Vec::new(),
+            TransferMode::Octet,
+            options(4, 1),
+            OptionsPrivate {
+                max_size: Some(6),
+                ..Default::default()
+            },
+            now,
+        );
+        receiver.handle_packet(
+            Packet::Data {
+                block_num: 1,
+                data: vec![1; 4],
+            },
+            now,
+        );
+        assert_eq!(receiver.poll_transmit(now), Some(Packet::Ack(1)));
+        assert!(receiver.poll_result().is_none());
+
+        receiver.handle_packet(
+            Packet::Data {
+                block_num: 2,
+                data: vec![2; 4],
+            },
+            now,
+        );
+        assert!(matches!(
+            receiver.poll_transmit(now),
+            Some(Packet::Error {
+                code: ErrorCode::AccessViolation,
+                ..
+            })
+        ));
+        assert!(matches!(
+            receiver.poll_result().unwrap(),
+            Err(Error::Protocol(ErrorCode::AccessViolation, _))
+        ));
+        assert_eq!(receiver.into_inner(), vec![1; 4]);
+    }
+}
@@ -1,52 +1,67 @@
 use std::{
     collections::VecDeque,
-    error::Error,
     fs::File,
-    io::{BufRead, BufReader, Read, Write},
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
 };
 
-/// WindowRead `struct` is used to store chunks of data from a file. It is
-/// used to help store the data that is being sent for the
-/// [RFC 7440](https://www.rfc-editor.org/rfc/rfc7440) Windowsize option.
+use crate::{Error, NetasciiDecoder, NetasciiEncoder};
+
+/// WindowRead `struct` is used to store chunks of data from a file, or from
+/// any other [`Read`] source. It is used to help store the data that is being
+/// sent for the [RFC 7440](https://www.rfc-editor.org/rfc/rfc7440) Windowsize option.
 ///
 /// # Example
 /// ```rust
-/// use std::{fs::{self, OpenOptions, File}, io::Write};
+/// use std::{env, fs::{self, File}, io::Write};
 /// use tftpd::WindowRead;
 ///
-/// let mut file = File::create("test.txt").unwrap();
+/// let path = env::temp_dir().join("tftpd_window_read.txt");
+/// let mut file = File::create(&path).unwrap();
 /// file.write_all(b"Hello, world!").unwrap();
 /// file.flush().unwrap();
 ///
-/// let file = File::open("test.txt").unwrap();
+/// let file = File::open(&path).unwrap();
 /// let mut window = WindowRead::new(5, 512, file);
 /// window.fill().unwrap();
-/// fs::remove_file("test.txt").unwrap();
+/// fs::remove_file(&path).unwrap();
 /// ```
-pub struct WindowRead {
+pub struct WindowRead<R: Read = File> {
     elements: VecDeque<Vec<u8>>,
     size: u16,
     chunk_size: u16,
-    bufreader: BufReader<File>,
+    bufreader: BufReader<R>,
+    encoder: Option<NetasciiEncoder>,
 }
 
-impl WindowRead {
+impl<R: Read> WindowRead<R> {
     /// Creates a new `Window` with the supplied size and chunk size.
-    pub fn new(size: u16, chunk_size: u16, file: File) -> WindowRead {
+    pub fn new(size: u16, chunk_size: u16, file: R) -> WindowRead<R> {
         WindowRead {
             elements: VecDeque::new(),
             size,
             chunk_size,
             bufreader: BufReader::with_capacity(2 * size as usize * chunk_size as usize, file),
+            encoder: None,
+        }
+    }
+
+    /// Creates a new `Window` which translates the file to netascii while reading.
+    pub fn new_netascii(size: u16, chunk_size: u16, file: R) -> WindowRead<R> {
+        WindowRead {
+            encoder: Some(NetasciiEncoder::default()),
+            ..WindowRead::new(size, chunk_size, file)
         }
     }
 
     /// Fills the `Window` with chunks of data from the file.
     /// Returns `true` if the `Window` is full.
-    pub fn fill(&mut self) -> Result<bool, Box<dyn Error>> {
+    pub fn fill(&mut self) -> Result<bool, Error> {
         for _ in self.len()..self.size {
             let mut chunk = vec![0; self.chunk_size as usize];
-            let size = self.bufreader.read(&mut chunk)?;
+            let size = match &mut self.encoder {
+                Some(encoder) => encoder.read(&mut self.bufreader, &mut chunk)?,
+                None => read_chunk(&mut self.bufreader, &mut chunk)?,
+            };
             if size != self.chunk_size as usize {
                 chunk.truncate(size);
                 self.elements.push_back(chunk);
@@ -60,15 +75,18 @@ impl WindowRead {
     }
 
     /// Fill the read buffer to speed up next window fill
-    pub fn prefill(&mut self) -> Result<(), Box<dyn Error>> {
+    pub fn prefill(&mut self) -> Result<(), Error> {
         self.bufreader.fill_buf()?;
         Ok(())
     }
 
     /// Removes the first `amount` of elements from the `Window`.
-    pub fn remove(&mut self, amount: u16) -> Result<(), &'static str> {
+    pub fn remove(&mut self, amount: u16) -> Result<(), Error> {
         if amount > self.len() {
-            return Err("amount cannot be larger than length of window");
+            return Err(Error::io(
+                ErrorKind::InvalidInput,
+                "amount cannot be larger than length of window",
+            ));
         }
 
         drop(self.elements.drain(0..amount as usize));
@@ -92,41 +110,58 @@ impl WindowRead {
     }
 }
 
-/// WindowWrite `struct` is used to store data and write them in a file.
+/// WindowWrite `struct` is used to store data and write them in a file, or in
+/// any other [`Write`] destination.
 /// It is used to help store the data that is being received for the
 /// [RFC 7440](https://www.rfc-editor.org/rfc/rfc7440) Windowsize option.
 ///
 /// # Example
 /// ```rust
-/// use std::{fs::{self, OpenOptions, File}, io::Write};
+/// use std::{env, fs::{self, File}};
 /// use tftpd::WindowWrite;
 ///
-/// let file = File::create("test.txt").unwrap();
+/// let path = env::temp_dir().join("tftpd_window_write.txt");
+/// let file = File::create(&path).unwrap();
 /// let mut window = WindowWrite::new(5, file);
 /// window.add(vec![0x1, 0x2, 0x3]).unwrap();
 /// window.add(vec![0x4, 0x5, 0x6]).unwrap();
 /// window.empty().unwrap();
+/// fs::remove_file(&path).unwrap();
 /// ```
-pub struct WindowWrite {
+pub struct WindowWrite<W: Write = File> {
     elements: VecDeque<Vec<u8>>,
     size: u16,
-    file: File,
+    file: W,
+    decoder: Option<NetasciiDecoder>,
 }
 
-impl WindowWrite {
+impl<W: Write> WindowWrite<W> {
     /// Creates a new `Window` with the supplied size and chunk size.
-    pub fn new(size: u16, file: File) -> WindowWrite {
+    pub fn new(size: u16, file: W) -> WindowWrite<W> {
         WindowWrite {
             elements: VecDeque::new(),
             size,
             file,
+            decoder: None,
+        }
+    }
+
+    /// Creates a new `Window` which translates the received netascii data
+    /// while writing to the file.
+    pub fn new_netascii(size: u16, file: W) -> WindowWrite<W> {
+        WindowWrite {
+            decoder: Some(NetasciiDecoder::default()),
+            ..WindowWrite::new(size, file)
         }
     }
 
     /// Empties the `Window` by writing the data to the file.
-    pub fn empty(&mut self) -> Result<(), Box<dyn Error>> {
+    pub fn empty(&mut self) -> Result<(), Error> {
         for data in &self.elements {
-            self.file.write_all(data)?;
+            match &mut self.decoder {
+                Some(decoder) => self.file.write_all(&decoder.decode(data))?,
+                None => self.file.write_all(data)?,
+            }
         }
 
         self.elements.clear();
@@ -134,10 +169,23 @@ impl WindowWrite {
         Ok(())
     }
 
+    /// Writes the data held back by the netascii translation. Should be called
+    /// once the last block has been emptied.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        if let Some(decoder) = &mut self.decoder {
+            self.file.write_all(&decoder.finish())?;
+        }
+
+        Ok(())
+    }
+
     /// Adds a data `Vec<u8>` to the `Window`.
-    pub fn add(&mut self, data: Vec<u8>) -> Result<(), &'static str> {
+    pub fn add(&mut self, data: Vec<u8>) -> Result<(), Error> {
         if self.len() == self.size {
-            return Err("cannot add to a full window");
+            return Err(Error::io(
+                ErrorKind::InvalidInput,
+                "cannot add to a full window",
+            ));
         }
 
         self.elements.push_back(data);
@@ -160,12 +208,36 @@ impl WindowWrite {
         self.elements.len() as u16 == self.size
     }
 
+    /// Consumes the `Window`, returning the underlying destination.
+    pub fn into_inner(self) -> W {
+        self.file
+    }
+}
+
+impl WindowWrite<File> {
     /// Returns the length of the file
-    pub fn file_len(&self) -> Result<u64, Box<dyn Error>> {
+    pub fn file_len(&self) -> Result<u64, Error> {
         Ok(self.file.metadata()?.len())
     }
 }
 
+/// Reads from `reader` until `chunk` is full or the end of the reader has
+/// been reached, as streams such as pipes may return less than requested.
+fn read_chunk<R: Read>(reader: &mut R, chunk: &mut [u8]) -> io::Result<usize> {
+    let mut size = 0;
+
+    while size < chunk.len() {
+        match reader.read(&mut chunk[size..]) {
+            Ok(0) => break,
+            Ok(read) => size += read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(size)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -205,6 +277,19 @@ mod tests {
         clean(FILENAME);
     }
 
+    #[test]
+    fn fills_window_from_short_reads() {
+        // The first read of a chained reader stops at the end of its first part
+        let stream = Read::chain(&[1; 100][..], &[2; 1000][..]);
+
+        let mut window = WindowRead::new(4, 512, stream);
+        assert!(!window.fill().unwrap());
+        assert_eq!(window.elements.len(), 3);
+        assert_eq!(window.elements[0].len(), 512);
+        assert_eq!(window.elements[1].len(), 512);
+        assert_eq!(window.elements[2].len(), 76);
+    }
+
     #[test]
     fn adds_to_and_empties_window() {
         const FILENAME: &str = "adds_to_and_empties_window.txt";
@@ -241,6 +326,41 @@ mod tests {
         clean(FILENAME);
     }
 
+    #[test]
+    fn translates_netascii_window() {
+        const FILENAME: &str = "translates_netascii_window.txt";
+
+        let mut file = initialize(FILENAME);
+        file.write_all(b"ab\ncd\r").unwrap();
+        file.flush().unwrap();
+        drop(file);
+
+        let mut window = WindowRead::new_netascii(4, 3, open(FILENAME));
+        assert!(!window.fill().unwrap());
+        assert_eq!(window.elements.len(), 3);
+        assert_eq!(window.elements[0], b"ab\r"[..]);
+        assert_eq!(window.elements[1], b"\ncd"[..]);
+        assert_eq!(window.elements[2], b"\r\0"[..]);
+        clean(FILENAME);
+
+        let mut window = WindowWrite::new_netascii(4, initialize(FILENAME));
+        window.add(b"ab\r".to_vec()).unwrap();
+        window.add(b"\ncd".to_vec()).unwrap();
+        window.add(b"\r\0".to_vec()).unwrap();
+        window.empty().unwrap();
+        window.finish().unwrap();
+
+        let mut contents = Default::default();
+        File::read_to_string(
+            &mut File::open(DIR_NAME.to_string() + "/" + FILENAME).unwrap(),
+            &mut contents,
+        )
+        .unwrap();
+        assert_eq!(contents, "ab\ncd\r");
+
+        clean(FILENAME);
+    }
+
     fn initialize(filename: &str) -> File {
         let filename = DIR_NAME.to_string() + "/" + filename;
 
@@ -1,35 +1,39 @@
 use std::{
-    error::Error,
-    fs::{self, File},
-    io::ErrorKind,
+    io::{ErrorKind, Read},
     path::PathBuf,
-    sync::{atomic::AtomicBool, Arc},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     thread,
     time::{Duration, Instant},
 };
 
 use crate::log::*;
-use crate::options::{OptionsPrivate, OptionsProtocol, Rollover};
-use crate::{ErrorCode, Packet, Socket, WindowRead, WindowWrite};
+use crate::observer::TransferTracker;
+use crate::options::{OptionsPrivate, OptionsProtocol};
+use crate::{
+    Direction, Error, FileSink, Observer, Packet, ReceiveTransfer, SendTransfer, Socket, Transfer,
+    TransferInfo, TransferMode, TransferReport,
+};
 
 #[cfg(feature = "debug_drop")]
 use crate::drop::drop_check;
 
 const DEFAULT_DUPLICATE_DELAY: Duration = Duration::from_millis(1);
-// Chosen arbitrarily because not specified in RFC
-const MAX_ERROR_PACKET_SIZE: usize = 128;
 
 /// Worker `struct` is used for multithreaded file sending and receiving.
 /// It creates a new socket using the Server's IP and a random port
-/// requested from the OS to communicate with the requesting client.
+/// requested from the OS to communicate with the requesting client, and
+/// drives a [`SendTransfer`] or a [`ReceiveTransfer`] from its own thread.
 ///
 /// See [`Worker::send()`] and [`Worker::receive()`] for more details.
 ///
 /// # Example
 ///
 /// ```rust
-/// use std::{net::{UdpSocket, SocketAddr}, path::PathBuf, str::FromStr, time::Duration};
-/// use tftpd::{Worker};
+/// use std::{fs::File, net::{UdpSocket, SocketAddr}, path::PathBuf, str::FromStr, time::Duration};
+/// use tftpd::{TransferMode, Worker};
 ///
 /// // Send a file, responding to a read request.
 /// let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
@@ -39,26 +43,33 @@ const MAX_ERROR_PACKET_SIZE: usize = 128;
 /// let worker = Worker::new(
 ///     Box::new(socket),
 ///     PathBuf::from_str("Cargo.toml").unwrap(),
+///     TransferMode::Octet,
 ///     Default::default(),
 ///     Default::default(),
 ///     Default::default(),
 /// );
 ///
-/// worker.send(has_options).unwrap();
+/// let file = File::open("Cargo.toml").unwrap();
+/// worker.send(Box::new(file), has_options).unwrap();
 /// ```
 pub struct Worker<T: Socket + ?Sized> {
     socket: Box<T>,
     file_path: PathBuf,
+    mode: TransferMode,
     opt_local: OptionsPrivate,
     opt_common: OptionsProtocol,
     abort: Arc<AtomicBool>,
+    observer: Option<Arc<dyn Observer>>,
+    first_packet: Option<Packet>,
 }
 
 impl<T: Socket + ?Sized> Worker<T> {
-    /// Creates a new [`Worker`] with the supplied options.
+    /// Creates a new [`Worker`] with the supplied options. The file is
+    /// translated according to the [`TransferMode`] during the transfer.
     pub fn new(
         socket: Box<T>,
         file_path: PathBuf,
+        mode: TransferMode,
         opt_local: OptionsPrivate,
         opt_common: OptionsProtocol,
         abort: Arc<AtomicBool>,
@@ -66,353 +77,159 @@ impl<T: Socket + ?Sized> Worker<T> {
         Worker {
             socket,
             file_path,
+            mode,
             opt_local,
             opt_common,
             abort,
+            observer: None,
+            first_packet: None,
         }
     }
 
-    /// Sends a file to the remote [`SocketAddr`] that has sent a read request using
-    /// a random port, asynchronously.
-    pub fn send(self, check_response: bool) -> Result<thread::JoinHandle<bool>, Box<dyn Error>> {
-        let file_path = self.file_path.clone();
-        let remote_addr = self.socket.remote_addr().unwrap();
+    /// Sets the [`Observer`] notified of the progress and the outcome of the
+    /// transfer.
+    pub fn set_observer(&mut self, observer: Arc<dyn Observer>) {
+        self.observer = Some(observer);
+    }
 
-        let handle = thread::spawn(move || {
-            let handle_send = || -> Result<(), Box<dyn Error>> {
-                self.send_file(File::open(&file_path)?, check_response)
-            };
+    /// Sets a packet already received from the remote peer, such as the
+    /// first data block of a server which does not support options. It is
+    /// handled by [`Worker::receive()`] before waiting for the next packets.
+    pub fn set_first_packet(&mut self, packet: Packet) {
+        self.first_packet = Some(packet);
+    }
 
-            match handle_send() {
-                Ok(_) => {
-                    log_info!(
-                        "Sent {} to {}",
-                        &file_path.file_name().unwrap().to_string_lossy(),
-                        &remote_addr
-                    );
-                    true
-                }
-                Err(err) => {
-                    log_err!(
-                        "Error \"{err}\", while sending {} to {}",
-                        &file_path.file_name().unwrap().to_string_lossy(),
-                        &remote_addr
-                    );
-                    false
-                }
-            }
+    /// Sends the content of `file` to the remote [`std::net::SocketAddr`]
+    /// that has sent a read request using a random port, asynchronously. The
+    /// thread returns the [`TransferReport`] of the transfer.
+    pub fn send(
+        mut self,
+        file: Box<dyn Read + Send>,
+        check_response: bool,
+    ) -> Result<thread::JoinHandle<TransferReport>, Error> {
+        if !self.mode.is_supported() {
+            return Err(Error::io(
+                ErrorKind::Unsupported,
+                format!("Unsupported transfer mode {}", self.mode),
+            ));
+        }
+
+        let mut tracker = self.tracker(Direction::Send);
+
+        let handle = thread::spawn(move || {
+            let result = SendTransfer::new(
+                file,
+                self.mode,
+                self.opt_common.clone(),
+                self.opt_local.clone(),
+                check_response,
+                Instant::now(),
+            )
+            .and_then(|mut transfer| self.run(&mut transfer, &mut tracker));
+
+            finish_send(result, &mut tracker)
         });
 
         Ok(handle)
     }
 
-    /// Receives a file from the remote [`SocketAddr`] (client or server) using
-    /// the supplied socket, asynchronously.
-    pub fn receive(self) -> Result<thread::JoinHandle<bool>, Box<dyn Error>> {
-        let clean_on_error = self.opt_local.clean_on_error;
-        let file_path = self.file_path.clone();
-        let remote_addr = self.socket.remote_addr().unwrap();
-        let opt_tsize = self.opt_common.transfer_size;
-
-        let handle = thread::spawn(move || {
-            let handle_receive =
-                || -> Result<u64, Box<dyn Error>> { self.receive_file(File::create(&file_path)?) };
+    /// Receives a file from the remote [`std::net::SocketAddr`] (client or
+    /// server) using the supplied socket into `file`, asynchronously. The
+    /// thread returns the [`TransferReport`] of the transfer.
+    pub fn receive(
+        mut self,
+        file: Box<dyn FileSink>,
+    ) -> Result<thread::JoinHandle<TransferReport>, Error> {
+        if !self.mode.is_supported() {
+            return Err(Error::io(
+                ErrorKind::Unsupported,
+                format!("Unsupported transfer mode {}", self.mode),
+            ));
+        }
 
-            match handle_receive() {
-                Ok(size) => {
-                    if let Some(tsize) = opt_tsize {
-                        if tsize != size {
-                            log_err!("Size mismatch, negotiated: {tsize}, transferred: {size}");
-                            return false;
-                        }
-                    }
+        let mut tracker = self.tracker(Direction::Receive);
 
-                    log_info!(
-                        "Received {} ({} bytes) from {}",
-                        &file_path.file_name().unwrap().to_string_lossy(),
-                        size,
-                        remote_addr
-                    );
-                    true
-                }
-                Err(err) => {
-                    log_err!(
-                        "Error \"{err}\", while receiving {} from {}",
-                        &file_path.file_name().unwrap().to_string_lossy(),
-                        remote_addr
-                    );
-                    if clean_on_error && fs::remove_file(&file_path).is_err() {
-                        log_err!("Error while cleaning {}", &file_path.to_str().unwrap());
-                    }
-                    false
-                }
+        let handle = thread::spawn(move || {
+            let mut transfer = ReceiveTransfer::new(
+                file,
+                self.mode,
+                self.opt_common.clone(),
+                self.opt_local.clone(),
+                Instant::now(),
+            );
+            if let Some(packet) = self.first_packet.take() {
+                transfer.handle_packet(packet, Instant::now());
             }
+            let result = self.run(&mut transfer, &mut tracker);
+
+            finish_receive(
+                result,
+                transfer.into_inner(),
+                &mut tracker,
+                self.opt_local.clean_on_error,
+            )
         });
 
         Ok(handle)
     }
 
-    fn send_file(mut self, file: File, check_response: bool) -> Result<(), Box<dyn Error>> {
-        let mut block_seq_win: u16 = 0;
-        let mut win_idx: u16 = 0;
-        let mut window = WindowRead::new(
-            self.opt_common.window_size,
-            self.opt_common.block_size,
-            file,
-        );
-        let mut more = window.fill()?;
-
-        let mut timeout_end = Instant::now() + self.opt_common.timeout;
-        let mut retry_cnt = 0;
-
-        if cfg!(windows) {
-            // On Windows, recv can return up to 15ms before timeout
-            self.socket
-                .set_read_timeout(self.opt_common.timeout + Duration::from_millis(15))?;
-        } else if cfg!(unix) {
-            self.socket.set_read_timeout(self.opt_common.timeout)?;
-        }
-
-        if check_response {
-            self.check_response()?;
-        }
+    fn tracker(&self, direction: Direction) -> TransferTracker {
+        let info = TransferInfo {
+            file_path: self.file_path.clone(),
+            peer: self.socket.remote_addr().unwrap(),
+            direction,
+        };
 
-        self.socket.set_nonblocking(true)?;
+        TransferTracker::new(info, self.opt_common.clone(), self.observer.clone())
+    }
 
+    fn run<X: Transfer>(
+        &mut self,
+        transfer: &mut X,
+        tracker: &mut TransferTracker,
+    ) -> Result<u64, Error> {
         loop {
-            if let Some(frame) = window.get_elements().get(win_idx as usize) {
-                let mut block_seq_tx = block_seq_win.wrapping_add(win_idx + 1);
-                if block_seq_tx < block_seq_win {
-                    match self.opt_local.rollover {
-                        Rollover::None => return Err(self.send_rollover_error()),
-                        Rollover::Enforce0 | Rollover::DontCare => (),
-                        Rollover::Enforce1 => block_seq_tx += 1,
-                    }
-                }
-
-                self.send_packet(&Packet::Data {
-                    block_num: block_seq_tx,
-                    data: frame.to_vec(),
-                })?;
-                win_idx += 1;
-
-                if win_idx < window.len() {
-                    if !self.opt_common.window_wait.is_zero() {
-                        thread::sleep(self.opt_common.window_wait);
-                    }
-                } else {
-                    window.prefill()?;
-                    self.socket.set_nonblocking(false)?;
-                }
-
-                timeout_end = Instant::now() + self.opt_common.timeout;
+            while let Some(packet) = transfer.poll_transmit(Instant::now()) {
+                self.send_packet(&packet)?;
             }
-
-            let mut last_ack: Option<u16> = None;
-            loop {
-                self.check_abort()?;
-
-                match self.socket.recv() {
-                    Ok(Packet::Ack(block_seq_rx)) => {
-                        if last_ack.is_none() {
-                            self.socket.set_nonblocking(true)?;
-                        }
-                        last_ack = Some(block_seq_rx);
-                        continue;
-                    }
-
-                    Ok(Packet::Error { code, msg }) => {
-                        return Err(format!("Received error code {code}: {msg}").into())
-                    }
-
-                    Ok(_) => log_info!("  Received unexpected packet"),
-
-                    Err(e) => {
-                        if let Some(io_e) = e.downcast_ref::<std::io::Error>() {
-                            match io_e.kind() {
-                                /* On non-blocking sockets, Windows returns WouldBlock and Unix TimedOut */
-                                ErrorKind::WouldBlock | ErrorKind::TimedOut => {
-                                    if let Some(ack) = last_ack {
-                                        let mut diff = ack.wrapping_sub(block_seq_win);
-                                        if ack < block_seq_win
-                                            && self.opt_local.rollover == Rollover::Enforce1
-                                        {
-                                            diff -= 1;
-                                        }
-
-                                        if diff == 0 {
-                                            break;
-                                        } else if diff <= self.opt_common.window_size {
-                                            block_seq_win = ack;
-                                            window.remove(diff)?;
-                                            if !more && window.is_empty() {
-                                                return Ok(());
-                                            }
-                                            more = more && window.fill()?;
-                                            win_idx = 0;
-                                            break;
-                                        } else {
-                                            log_dbg!("      Received Ack with unexpected seq {ack} (prev {block_seq_win})");
-                                        }
-                                    }
-                                    if win_idx < window.len() && Instant::now() < timeout_end {
-                                        break;
-                                    }
-                                }
-                                ErrorKind::ConnectionReset => {
-                                    log_info!("  Cnx reset during reception {io_e:?}")
-                                }
-                                _ => log_warn!("  IO error during reception {io_e:?}"),
-                            }
-                        } else {
-                            log_warn!("  Unkown error during reception {e:?}");
-                        }
-                    }
-                }
-
-                if timeout_end < Instant::now() {
-                    log_info!("  Ack timeout {}/{}", retry_cnt, self.opt_local.max_retries);
-                    if retry_cnt == self.opt_local.max_retries {
-                        return Err(format!(
-                            "Transfer timed out after {} tries",
-                            self.opt_local.max_retries
-                        )
-                        .into());
-                    }
-                    retry_cnt += 1;
-                    timeout_end = Instant::now() + self.opt_common.timeout;
-                    win_idx = 0;
-                    self.socket.set_nonblocking(true)?;
-                    break;
-                }
+            tracker.update(transfer);
+            if let Some(result) = transfer.poll_result() {
+                return result;
+            }
+            if self.abort.load(Ordering::Relaxed) {
+                transfer.abort("Transfert aborted by user");
+                continue;
             }
-        }
-    }
-
-    fn send_rollover_error(&self) -> Box<dyn Error> {
-        self.send_packet(&Packet::Error {
-            code: ErrorCode::IllegalOperation,
-            msg: "Block counter rollover error".to_string(),
-        })
-        .unwrap_or_else(|err| {
-            log_err!("Error: error '{err:?}' while sending error code");
-        });
-        "Block counter rollover error".into()
-    }
-
-    fn receive_file(mut self, file: File) -> Result<u64, Box<dyn Error>> {
-        // rx socket size for data and error packets
-        let max_pkt_size: usize =
-            std::cmp::max(MAX_ERROR_PACKET_SIZE, self.opt_common.block_size as usize);
-        let mut block_number: u16 = 0;
-        let mut window = WindowWrite::new(self.opt_common.window_size, file);
-        let mut retry_cnt = 0;
-
-        let mut last = false;
-        let mut listen_all = false;
-        let mut send_ack = false;
-
-        while !last {
-            while !send_ack {
-                match self.socket.recv_with_size(max_pkt_size) {
-                    Ok(Packet::Data {
-                        block_num: received_block_number,
-                        data,
-                    }) => {
-                        let mut new_block_number = block_number.wrapping_add(1);
-                        if new_block_number == 0 {
-                            match self.opt_local.rollover {
-                                Rollover::None => return Err(self.send_rollover_error()),
-                                Rollover::Enforce0 => {
-                                    if received_block_number == 1 {
-                                        log_warn!("  Warning: data packet 0 missed. Possible rollover policy mismatch.");
-                                    }
-                                }
-                                Rollover::Enforce1 => {
-                                    new_block_number = 1;
-                                    if received_block_number == 0 {
-                                        return Err(self.send_rollover_error());
-                                    }
-                                }
-                                Rollover::DontCare => {
-                                    if received_block_number == 1 {
-                                        // Possible data loss if previous packet was 0 and lost
-                                        log_dbg!("  Data packet 0 missed. Possible data loss.");
-                                        new_block_number = 1;
-                                    }
-                                }
-                            }
-                        }
-
-                        if received_block_number == new_block_number {
-                            block_number = received_block_number;
-                            last = data.len() < self.opt_common.block_size as usize;
-                            window.add(data)?;
-                            send_ack = window.is_full() || last;
-                        } else {
-                            log_dbg!("  Data packet mismatch. Received {received_block_number} instead of {new_block_number}.");
-                            send_ack = true;
-                        }
 
-                        self.socket.set_nonblocking(true)?;
-                        listen_all = true;
-                    }
-                    Ok(Packet::Error { code, msg }) => {
-                        return Err(format!("Received error '{code}': {msg}").into());
-                    }
-                    Ok(_) => log_info!("  Received unexpected packet"),
+            let now = Instant::now();
+            let deadline = transfer
+                .poll_timeout()
+                .unwrap_or(now + self.opt_common.timeout);
+            if deadline <= now {
+                transfer.handle_timeout(now);
+                continue;
+            }
 
-                    Err(e) => {
-                        if let Some(io_e) = e.downcast_ref::<std::io::Error>() {
-                            match io_e.kind() {
-                                ErrorKind::WouldBlock | ErrorKind::TimedOut => {
-                                    if listen_all {
-                                        self.socket.set_nonblocking(false)?;
-                                        listen_all = false;
-                                    } else {
-                                        log_dbg!(
-                                            "  Ack timeout {}/{}",
-                                            retry_cnt,
-                                            self.opt_local.max_retries
-                                        );
-                                        if retry_cnt == self.opt_local.max_retries {
-                                            return Err(format!(
-                                                "Transfer timed out after {} tries",
-                                                self.opt_local.max_retries
-                                            )
-                                            .into());
-                                        }
-                                        retry_cnt += 1;
-                                        send_ack = true;
-                                    }
-                                }
-                                ErrorKind::ConnectionReset => {
-                                    log_info!("  Cnx reset during reception {io_e:?}");
-                                    self.socket.set_nonblocking(false)?;
-                                }
-                                _ => log_warn!("  IO error during reception {io_e:?}"),
-                            }
-                        } else {
-                            log_warn!("  Unkown error during reception {e:?}");
+            // The read timeout is set to the deadline of the transfer
+            self.socket.set_read_timeout(deadline - now)?;
+            match self.socket.recv_with_size(transfer.max_packet_size()) {
+                Ok(packet) => transfer.handle_packet(packet, Instant::now()),
+                Err(e) => {
+                    match e {
+                        Error::Timeout(_) => (),
+                        Error::Io(io_e) if io_e.kind() == ErrorKind::ConnectionReset => {
+                            log_info!("  Cnx reset during reception {io_e:?}")
                         }
+                        Error::Io(io_e) => log_warn!("  IO error during reception {io_e:?}"),
+                        _ => (),
                     }
+                    transfer.handle_timeout(Instant::now());
                 }
-
-                self.check_abort()?;
             }
-
-            self.send_packet(&Packet::Ack(block_number))?;
-            send_ack = false;
-
-            window.empty()?;
         }
-
-        // we should wait and listen a bit more as per RFC 1350 section 6
-
-        window.file_len()
     }
 
-    fn send_packet(&self, packet: &Packet) -> Result<(), Box<dyn Error>> {
+    fn send_packet(&self, packet: &Packet) -> Result<(), Error> {
         #[cfg(feature = "debug_drop")]
         if drop_check(packet) {
             return Ok(());
@@ -422,51 +239,88 @@ impl<T: Socket + ?Sized> Worker<T> {
             if i > 0 {
                 thread::sleep(DEFAULT_DUPLICATE_DELAY);
             }
-            loop {
-                match self.socket.send(packet) {
-                    Ok(_) => break,
-                    Err(e) => {
-                        if let Some(io_e) = e.downcast_ref::<std::io::Error>() {
-                            if let ErrorKind::WouldBlock | ErrorKind::TimedOut = io_e.kind() {
-                                thread::sleep(DEFAULT_DUPLICATE_DELAY);
-                                continue;
-                            }
-                            return Err(e);
-                        }
-                    }
-                }
-            }
+            self.socket.send(packet)?;
         }
 
         Ok(())
     }
+}
 
-    fn check_response(&self) -> Result<(), Box<dyn Error>> {
-        let pkt = self.socket.recv()?;
-        if let Packet::Ack(received_block_number) = pkt {
-            if received_block_number == 0 {
-                return Ok(());
-            }
+/// Logs and reports the outcome of sending a file, and returns the
+/// [`TransferReport`] of the transfer.
+pub(crate) fn finish_send(
+    result: Result<u64, Error>,
+    tracker: &mut TransferTracker,
+) -> TransferReport {
+    let TransferInfo {
+        file_path,
+        peer: remote_addr,
+        ..
+    } = tracker.info();
+
+    match result {
+        Ok(size) => {
+            log_info!(
+                "Sent {} to {}",
+                &file_path.file_name().unwrap().to_string_lossy(),
+                remote_addr
+            );
+            tracker.completed(size)
+        }
+        Err(err) => {
+            log_err!(
+                "Error \"{err}\", while sending {} to {}",
+                &file_path.file_name().unwrap().to_string_lossy(),
+                remote_addr
+            );
+            tracker.failed(&err)
         }
-
-        self.socket.send(&Packet::Error {
-            code: ErrorCode::IllegalOperation,
-            msg: "invalid oack response".to_string(),
-        })?;
-
-        Err(format!("Unexpected packet received instead of Ack(0): {pkt:#?}").into())
     }
+}
 
-    fn check_abort(&self) -> Result<(), Box<dyn Error>> {
-        if self.abort.load(std::sync::atomic::Ordering::Relaxed) {
-            self.socket.send(&Packet::Error {
-                code: ErrorCode::NotDefined,
-                msg: "Transfert aborted by user".to_string(),
-            })?;
+/// Commits or discards the received `file` depending on the outcome of the
+/// transfer, logs and reports it, and returns the [`TransferReport`] of the
+/// transfer.
+pub(crate) fn finish_receive(
+    result: Result<u64, Error>,
+    file: Box<dyn FileSink>,
+    tracker: &mut TransferTracker,
+    clean_on_error: bool,
+) -> TransferReport {
+    let TransferInfo {
+        file_path,
+        peer: remote_addr,
+        ..
+    } = tracker.info();
+
+    match result {
+        Ok(size) => {
+            if let Err(err) = file.commit() {
+                log_err!(
+                    "Error \"{err}\", while saving {}",
+                    &file_path.file_name().unwrap().to_string_lossy()
+                );
+                return tracker.failed(&err);
+            }
 
-            Err("Transfert aborted by user".into())
-        } else {
-            Ok(())
+            log_info!(
+                "Received {} ({} bytes) from {}",
+                &file_path.file_name().unwrap().to_string_lossy(),
+                size,
+                remote_addr
+            );
+            tracker.completed(size)
+        }
+        Err(err) => {
+            log_err!(
+                "Error \"{err}\", while receiving {} from {}",
+                &file_path.file_name().unwrap().to_string_lossy(),
+                remote_addr
+            );
+            if clean_on_error && file.discard().is_err() {
+                log_err!("Error while cleaning {}", &file_path.to_str().unwrap());
+            }
+            tracker.failed(&err)
         }
     }
 }
@@ -2,6 +2,7 @@
 
 use std::fs::{self, create_dir_all, remove_dir_all};
 use std::io::Read;
+use std::path::Path;
 use std::process::{Child, Command, ExitStatus, Stdio};
 use std::thread;
 use std::time::{Duration, Instant};
@@ -668,6 +669,12 @@ fn check_files(filename: &str) {
     let server_file = format!("{SERVER_DIR}/{filename}");
     let client_file = format!("{CLIENT_DIR}/{filename}");
 
+    // The server commits a received file after acknowledging its last block
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !Path::new(&server_file).exists() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+    }
+
     let server_content = fs::read(server_file).expect("error reading server file");
     let client_content = fs::read(client_file).expect("error reading client file");
 